@@ -0,0 +1,268 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+use eyre::{bail, ensure, eyre, WrapErr};
+use inputshare_client_core::sender::{detents_to_scroll, InputSender};
+use inputshare_common::{HidButtonCode, HidKeyCode, MouseType};
+
+use crate::keys::{char_to_key, parse_button, parse_key};
+
+pub const SYNTAX: &str = "Commands:
+  key <key>[+<key>...]      presses the keys in order and releases them in reverse, e.g. key ctrl+alt+delete
+  press <key>               holds a key down
+  release <key>             releases a held key
+  text <text>               types the rest of the line using a US keyboard layout
+  move <x> <y>              moves the mouse by exactly x / y pixels
+  scroll <y> [<x>]          scrolls by the given number of detents, positive values scroll up / right
+  click [<button>]          clicks left, right, middle, back or forward (default: left)
+  down [<button>]           holds a mouse button down
+  up [<button>]             releases a held mouse button
+  sleep <milliseconds>      waits, held inputs stay held
+  reset                     releases everything
+
+Keys are given as a single character, a name like ctrl, shift, alt, win, esc or del, or by the name of the HID usage, e.g. F5 or KpEnter.";
+
+/// A single command for the remote device
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Pressed in order, then released in reverse order
+    Keys(Vec<HidKeyCode>),
+    Press(HidKeyCode),
+    Release(HidKeyCode),
+    Text(String),
+    Move(MouseType, MouseType),
+    /// Amounts in detents
+    Scroll {
+        vertical: f32,
+        horizontal: f32
+    },
+    Click(HidButtonCode),
+    Down(HidButtonCode),
+    Up(HidButtonCode),
+    Sleep(Duration),
+    Reset
+}
+
+impl Command {
+    /// Queues the inputs of the command, `Sleep` has to be handled by the caller
+    pub fn apply(&self, sender: &mut InputSender) {
+        match self {
+            Command::Keys(keys) => {
+                keys.iter().for_each(|key| sender.press_key(*key));
+                keys.iter().rev().for_each(|key| sender.release_key(*key));
+            }
+            Command::Press(key) => sender.press_key(*key),
+            Command::Release(key) => sender.release_key(*key),
+            Command::Text(text) => {
+                for (key, shift) in text.chars().filter_map(char_to_key) {
+                    if shift {
+                        sender.press_key(HidKeyCode::LeftShift);
+                    }
+                    sender.press_key(key);
+                    sender.release_key(key);
+                    if shift {
+                        sender.release_key(HidKeyCode::LeftShift);
+                    }
+                }
+            }
+            Command::Move(x, y) => sender.warp_mouse(*x, *y),
+            Command::Scroll { vertical, horizontal } => {
+                if *vertical != 0.0 {
+                    sender.scroll_vertical(detents_to_scroll(*vertical));
+                }
+                if *horizontal != 0.0 {
+                    sender.scroll_horizontal(detents_to_scroll(*horizontal));
+                }
+            }
+            Command::Click(button) => {
+                sender.press_mouse_button(*button);
+                sender.release_mouse_button(*button);
+            }
+            Command::Down(button) => sender.press_mouse_button(*button),
+            Command::Up(button) => sender.release_mouse_button(*button),
+            Command::Sleep(_) => {}
+            Command::Reset => sender.reset()
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = eyre::Report;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim_start();
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        // the text is the only argument in which whitespace matters
+        if name == "text" {
+            if let Some(c) = rest.chars().find(|c| char_to_key(*c).is_none()) {
+                bail!("Can not type {:?}", c);
+            }
+            return Ok(Command::Text(rest.to_string()));
+        }
+        let args: Vec<&str> = rest.split_whitespace().collect();
+        let expect = |min: usize, max: usize| {
+            ensure!(
+                (min..=max).contains(&args.len()),
+                "{} takes {} arguments, not {}",
+                name,
+                match min == max {
+                    true => min.to_string(),
+                    false => format!("{} to {}", min, max)
+                },
+                args.len()
+            );
+            Ok(())
+        };
+        let key = |name: &str| parse_key(name).ok_or_else(|| eyre!("Unknown key: {}", name));
+        let button = || match args.first() {
+            Some(name) => parse_button(name).ok_or_else(|| eyre!("Unknown mouse button: {}", name)),
+            None => Ok(HidButtonCode::LButton)
+        };
+        let command = match name {
+            "key" => {
+                expect(1, 1)?;
+                Command::Keys(args[0].split('+').map(key).collect::<eyre::Result<_>>()?)
+            }
+            "press" => {
+                expect(1, 1)?;
+                Command::Press(key(args[0])?)
+            }
+            "release" => {
+                expect(1, 1)?;
+                Command::Release(key(args[0])?)
+            }
+            "move" => {
+                expect(2, 2)?;
+                Command::Move(args[0].parse().wrap_err("Invalid x")?, args[1].parse().wrap_err("Invalid y")?)
+            }
+            "scroll" => {
+                expect(1, 2)?;
+                Command::Scroll {
+                    vertical: args[0].parse().wrap_err("Invalid amount")?,
+                    horizontal: args
+                        .get(1)
+                        .map_or(Ok(0.0), |x| x.parse())
+                        .wrap_err("Invalid amount")?
+                }
+            }
+            "click" => {
+                expect(0, 1)?;
+                Command::Click(button()?)
+            }
+            "down" => {
+                expect(0, 1)?;
+                Command::Down(button()?)
+            }
+            "up" => {
+                expect(0, 1)?;
+                Command::Up(button()?)
+            }
+            "sleep" => {
+                expect(1, 1)?;
+                Command::Sleep(Duration::from_millis(args[0].parse().wrap_err("Invalid duration")?))
+            }
+            "reset" => {
+                expect(0, 0)?;
+                Command::Reset
+            }
+            _ => bail!("Unknown command: {:?}", name)
+        };
+        Ok(command)
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::Keys(keys) => {
+                let keys: Vec<String> = keys.iter().map(|key| format!("{:?}", key)).collect();
+                write!(f, "key {}", keys.join("+"))
+            }
+            Command::Press(key) => write!(f, "press {:?}", key),
+            Command::Release(key) => write!(f, "release {:?}", key),
+            Command::Text(text) => write!(f, "text {}", text),
+            Command::Move(x, y) => write!(f, "move {} {}", x, y),
+            Command::Scroll { vertical, horizontal } => write!(f, "scroll {} {}", vertical, horizontal),
+            Command::Click(button) => write!(f, "click {:?}", button),
+            Command::Down(button) => write!(f, "down {:?}", button),
+            Command::Up(button) => write!(f, "up {:?}", button),
+            Command::Sleep(duration) => write!(f, "sleep {}", duration.as_millis()),
+            Command::Reset => write!(f, "reset")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use inputshare_client_core::pointer::PointerProfile;
+
+    use super::*;
+
+    fn parse_err(line: &str) -> String {
+        line.parse::<Command>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn key_combos_are_split_on_plus() {
+        assert_eq!(
+            "key ctrl+alt+delete".parse::<Command>().unwrap(),
+            Command::Keys(vec![HidKeyCode::LeftCtrl, HidKeyCode::LeftAlt, HidKeyCode::Delete])
+        );
+        assert_eq!("  key a".parse::<Command>().unwrap(), Command::Keys(vec![HidKeyCode::KeyA]));
+        assert_eq!("press shift".parse::<Command>().unwrap(), Command::Press(HidKeyCode::LeftShift));
+    }
+
+    #[test]
+    fn text_keeps_its_whitespace() {
+        assert_eq!("text Hi,  you!".parse::<Command>().unwrap(), Command::Text("Hi,  you!".to_string()));
+        assert_eq!(parse_err("text naïve"), "Can not type 'ï'");
+    }
+
+    #[test]
+    fn text_holds_shift_for_shifted_characters() {
+        let mut sender = InputSender::new(&PointerProfile::default());
+        "text a!".parse::<Command>().unwrap().apply(&mut sender);
+
+        let mut expected = InputSender::new(&PointerProfile::default());
+        expected.press_key(HidKeyCode::KeyA);
+        expected.release_key(HidKeyCode::KeyA);
+        expected.press_key(HidKeyCode::LeftShift);
+        expected.press_key(HidKeyCode::Key1);
+        expected.release_key(HidKeyCode::Key1);
+        expected.release_key(HidKeyCode::LeftShift);
+        assert_eq!(sender.write_packet().unwrap(), expected.write_packet().unwrap());
+    }
+
+    #[test]
+    fn optional_arguments_have_defaults() {
+        assert_eq!("click".parse::<Command>().unwrap(), Command::Click(HidButtonCode::LButton));
+        assert_eq!("up right".parse::<Command>().unwrap(), Command::Up(HidButtonCode::RButton));
+        assert_eq!(
+            "scroll -1.5".parse::<Command>().unwrap(),
+            Command::Scroll {
+                vertical: -1.5,
+                horizontal: 0.0
+            }
+        );
+        assert_eq!("move -10 20".parse::<Command>().unwrap(), Command::Move(-10, 20));
+        assert_eq!("sleep 250".parse::<Command>().unwrap(), Command::Sleep(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn argument_counts_are_checked() {
+        assert_eq!(parse_err("key"), "key takes 1 arguments, not 0");
+        assert_eq!(parse_err("key a b"), "key takes 1 arguments, not 2");
+        assert_eq!(parse_err("scroll 1 2 3"), "scroll takes 1 to 2 arguments, not 3");
+        assert_eq!(parse_err("click left right"), "click takes 0 to 1 arguments, not 2");
+        assert_eq!(parse_err("reset now"), "reset takes 0 arguments, not 1");
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        assert_eq!(parse_err("key ctrl+nope"), "Unknown key: nope");
+        assert_eq!(parse_err("down sideways"), "Unknown mouse button: sideways");
+        assert_eq!(parse_err("jump"), "Unknown command: \"jump\"");
+        assert_eq!(parse_err("move 1 x"), "Invalid y");
+    }
+}
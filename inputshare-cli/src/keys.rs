@@ -0,0 +1,151 @@
+use inputshare_common::{HidButtonCode, HidKeyCode};
+
+/// Names that are easier to remember than the ones of `HidKeyCode`
+const KEY_ALIASES: &[(&str, HidKeyCode)] = &[
+    ("ctrl", HidKeyCode::LeftCtrl),
+    ("control", HidKeyCode::LeftCtrl),
+    ("shift", HidKeyCode::LeftShift),
+    ("alt", HidKeyCode::LeftAlt),
+    ("altgr", HidKeyCode::RightAlt),
+    ("win", HidKeyCode::LeftMeta),
+    ("meta", HidKeyCode::LeftMeta),
+    ("super", HidKeyCode::LeftMeta),
+    ("cmd", HidKeyCode::LeftMeta),
+    ("esc", HidKeyCode::Escape),
+    ("del", HidKeyCode::Delete),
+    ("ins", HidKeyCode::Insert),
+    ("pgup", HidKeyCode::PageUp),
+    ("pgdn", HidKeyCode::PageDown),
+    ("caps", HidKeyCode::Capslock),
+    ("apps", HidKeyCode::Compose)
+];
+
+const BUTTON_ALIASES: &[(&str, HidButtonCode)] = &[
+    ("left", HidButtonCode::LButton),
+    ("right", HidButtonCode::RButton),
+    ("middle", HidButtonCode::MButton),
+    ("back", HidButtonCode::Button4),
+    ("forward", HidButtonCode::Button5)
+];
+
+/// Parses an alias, a single character or the name of a `HidKeyCode`, ignoring the case
+pub fn parse_key(name: &str) -> Option<HidKeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return char_to_key(c).map(|(key, _)| key);
+    }
+    KEY_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+        .map(|(_, key)| *key)
+        .or_else(|| by_name(name))
+}
+
+/// Parses an alias or the name of a `HidButtonCode`, ignoring the case
+pub fn parse_button(name: &str) -> Option<HidButtonCode> {
+    BUTTON_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+        .map(|(_, button)| *button)
+        .or_else(|| by_name(name))
+}
+
+fn by_name<T: From<u8> + PartialEq + std::fmt::Debug>(name: &str) -> Option<T> {
+    (1..=u8::MAX)
+        .map(T::from)
+        .filter(|code| *code != T::from(0))
+        .find(|code| format!("{:?}", code).eq_ignore_ascii_case(name))
+}
+
+/// The key that types `c` on a US keyboard and whether shift has to be held for it
+pub fn char_to_key(c: char) -> Option<(HidKeyCode, bool)> {
+    const LETTERS: u8 = HidKeyCode::KeyA as u8;
+    const DIGITS: u8 = HidKeyCode::Key1 as u8;
+    let key = match c {
+        'a'..='z' => (HidKeyCode::from(LETTERS + (c as u8 - b'a')), false),
+        'A'..='Z' => (HidKeyCode::from(LETTERS + (c as u8 - b'A')), true),
+        '0' => (HidKeyCode::Key0, false),
+        '1'..='9' => (HidKeyCode::from(DIGITS + (c as u8 - b'1')), false),
+        ' ' => (HidKeyCode::Space, false),
+        '\n' => (HidKeyCode::Enter, false),
+        '\t' => (HidKeyCode::Tab, false),
+        '-' => (HidKeyCode::Minus, false),
+        '_' => (HidKeyCode::Minus, true),
+        '=' => (HidKeyCode::Equal, false),
+        '+' => (HidKeyCode::Equal, true),
+        '[' => (HidKeyCode::LeftBrace, false),
+        '{' => (HidKeyCode::LeftBrace, true),
+        ']' => (HidKeyCode::RightBrace, false),
+        '}' => (HidKeyCode::RightBrace, true),
+        '\\' => (HidKeyCode::Backslash, false),
+        '|' => (HidKeyCode::Backslash, true),
+        ';' => (HidKeyCode::Semicolon, false),
+        ':' => (HidKeyCode::Semicolon, true),
+        '\'' => (HidKeyCode::Apostrophe, false),
+        '"' => (HidKeyCode::Apostrophe, true),
+        '`' => (HidKeyCode::Grave, false),
+        '~' => (HidKeyCode::Grave, true),
+        ',' => (HidKeyCode::Comma, false),
+        '<' => (HidKeyCode::Comma, true),
+        '.' => (HidKeyCode::Dot, false),
+        '>' => (HidKeyCode::Dot, true),
+        '/' => (HidKeyCode::Slash, false),
+        '?' => (HidKeyCode::Slash, true),
+        '!' => (HidKeyCode::Key1, true),
+        '@' => (HidKeyCode::Key2, true),
+        '#' => (HidKeyCode::Key3, true),
+        '$' => (HidKeyCode::Key4, true),
+        '%' => (HidKeyCode::Key5, true),
+        '^' => (HidKeyCode::Key6, true),
+        '&' => (HidKeyCode::Key7, true),
+        '*' => (HidKeyCode::Key8, true),
+        '(' => (HidKeyCode::Key9, true),
+        ')' => (HidKeyCode::Key0, true),
+        _ => return None
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_parsed_by_alias_character_and_name() {
+        assert_eq!(parse_key("ctrl"), Some(HidKeyCode::LeftCtrl));
+        assert_eq!(parse_key("Win"), Some(HidKeyCode::LeftMeta));
+        assert_eq!(parse_key("a"), Some(HidKeyCode::KeyA));
+        assert_eq!(parse_key("A"), Some(HidKeyCode::KeyA));
+        assert_eq!(parse_key("?"), Some(HidKeyCode::Slash));
+        assert_eq!(parse_key("f5"), Some(HidKeyCode::F5));
+        assert_eq!(parse_key("KpEnter"), Some(HidKeyCode::KpEnter));
+        assert_eq!(parse_key("delete"), Some(HidKeyCode::Delete));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert_eq!(parse_key(""), None);
+        assert_eq!(parse_key("ä"), None);
+        assert_eq!(parse_key("control-alt"), None);
+        assert_eq!(parse_button("sideways"), None);
+    }
+
+    #[test]
+    fn buttons_are_parsed_by_alias_and_name() {
+        assert_eq!(parse_button("left"), Some(HidButtonCode::LButton));
+        assert_eq!(parse_button("Forward"), Some(HidButtonCode::Button5));
+        assert_eq!(parse_button("mbutton"), Some(HidButtonCode::MButton));
+    }
+
+    #[test]
+    fn shifted_characters_need_shift() {
+        assert_eq!(char_to_key('z'), Some((HidKeyCode::KeyZ, false)));
+        assert_eq!(char_to_key('Z'), Some((HidKeyCode::KeyZ, true)));
+        assert_eq!(char_to_key('0'), Some((HidKeyCode::Key0, false)));
+        assert_eq!(char_to_key('9'), Some((HidKeyCode::Key9, false)));
+        assert_eq!(char_to_key(')'), Some((HidKeyCode::Key0, true)));
+        assert_eq!(char_to_key('"'), Some((HidKeyCode::Apostrophe, true)));
+        assert_eq!(char_to_key('\n'), Some((HidKeyCode::Enter, false)));
+        assert_eq!(char_to_key('é'), None);
+    }
+}
@@ -0,0 +1,185 @@
+mod command;
+mod keys;
+
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::Parser;
+use eyre::{eyre, Report};
+use inputshare_client_core::{server_fingerprint, CertificateCheck, CertificateMismatch, Fingerprint, Session, SessionOptions};
+use tokio::io::{stdin, AsyncBufReadExt, BufReader};
+use tokio::time::Instant;
+use tracing_subscriber::filter::LevelFilter;
+
+use crate::command::{Command, SYNTAX};
+
+const EXIT_CODES: &str = "Exit codes:
+  0  every command was acknowledged
+  1  an unexpected error
+  2  invalid arguments
+  3  an invalid command
+  4  could not connect to the server
+  5  the certificate of the server does not match --pin
+  6  a command was not acknowledged in time or the connection was lost";
+
+/// Sends input to an InputShare server without a user interface
+#[derive(Parser, Debug)]
+#[command(about, version, author, after_help = format!("{}\n\n{}", SYNTAX, EXIT_CODES))]
+struct Args {
+    /// The address of the server, e.g. raspberrypi.local:60067
+    host: String,
+
+    /// The commands that are sent in order, read line by line from stdin if none are given
+    commands: Vec<String>,
+
+    /// Only connect if the SHA-256 fingerprint of the server certificate matches
+    /// The server prints its fingerprint on startup, it only stays the same when the server uses --certificate
+    #[arg(long)]
+    pin: Option<Fingerprint>,
+
+    /// The number of packets per second that are sent until a command is acknowledged
+    #[arg(long, default_value_t = 100)]
+    rate: u32,
+
+    /// How many milliseconds the server has to acknowledge a command
+    #[arg(long, default_value_t = 2000)]
+    timeout: u64,
+
+    /// Log what is going on to stderr
+    #[arg(short, long)]
+    verbose: bool
+}
+
+/// Why the cli stopped early, the discriminants are the exit codes
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Failure {
+    Other = 1,
+    Command = 3,
+    Connect = 4,
+    Certificate = 5,
+    Unacknowledged = 6
+}
+
+type Result<T> = std::result::Result<T, (Failure, Report)>;
+
+trait FailWith<T> {
+    fn fail_with(self, failure: Failure) -> Result<T>;
+}
+
+impl<T, E: Into<Report>> FailWith<T> for std::result::Result<T, E> {
+    fn fail_with(self, failure: Failure) -> Result<T> {
+        self.map_err(|err| (failure, err.into()))
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    tracing_subscriber::fmt()
+        .with_max_level(match args.verbose {
+            true => LevelFilter::DEBUG,
+            false => LevelFilter::WARN
+        })
+        .with_writer(std::io::stderr)
+        .without_time()
+        .init();
+
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err((failure, err)) => {
+            eprintln!("Error: {:#}", err);
+            ExitCode::from(failure as u8)
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<()> {
+    // a typo should not leave the remote device with half of the commands executed
+    let commands = args
+        .commands
+        .iter()
+        .map(|line| line.parse())
+        .collect::<eyre::Result<Vec<Command>>>()
+        .fail_with(Failure::Command)?;
+
+    let options = SessionOptions {
+        certificate: args
+            .pin
+            .map_or(CertificateCheck::Skip, CertificateCheck::Pinned),
+        send_rate: args.rate,
+        ..Default::default()
+    };
+    let session = match Session::connect(&args.host, options).await {
+        Ok(session) => session,
+        Err(err) if err.is::<CertificateMismatch>() => return Err((Failure::Certificate, err)),
+        Err(err) => return Err((Failure::Connect, err))
+    };
+    let connection = session.connection();
+    match server_fingerprint(connection) {
+        Some(fingerprint) => println!("connected {} {}", connection.remote_address(), fingerprint),
+        None => println!("connected {}", connection.remote_address())
+    }
+    // inputs that are held by a command have to stay held until the next one
+    session.set_keepalive(true);
+
+    let timeout = Duration::from_millis(args.timeout);
+    let result = match commands.is_empty() {
+        true => {
+            let mut lines = BufReader::new(stdin()).lines();
+            let mut result = Ok(());
+            while let Some(line) = lines.next_line().await.fail_with(Failure::Other)? {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                result = match line.parse() {
+                    Ok(command) => execute(&session, &command, timeout).await,
+                    Err(err) => Err((Failure::Command, err))
+                };
+                if result.is_err() {
+                    break;
+                }
+            }
+            result
+        }
+        false => {
+            let mut result = Ok(());
+            for command in &commands {
+                result = execute(&session, command, timeout).await;
+                if result.is_err() {
+                    break;
+                }
+            }
+            result
+        }
+    };
+
+    match (result, session.close().await) {
+        // the lost connection explains why the command was not acknowledged
+        (Ok(()) | Err((Failure::Unacknowledged, _)), Err(err)) => Err((Failure::Unacknowledged, err)),
+        (result, _) => result
+    }
+}
+
+/// Sends the command and prints its acknowledgement
+async fn execute(session: &Session, command: &Command, timeout: Duration) -> Result<()> {
+    tracing::debug!("Executing {}", command);
+    if let Command::Sleep(duration) = command {
+        tokio::time::sleep(*duration).await;
+        return Ok(());
+    }
+    let start = Instant::now();
+    session.update(|sender| command.apply(sender));
+    tokio::time::timeout(timeout, session.flush())
+        .await
+        .map_err(|_| eyre!("The server did not acknowledge the input within {:?}", timeout))
+        .and_then(|result| result)
+        .fail_with(Failure::Unacknowledged)?;
+    println!(
+        "ack {:.1}ms rtt {:.1}ms: {}",
+        start.elapsed().as_secs_f64() * 1000.0,
+        session.connection().stats().path.rtt.as_secs_f64() * 1000.0,
+        command
+    );
+    Ok(())
+}
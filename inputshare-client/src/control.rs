@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use druid::ExtEventSink;
+use eyre::eyre;
+use inputshare_client_core::request;
+use inputshare_common::{ActionOutput, ControlRequest, ControlResponse, PowerAction, PresenceSettings};
+use quinn::Connection;
+use tracing::instrument;
+
+use crate::model::{AppState, PopupType, RemoteAction};
+use crate::runtime::ExtEventSinkCallback;
+use crate::ui::actions::open_popup;
+use crate::utils::error::strip_color;
+
+/// How long the client waits for the server to describe itself after connecting
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Asks the server which power actions it supports and how its presence keeper is set up
+///
+/// Servers without a control channel never answer, so they end up without any power actions
+#[instrument(skip(connection, sink))]
+pub async fn handshake(connection: Connection, sink: ExtEventSink) {
+    let (power_actions, presence) = match tokio::time::timeout(HANDSHAKE_TIMEOUT, request(&connection, &ControlRequest::Hello)).await {
+        Ok(Ok(ControlResponse::Hello { power_actions, presence })) => (power_actions, Some(presence)),
+        Ok(Ok(response)) => {
+            tracing::warn!("Unexpected response to the handshake: {:?}", response);
+            (Vec::new(), None)
+        }
+        Ok(Err(err)) => {
+            tracing::warn!("Handshake failed: {:?}", err);
+            (Vec::new(), None)
+        }
+        Err(_) => {
+            tracing::warn!("The server did not answer the handshake");
+            (Vec::new(), None)
+        }
+    };
+    tracing::debug!("Enabled power actions: {:?}, presence keeper: {:?}", power_actions, presence);
+    sink.add_idle_callback(move |data: &mut AppState| {
+        data.power_actions = power_actions;
+        data.presence = presence;
+    });
+}
+
+#[instrument(skip(connection, sink))]
+pub async fn request_power_action(connection: Connection, sink: ExtEventSink, action: PowerAction) {
+    let result = match request(&connection, &ControlRequest::Power(action)).await {
+        Ok(ControlResponse::Done) => Ok(()),
+        Ok(ControlResponse::Failed(reason)) => Err(eyre!(reason)),
+        Ok(response) => Err(eyre!("Unexpected response: {:?}", response)),
+        Err(err) => Err(err)
+    };
+    if let Err(err) = result {
+        let popup = error_popup(err.wrap_err(format!("The server could not perform {:?}", action)));
+        sink.add_rt_callback(move |rt, data| open_popup(rt, data, popup));
+    }
+}
+
+#[instrument(skip(connection, sink))]
+pub async fn set_presence(connection: Connection, sink: ExtEventSink, settings: PresenceSettings) {
+    match request(&connection, &ControlRequest::SetPresence(settings)).await {
+        Ok(ControlResponse::Done) => sink.add_idle_callback(move |data: &mut AppState| data.presence = Some(settings)),
+        Ok(response) => {
+            let popup = error_popup(eyre!("Unexpected response: {:?}", response));
+            sink.add_rt_callback(move |rt, data| open_popup(rt, data, popup));
+        }
+        Err(err) => {
+            let popup = error_popup(err.wrap_err("Could not change the presence keeper"));
+            sink.add_rt_callback(move |rt, data| open_popup(rt, data, popup));
+        }
+    }
+}
+
+#[instrument(skip(connection, sink))]
+pub async fn list_actions(connection: Connection, sink: ExtEventSink) {
+    let popup = match request(&connection, &ControlRequest::ListActions).await {
+        Ok(ControlResponse::Actions(actions)) => PopupType::Actions(
+            actions
+                .into_iter()
+                .map(|action| RemoteAction {
+                    name: action.name,
+                    description: action.description,
+                    takes_arguments: action.takes_arguments,
+                    arguments: String::new()
+                })
+                .collect()
+        ),
+        Ok(response) => error_popup(eyre!("Unexpected response: {:?}", response)),
+        Err(err) => error_popup(err.wrap_err("Could not list the remote actions"))
+    };
+    sink.add_rt_callback(move |rt, data| open_popup(rt, data, popup));
+}
+
+#[instrument(skip(connection, sink))]
+pub async fn run_action(connection: Connection, sink: ExtEventSink, name: String, arguments: Vec<String>) {
+    let request = ControlRequest::RunAction {
+        name: name.clone(),
+        arguments
+    };
+    let popup = match inputshare_client_core::request(&connection, &request).await {
+        Ok(ControlResponse::ActionFinished(output)) => PopupType::ActionOutput(describe_output(&name, &output)),
+        Ok(ControlResponse::Failed(reason)) => error_popup(eyre!(reason).wrap_err(format!("Could not run {:?}", name))),
+        Ok(response) => error_popup(eyre!("Unexpected response: {:?}", response)),
+        Err(err) => error_popup(err.wrap_err(format!("Could not run {:?}", name)))
+    };
+    sink.add_rt_callback(move |rt, data| open_popup(rt, data, popup));
+}
+
+fn error_popup(err: eyre::Report) -> PopupType {
+    tracing::warn!("{:?}", err);
+    PopupType::Error(strip_color(&format!("{:?}", err)))
+}
+
+fn describe_output(name: &str, output: &ActionOutput) -> String {
+    let status = match output.exit_code {
+        Some(code) => format!("{} exited with code {}", name, code),
+        None => format!("{} was terminated", name)
+    };
+    format!("{}\n\nstdout:\n{}\n\nstderr:\n{}", status, output.stdout, output.stderr)
+}
@@ -0,0 +1,180 @@
+use inputshare_client_core::sender::InputSender;
+use inputshare_common::edge::{Edge, Rect, RemotePointer};
+use inputshare_common::{MouseType, Vec2};
+use serde::{Deserialize, Serialize};
+
+/// Where the remote screen is located relative to the local desktop
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScreenLayout {
+    /// The edge of the local desktop that leads to the remote screen
+    pub edge: Edge,
+    /// The resolution of the remote screen in pixels
+    pub remote_size: (MouseType, MouseType),
+    /// The edge of the remote screen that leads back, the opposite of `edge` if omitted
+    #[serde(default)]
+    pub return_edge: Option<Edge>
+}
+
+impl ScreenLayout {
+    fn return_edge(&self) -> Edge {
+        self.return_edge.unwrap_or(self.edge.opposite())
+    }
+
+    /// Tracks the remote pointer, which leaves the remote screen through the return edge
+    fn pointer(&self) -> RemotePointer {
+        RemotePointer::new(Rect::screen(self.remote_size), self.return_edge())
+    }
+
+    /// Returns where along the edge the local pointer left the desktop if it was pushed past the edge
+    pub fn local_crossing(&self, local: Rect, point: Vec2<MouseType>) -> Option<f64> {
+        (local.overshoot(self.edge, point) > 0).then(|| local.along(self.edge, point))
+    }
+
+    /// The point at which the local pointer reappears after it left the remote screen
+    pub fn local_entry(&self, local: Rect, along: f64) -> Vec2<MouseType> {
+        local.point_on(self.edge, along)
+    }
+}
+
+/// Connects a `RemotePointer` to the movements of an `InputSender`
+///
+/// A server with a return edge of its own also reports when its pointer left the screen, which is handled the same way.
+#[derive(Debug)]
+pub struct EdgeSwitch {
+    layout: ScreenLayout,
+    pointer: RemotePointer,
+    /// The pointer position of the sender after the last tracked movement
+    last: Vec2<MouseType>,
+    /// The entry point that gets approached once the remote pointer reached the corner
+    entry: Option<Vec2<MouseType>>
+}
+
+impl EdgeSwitch {
+    pub fn new(layout: ScreenLayout) -> Self {
+        Self {
+            pointer: layout.pointer(),
+            layout,
+            last: Vec2::new(0, 0),
+            entry: None
+        }
+    }
+
+    pub fn layout(&self) -> &ScreenLayout {
+        &self.layout
+    }
+
+    /// Moves the remote pointer to the point that matches where the local pointer left the desktop
+    pub fn enter(&mut self, sender: &mut InputSender, along: f64) {
+        // the remote pointer could be anywhere, so it is pushed into the top left corner first
+        let (width, height) = self.layout.remote_size;
+        sender.warp_mouse(-2 * width, -2 * height);
+        self.entry = Some(self.pointer.enter(along));
+        self.last = sender.mouse_position();
+        sender.take_edge_return();
+    }
+
+    /// Continues from the last estimate, for when the input got captured without crossing the edge
+    pub fn resume(&mut self, sender: &mut InputSender) {
+        self.entry = None;
+        self.last = sender.mouse_position();
+        sender.take_edge_return();
+    }
+
+    /// Returns where along the return edge the remote pointer left the remote screen
+    pub fn update(&mut self, sender: &mut InputSender) -> Option<f64> {
+        if let Some(along) = sender.take_edge_return() {
+            return Some(along);
+        }
+        if let Some(entry) = self.entry {
+            // the corner has to be reached before the entry point can be approached from it
+            if sender.in_sync() {
+                sender.warp_mouse(entry.x, entry.y);
+                self.entry = None;
+            }
+            self.last = sender.mouse_position();
+            return None;
+        }
+        let position = sender.mouse_position();
+        let (x, y) = (position.x - self.last.x, position.y - self.last.y);
+        self.last = position;
+        self.pointer.moved(x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESKTOP: Rect = Rect {
+        x: -1920,
+        y: 0,
+        width: 3840,
+        height: 1080
+    };
+
+    fn layout(edge: Edge) -> ScreenLayout {
+        ScreenLayout {
+            edge,
+            remote_size: (1280, 720),
+            return_edge: None
+        }
+    }
+
+    #[test]
+    fn crossing_requires_pushing_past_the_edge() {
+        let layout = layout(Edge::Right);
+        assert_eq!(layout.local_crossing(DESKTOP, Vec2::new(1919, 540)), None);
+        assert_eq!(layout.local_crossing(DESKTOP, Vec2::new(-1921, 540)), None);
+        assert_eq!(layout.local_crossing(DESKTOP, Vec2::new(1920, 0)), Some(0.0));
+        assert_eq!(layout.local_crossing(DESKTOP, Vec2::new(1925, 1079)), Some(1.0));
+    }
+
+    #[test]
+    fn crossing_works_for_every_edge() {
+        assert!(layout(Edge::Left)
+            .local_crossing(DESKTOP, Vec2::new(-1921, 10))
+            .is_some());
+        assert!(layout(Edge::Top)
+            .local_crossing(DESKTOP, Vec2::new(0, -1))
+            .is_some());
+        assert!(layout(Edge::Bottom)
+            .local_crossing(DESKTOP, Vec2::new(0, 1080))
+            .is_some());
+        assert!(layout(Edge::Bottom)
+            .local_crossing(DESKTOP, Vec2::new(0, 1079))
+            .is_none());
+    }
+
+    #[test]
+    fn entry_keeps_the_relative_position() {
+        let mut pointer = layout(Edge::Right).pointer();
+        let along = layout(Edge::Right)
+            .local_crossing(DESKTOP, Vec2::new(1920, 1079))
+            .unwrap();
+        assert_eq!(pointer.enter(along), Vec2::new(0, 719));
+        assert_eq!(pointer.enter(0.5), Vec2::new(0, 360));
+        assert_eq!(layout(Edge::Right).local_entry(DESKTOP, 0.5), Vec2::new(1919, 540));
+    }
+
+    #[test]
+    fn other_edges_clamp_the_pointer() {
+        let mut pointer = layout(Edge::Right).pointer();
+        pointer.enter(0.0);
+        // pushing against the far edge must not build up a debt that has to be moved back first
+        assert_eq!(pointer.moved(5000, -100), None);
+        assert_eq!(pointer.moved(-1279, 0), None);
+        assert_eq!(pointer.moved(-1, 0), Some(0.0));
+    }
+
+    #[test]
+    fn return_edge_can_be_overridden() {
+        let mut pointer = ScreenLayout {
+            return_edge: Some(Edge::Bottom),
+            ..layout(Edge::Top)
+        }
+        .pointer();
+        assert_eq!(pointer.enter(1.0), Vec2::new(1279, 719));
+        assert_eq!(pointer.moved(0, -10), None);
+        assert_eq!(pointer.moved(0, 11), Some(1.0));
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use inputshare_client_core::sender::InputSender;
+use inputshare_common::GamepadState;
+
+use crate::utils::conversions::controller_to_gamepad;
+
+pub const GAMEPAD_POLL_INTERVAL: Duration = Duration::from_millis(8);
+
+/// Anything that can provide gamepad snapshots
+pub trait GamepadSource: Send {
+    /// Returns the current state of the gamepad or `None` if no gamepad is connected
+    fn poll(&mut self) -> Option<GamepadState>;
+}
+
+/// Reads the gamepad state from the first XInput controller
+#[derive(Debug, Default)]
+pub struct XInputSource {
+    connected: bool
+}
+
+impl GamepadSource for XInputSource {
+    fn poll(&mut self) -> Option<GamepadState> {
+        let state = yawi::get_controller_state(0);
+        if state.is_some() != self.connected {
+            self.connected = state.is_some();
+            tracing::debug!("Gamepad {}", if self.connected { "connected" } else { "disconnected" });
+        }
+        state.map(controller_to_gamepad)
+    }
+}
+
+/// Forwards the latest gamepad snapshot to the sender
+///
+/// A disconnected gamepad is forwarded as a neutral snapshot so that no button stays pressed on the remote
+pub fn forward_gamepad(source: &mut dyn GamepadSource, sender: &mut InputSender) {
+    sender.update_gamepad(source.poll().unwrap_or_default());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use inputshare_client_core::pointer::PointerProfile;
+    use inputshare_client_core::sender::{layout, InputSender};
+    use inputshare_common::{GamepadButton, GamepadState};
+
+    use super::{forward_gamepad, GamepadSource};
+
+    struct MockSource(VecDeque<Option<GamepadState>>);
+
+    impl GamepadSource for MockSource {
+        fn poll(&mut self) -> Option<GamepadState> {
+            self.0.pop_front().flatten()
+        }
+    }
+
+    #[test]
+    fn disconnect_releases_buttons() {
+        let mut pressed = GamepadState::default();
+        pressed.set_pressed(GamepadButton::South, true);
+        let mut source = MockSource(VecDeque::from([Some(pressed), Some(pressed), None]));
+        let mut sender = InputSender::new(&PointerProfile::default());
+
+        forward_gamepad(&mut source, &mut sender);
+        assert!(!sender.in_sync());
+        let first = sender.write_packet().unwrap().to_vec();
+
+        forward_gamepad(&mut source, &mut sender);
+        assert_eq!(
+            sender.write_packet().unwrap()[layout::SEQUENCE.end..],
+            first[layout::SEQUENCE.end..],
+            "unchanged state must not queue new messages"
+        );
+
+        forward_gamepad(&mut source, &mut sender);
+        let packet = sender.write_packet().unwrap();
+        assert_eq!(
+            packet[layout::MESSAGE_COUNT],
+            2,
+            "the release must be queued behind the unacknowledged press"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use inputshare_client_core::sender::{detents_to_scroll, InputSender};
+use inputshare_common::{HidButtonCode, HidKeyCode};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::model::Hotkey;
+
+/// A sequence of inputs that is sent to the remote device when its hotkey is pressed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Macro {
+    pub hotkey: Hotkey,
+    pub steps: Vec<MacroStep>
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MacroStep {
+    /// Presses the keys in order and releases them in reverse order
+    Keys(Vec<HidKeyCode>),
+    Press(HidKeyCode),
+    Release(HidKeyCode),
+    Click(HidButtonCode),
+    /// Scrolls by the given amount of detents
+    Scroll {
+        horizontal: f32,
+        vertical: f32
+    },
+    /// Waits for the given amount of milliseconds
+    Delay(u64)
+}
+
+/// Feeds the steps of triggered macros into an `InputSender`
+///
+/// Steps are queued like any other input, so they are acknowledged in the order they were played.
+/// The modifiers that are held down on the remote device are released for the duration of a macro and pressed again afterwards.
+#[derive(Debug, Default)]
+pub struct MacroPlayer {
+    pending: VecDeque<Step>,
+    resume: Option<Instant>
+}
+
+#[derive(Debug)]
+enum Step {
+    Play(MacroStep),
+    /// Presses a held modifier again once the macro is done
+    Restore(HidKeyCode)
+}
+
+impl MacroPlayer {
+    /// Queues `steps` after the steps of the macros that are still playing
+    pub fn play(&mut self, steps: &[MacroStep], held: &[HidKeyCode]) {
+        self.pending
+            .extend(held.iter().map(|key| Step::Play(MacroStep::Release(*key))));
+        self.pending.extend(steps.iter().cloned().map(Step::Play));
+        self.pending
+            .extend(held.iter().map(|key| Step::Restore(*key)));
+        if self.resume.is_none() && !self.pending.is_empty() {
+            self.resume = Some(Instant::now());
+        }
+    }
+
+    /// The user let go of `key`, so it must not be pressed again after the macro
+    pub fn released(&mut self, key: HidKeyCode) {
+        self.pending
+            .retain(|step| !matches!(step, Step::Restore(restored) if *restored == key));
+    }
+
+    /// Drops every pending restore, for when the remote device was reset
+    pub fn forget_held(&mut self) {
+        self.pending
+            .retain(|step| !matches!(step, Step::Restore(_)));
+    }
+
+    /// The time at which `advance` should be called next or `None` if nothing is playing
+    pub fn deadline(&self) -> Option<Instant> {
+        self.resume
+    }
+
+    /// Plays the pending steps up to the next delay
+    pub fn advance(&mut self, sender: &mut InputSender) {
+        while let Some(step) = self.pending.pop_front() {
+            let step = match step {
+                Step::Play(step) => step,
+                Step::Restore(key) => {
+                    sender.press_key(key);
+                    continue;
+                }
+            };
+            match step {
+                MacroStep::Keys(keys) => {
+                    for key in keys.iter() {
+                        sender.press_key(*key);
+                    }
+                    for key in keys.iter().rev() {
+                        sender.release_key(*key);
+                    }
+                }
+                MacroStep::Press(key) => sender.press_key(key),
+                MacroStep::Release(key) => sender.release_key(key),
+                MacroStep::Click(button) => {
+                    sender.press_mouse_button(button);
+                    sender.release_mouse_button(button);
+                }
+                MacroStep::Scroll { horizontal, vertical } => {
+                    if horizontal != 0.0 {
+                        sender.scroll_horizontal(detents_to_scroll(horizontal));
+                    }
+                    if vertical != 0.0 {
+                        sender.scroll_vertical(detents_to_scroll(vertical));
+                    }
+                }
+                MacroStep::Delay(millis) => {
+                    self.resume = Some(Instant::now() + Duration::from_millis(millis));
+                    return;
+                }
+            }
+        }
+        self.resume = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use inputshare_client_core::pointer::PointerProfile;
+    use inputshare_client_core::sender::layout;
+
+    use super::*;
+
+    fn new_sender() -> InputSender {
+        InputSender::new(&PointerProfile::default())
+    }
+
+    /// The message count followed by the messages
+    fn messages(sender: &mut InputSender) -> Vec<u8> {
+        sender.write_packet().unwrap()[layout::MESSAGE_COUNT..].to_vec()
+    }
+
+    #[test]
+    fn delays_pause_the_macro() {
+        let mut player = MacroPlayer::default();
+        let mut sender = new_sender();
+        assert_eq!(player.deadline(), None);
+
+        let start = Instant::now();
+        player.play(
+            &[
+                MacroStep::Press(HidKeyCode::KeyA),
+                MacroStep::Delay(50),
+                MacroStep::Release(HidKeyCode::KeyA)
+            ],
+            &[]
+        );
+        assert!(player.deadline().unwrap() <= Instant::now());
+
+        player.advance(&mut sender);
+        let mut expected = new_sender();
+        expected.press_key(HidKeyCode::KeyA);
+        assert_eq!(messages(&mut sender), messages(&mut expected));
+        assert!(player.deadline().unwrap() >= start + Duration::from_millis(50));
+
+        player.advance(&mut sender);
+        expected.release_key(HidKeyCode::KeyA);
+        assert_eq!(messages(&mut sender), messages(&mut expected));
+        assert_eq!(player.deadline(), None);
+    }
+
+    #[test]
+    fn overlapping_macros_are_queued() {
+        let mut player = MacroPlayer::default();
+        let mut sender = new_sender();
+        player.play(&[MacroStep::Keys(vec![HidKeyCode::KeyA]), MacroStep::Delay(50)], &[]);
+        player.advance(&mut sender);
+        // the second macro waits for the delay of the first one
+        player.play(&[MacroStep::Click(HidButtonCode::LButton)], &[]);
+        assert!(player.deadline().unwrap() > Instant::now());
+
+        player.advance(&mut sender);
+        let mut expected = new_sender();
+        expected.press_key(HidKeyCode::KeyA);
+        expected.release_key(HidKeyCode::KeyA);
+        expected.press_mouse_button(HidButtonCode::LButton);
+        expected.release_mouse_button(HidButtonCode::LButton);
+        assert_eq!(messages(&mut sender), messages(&mut expected));
+        assert_eq!(player.deadline(), None);
+    }
+
+    #[test]
+    fn held_modifiers_are_released_and_restored() {
+        let mut player = MacroPlayer::default();
+        let mut sender = new_sender();
+        player.play(
+            &[MacroStep::Keys(vec![HidKeyCode::LeftMeta, HidKeyCode::KeyL])],
+            &[HidKeyCode::LeftCtrl, HidKeyCode::LeftAlt]
+        );
+        player.advance(&mut sender);
+
+        let mut expected = new_sender();
+        expected.release_key(HidKeyCode::LeftCtrl);
+        expected.release_key(HidKeyCode::LeftAlt);
+        expected.press_key(HidKeyCode::LeftMeta);
+        expected.press_key(HidKeyCode::KeyL);
+        expected.release_key(HidKeyCode::KeyL);
+        expected.release_key(HidKeyCode::LeftMeta);
+        expected.press_key(HidKeyCode::LeftCtrl);
+        expected.press_key(HidKeyCode::LeftAlt);
+        assert_eq!(messages(&mut sender), messages(&mut expected));
+    }
+
+    #[test]
+    fn released_modifiers_are_not_restored() {
+        let mut player = MacroPlayer::default();
+        let mut sender = new_sender();
+        player.play(&[MacroStep::Delay(50)], &[HidKeyCode::LeftCtrl, HidKeyCode::LeftAlt]);
+        player.advance(&mut sender);
+        player.released(HidKeyCode::LeftCtrl);
+        player.advance(&mut sender);
+
+        let mut expected = new_sender();
+        expected.release_key(HidKeyCode::LeftCtrl);
+        expected.release_key(HidKeyCode::LeftAlt);
+        expected.press_key(HidKeyCode::LeftAlt);
+        assert_eq!(messages(&mut sender), messages(&mut expected));
+
+        player.play(&[], &[HidKeyCode::LeftShift]);
+        player.forget_held();
+        player.advance(&mut sender);
+        expected.release_key(HidKeyCode::LeftShift);
+        assert_eq!(messages(&mut sender), messages(&mut expected));
+    }
+}
@@ -1,22 +1,25 @@
 #![windows_subsystem = "windows"]
 
+mod control;
+mod edge;
+mod gamepad;
+mod macros;
 mod model;
 mod runtime;
-mod sender;
 mod ui;
 mod utils;
 
-use std::collections::VecDeque;
-use std::net::ToSocketAddrs;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
 
-use bytes::Bytes;
 use druid::{AppLauncher, ExtEventSink, WindowDesc};
-use eyre::{eyre, WrapErr};
-use quinn::{ClientConfig, Connection, Endpoint, TransportConfig};
+use eyre::eyre;
+use inputshare_client_core::sender::InputSender;
+use inputshare_client_core::{Session, SessionOptions, Stats};
+use inputshare_common::DeviceStatus;
 use tokio::sync::mpsc::UnboundedReceiver;
-use tokio::time::Instant;
+use tokio::sync::watch;
+use tokio::time::{interval, MissedTickBehavior};
 use tokio::{select, spawn};
 use tracing::instrument;
 use tracing_error::ErrorLayer;
@@ -26,12 +29,17 @@ use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use yawi::InputHook;
 
-use crate::model::{AppState, ConnectionCommand, NetworkInfo};
+use crate::control::{handshake, list_actions, request_power_action, run_action, set_presence};
+use crate::edge::EdgeSwitch;
+use crate::gamepad::{forward_gamepad, GamepadSource, XInputSource, GAMEPAD_POLL_INTERVAL};
+use crate::macros::MacroPlayer;
+use crate::model::{AppState, Config, ConnectionCommand, NetworkInfo};
 use crate::runtime::{ExtEventSinkCallback, RuntimeDelegate};
-use crate::sender::InputSender;
 use crate::ui::widget::{theme, Theme};
 use crate::utils::error::set_eyre_hook;
-use crate::utils::{hook, process_hook_event, SkipServerVerification};
+use crate::utils::hook::HookEvent;
+use crate::utils::hook::local_desktop;
+use crate::utils::{hook, process_hook_event, set_captured};
 
 #[instrument]
 pub fn main() {
@@ -64,15 +72,21 @@ pub fn main() {
         .expect("launch failed");
 }
 
-#[instrument(skip(sink, controller))]
-async fn connection(
-    sink: &ExtEventSink, mut controller: UnboundedReceiver<ConnectionCommand>, host: &str, info: bool, rate: f32
-) -> eyre::Result<()> {
+#[instrument(skip_all, fields(host = %config.host_address))]
+async fn connection(sink: &ExtEventSink, mut controller: UnboundedReceiver<ConnectionCommand>, config: Config) -> eyre::Result<()> {
+    let host = config.host_address.as_str();
+    let mut pointer = config.pointer_profile(host).clone();
     let wait = async {
         loop {
             match controller.recv().await {
                 None => return Err(eyre!("control channel closed")),
-                Some(ConnectionCommand::ShutdownServer) => tracing::warn!("Can not send a shutdown signal until connected"),
+                Some(ConnectionCommand::Power(_)) => tracing::warn!("Can not request a power action until connected"),
+                Some(ConnectionCommand::SystemControl(_)) => tracing::warn!("Can not send a system control signal until connected"),
+                Some(ConnectionCommand::ListActions | ConnectionCommand::RunAction { .. }) => tracing::warn!("Can not run remote actions until connected"),
+                Some(ConnectionCommand::SetPresence(_)) => tracing::warn!("Can not change the presence keeper until connected"),
+                Some(ConnectionCommand::CycleMouseSpeed | ConnectionCommand::ResetRemote | ConnectionCommand::ToggleNetworkInfo) => {
+                    tracing::warn!("Can not run hotkey commands until connected")
+                }
                 Some(ConnectionCommand::Disconnect) => {
                     tracing::debug!("Canceling connection");
                     return Ok(());
@@ -80,124 +94,181 @@ async fn connection(
             }
         }
     };
-    let connection: Connection = select! {
-        conn = connect(host) => conn?,
+    let options = SessionOptions {
+        pointer: pointer.clone(),
+        send_rate: config.network_send_rate,
+        ..Default::default()
+    };
+    let session = select! {
+        session = Session::connect(host, options) => session?,
         res = wait => return res
     };
-    tracing::debug!("Connected to {}", connection.remote_address());
+    let connection = session.connection().clone();
 
-    if info {
-        spawn(collect_network_info(connection.clone(), sink.clone()));
-    }
+    let mut network_info = match config.show_network_info {
+        true => Some(spawn(collect_network_info(session.stats(), sink.clone()))),
+        false => None
+    };
+    spawn(handshake(connection.clone(), sink.clone()));
 
     let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let capture = Arc::new(AtomicBool::new(false));
+    let hook_capture = capture.clone();
     sink.add_rt_callback(|rt, data| {
         tracing::info_span!("register input hook").in_scope(move || {
             if rt.hook.is_some() {
                 tracing::warn!("Hook already exists");
             }
-            rt.hook = InputHook::register(hook::create_callback(&data.config, sender))
+            let commands = match rt.connection.clone() {
+                Some(commands) => commands,
+                None => {
+                    tracing::warn!("Connection closed before the hook was registered");
+                    return;
+                }
+            };
+            rt.hook = InputHook::register(hook::create_callback(&data.config, sender, commands, hook_capture))
                 .map_err(|err| tracing::warn!("Failed to register hook: {}", err))
                 .ok();
             data.enable_shutdown = true;
         })
     });
 
-    let mut sender = InputSender::new(1.0);
-    let mut deadline = None;
+    let mut state = session.state();
+    let mut player = MacroPlayer::default();
+    let mut edges = config.screen_layout.clone().map(EdgeSwitch::new);
+    let mut captured = false;
+    let mut remote_status = DeviceStatus::empty();
+    let mut gamepad: Option<Box<dyn GamepadSource>> = match config.forward_gamepad {
+        true => Some(Box::<XInputSource>::default()),
+        false => None
+    };
+    let mut gamepad_timer = interval(GAMEPAD_POLL_INTERVAL);
+    gamepad_timer.set_missed_tick_behavior(MissedTickBehavior::Skip);
     loop {
-        let timeout = async move {
-            match deadline {
+        let macro_deadline = player.deadline();
+        let macro_step = async move {
+            match macro_deadline {
                 Some(deadline) => tokio::time::sleep_until(deadline).await,
                 None => std::future::pending().await
             };
         };
         select! {
-            datagram = connection.read_datagram() => {
-                let datagram: Bytes = datagram?;
-                sender.read_packet(&datagram)?;
+            _ = session.closed() => break,
+            Ok(()) = state.changed() => {
+                let status = state.borrow_and_update().remote_status;
+                if status != remote_status {
+                    remote_status = status;
+                    let warning = describe_status(remote_status);
+                    sink.add_idle_callback(move |data: &mut AppState| data.remote_warning = warning);
+                }
             },
             event = receiver.recv() => match event {
-                Some(event) => process_hook_event(&mut sender, sink, event),
+                Some(event) => {
+                    match event {
+                        HookEvent::Captured(c) => captured = c,
+                        HookEvent::EdgeCrossed(_) => captured = true,
+                        _ => {}
+                    }
+                    session.update(|sender| process_hook_event(sender, &mut player, edges.as_mut(), sink, event))
+                },
                 None => return Err(eyre!("Input hook got removed"))
             },
             cmd = controller.recv() => match cmd {
                 None => return Err(eyre!("control channel got removed")),
                 Some(ConnectionCommand::Disconnect) => break,
-                Some(ConnectionCommand::ShutdownServer) => sender.shutdown_remote()
+                Some(ConnectionCommand::Power(action)) => {
+                    spawn(request_power_action(connection.clone(), sink.clone(), action));
+                }
+                Some(ConnectionCommand::SystemControl(button)) => session.update(|sender| {
+                    sender.press_system_control(button);
+                    sender.release_system_control(button);
+                }),
+                Some(ConnectionCommand::ListActions) => {
+                    spawn(list_actions(connection.clone(), sink.clone()));
+                }
+                Some(ConnectionCommand::RunAction { name, arguments }) => {
+                    spawn(run_action(connection.clone(), sink.clone(), name, arguments));
+                }
+                Some(ConnectionCommand::SetPresence(settings)) => {
+                    spawn(set_presence(connection.clone(), sink.clone(), settings));
+                }
+                Some(ConnectionCommand::CycleMouseSpeed) => {
+                    pointer.speed = next_speed(&config.hotkeys.speed_presets, pointer.speed);
+                    session.update(|sender| sender.set_pointer_speed(pointer.speed));
+                    tracing::info!("Changed the mouse speed to {}", pointer.speed);
+                }
+                Some(ConnectionCommand::ResetRemote) => session.update(InputSender::reset),
+                Some(ConnectionCommand::ToggleNetworkInfo) => match network_info.take() {
+                    Some(task) => {
+                        task.abort();
+                        sink.add_idle_callback(|data: &mut AppState| data.network_info = None);
+                    }
+                    None => network_info = Some(spawn(collect_network_info(session.stats(), sink.clone())))
+                }
             },
-            _ = timeout => {
-                let msg = sender.write_packet()?;
-                debug_assert!(msg.len() <= connection.max_datagram_size().unwrap());
-                connection.send_datagram(Bytes::copy_from_slice(msg))?;
-                deadline = Some(Instant::now() + Duration::from_secs_f32(1.0 / rate));
-                //tracing::debug!("stats: {:#?}", connection.stats().path);
+            _ = macro_step => session.update(|sender| player.advance(sender)),
+            _ = gamepad_timer.tick(), if captured && gamepad.is_some() => {
+                if let Some(source) = gamepad.as_mut() {
+                    session.update(|sender| forward_gamepad(source.as_mut(), sender));
+                }
             }
         };
-        deadline = match sender.in_sync() {
-            true => None,
-            false => Some(deadline.unwrap_or_else(Instant::now))
-        };
+        if let Some(edges) = edges.as_mut().filter(|_| captured) {
+            if let Some(along) = session.update(|sender| edges.update(sender)) {
+                capture.store(false, Ordering::Relaxed);
+                captured = false;
+                session.update(|sender| set_captured(sender, sink, false));
+                let entry = edges.layout().local_entry(local_desktop(), along);
+                yawi::set_cursor_pos(entry.x as i32, entry.y as i32).unwrap_or_else(|err| tracing::warn!("Could not move the cursor: {}", err));
+            }
+        }
+        // keeps the server from releasing keys that are held down for a long time, including those held by a macro
+        session.set_keepalive(captured || player.deadline().is_some());
     }
 
-    connection.close(0u8.into(), b"Disconnected");
     tracing::trace!("Shutting down key handler");
+    session.close().await
+}
 
-    Ok(())
+/// Returns the first preset that is faster than `current`, wrapping around to the slowest one
+fn next_speed(presets: &[f64], current: f64) -> f64 {
+    let faster = presets
+        .iter()
+        .copied()
+        .filter(|speed| *speed > current)
+        .min_by(f64::total_cmp);
+    let slowest = presets.iter().copied().min_by(f64::total_cmp);
+    faster.or(slowest).unwrap_or(current)
 }
 
-#[instrument]
-async fn connect(host: &str) -> eyre::Result<Connection> {
-    let crypto = rustls::ClientConfig::builder()
-        .with_safe_defaults()
-        .with_custom_certificate_verifier(SkipServerVerification::new())
-        .with_no_client_auth();
-    let mut transport = TransportConfig::default();
-    transport.keep_alive_interval(Some(Duration::from_secs(1)));
-
-    let mut config = ClientConfig::new(Arc::new(crypto));
-    config.transport_config(Arc::new(transport));
-    let mut endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
-    endpoint.set_default_client_config(config);
-
-    let addrs = host
-        .to_socket_addrs()
-        .wrap_err("Is the used host name correct?")?
-        .find(|a| a.is_ipv4())
-        .ok_or_else(|| eyre!("Can not find suitable address"))?;
-    tracing::debug!("Resolved {} to {}", host, addrs);
-    let connection = endpoint.connect(addrs, "dummy")?.await?;
-    Ok(connection)
+fn describe_status(status: DeviceStatus) -> Option<String> {
+    if status.contains(DeviceStatus::UNAVAILABLE) {
+        return Some("target unavailable".to_string());
+    }
+    let stalled: Vec<&str> = [
+        (DeviceStatus::KEYBOARD_STALLED, "keyboard"),
+        (DeviceStatus::MOUSE_STALLED, "mouse"),
+        (DeviceStatus::CONSUMER_DEVICE_STALLED, "media keys"),
+        (DeviceStatus::SYSTEM_CONTROL_STALLED, "power keys"),
+        (DeviceStatus::GAMEPAD_STALLED, "gamepad")
+    ]
+    .into_iter()
+    .filter(|(flag, _)| status.contains(*flag))
+    .map(|(_, name)| name)
+    .collect();
+    match stalled.is_empty() {
+        true => None,
+        false => Some(format!("target not reading: {}", stalled.join(", ")))
+    }
 }
 
-#[instrument(skip(connection, sink))]
-async fn collect_network_info(connection: Connection, sink: ExtEventSink) {
-    let mut queue = VecDeque::new();
-    while connection.close_reason().is_none() {
-        let path = connection.stats().path;
-        queue.push_back((path.sent_packets, path.lost_packets));
-        while queue.len() > 20 {
-            queue.pop_front();
-        }
-        let loss = queue
-            .front()
-            .zip(queue.back())
-            .map(|((ot, ol), (nt, nl))| (nt - ot, nl - ol))
-            .map(|(t, l)| l as f64 / t.max(1) as f64)
-            .unwrap_or(0.0);
-        let info = NetworkInfo {
-            rtt: path.rtt,
-            cwnd: path.cwnd,
-            congestion_events: path.congestion_events,
-            lost_packets: path.lost_packets,
-            lost_bytes: path.lost_bytes,
-            sent_packets: path.sent_packets,
-            recent_loss_rate: loss
-        };
+#[instrument(skip_all)]
+async fn collect_network_info(mut stats: watch::Receiver<Stats>, sink: ExtEventSink) {
+    while stats.changed().await.is_ok() {
+        let info = NetworkInfo::from(*stats.borrow_and_update());
         sink.add_idle_callback(move |data: &mut AppState| {
             data.network_info = Some(info);
         });
-        tokio::time::sleep(Duration::from_secs(1)).await;
     }
     sink.add_idle_callback(move |data: &mut AppState| {
         data.network_info = None;
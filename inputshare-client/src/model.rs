@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
@@ -5,42 +6,127 @@ use std::time::Duration;
 use directories::BaseDirs;
 use druid::im::Vector;
 use druid::{Data, Lens};
+use inputshare_client_core::pointer::PointerProfile;
+use inputshare_client_core::Stats;
+use inputshare_common::{PowerAction, PresenceSettings, SystemControlCode};
 use once_cell::sync::Lazy;
 use ron::ser::{to_string_pretty, PrettyConfig};
 use serde::{Deserialize, Serialize};
 use yawi::VirtualKey;
 
+use crate::edge::ScreenLayout;
+use crate::macros::Macro;
 use crate::utils::keyset::VirtualKeySet;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum ConnectionCommand {
-    ShutdownServer,
+    Power(PowerAction),
+    SystemControl(SystemControlCode),
+    ListActions,
+    RunAction { name: String, arguments: Vec<String> },
+    SetPresence(PresenceSettings),
+    CycleMouseSpeed,
+    ResetRemote,
+    ToggleNetworkInfo,
     Disconnect
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Data, Lens)]
+/// How the trigger of a hotkey has to be pressed
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Data)]
+pub enum Gesture {
+    /// Fires as soon as the trigger is pressed, the trigger itself is swallowed
+    #[default]
+    Press,
+    /// Fires on the second of two quick presses, the first press is passed through
+    DoubleTap,
+    /// Fires once the trigger was held for a while, the trigger is passed through
+    LongPress
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Data, Lens)]
 pub struct Hotkey {
     pub modifiers: VirtualKeySet,
-    pub trigger: VirtualKey
+    pub trigger: VirtualKey,
+    #[serde(default)]
+    pub gesture: Gesture
 }
 
 impl Hotkey {
     pub fn new<T: IntoIterator<Item = VirtualKey>>(modifiers: T, trigger: VirtualKey) -> Self {
         Self {
             modifiers: VirtualKeySet::from_iter(modifiers),
-            trigger
+            trigger,
+            gesture: Gesture::Press
+        }
+    }
+}
+
+/// The hotkeys besides the one that toggles capture
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hotkeys {
+    /// Milliseconds during which the capture hotkey is ignored after it toggled capture
+    pub capture_timeout: u64,
+    /// Milliseconds within which the second press of a double tap has to follow the first
+    pub double_tap: u64,
+    /// Milliseconds for which the trigger of a long press has to be held
+    pub long_press: u64,
+    /// Always switches the input back to the local pc
+    pub release: Option<Hotkey>,
+    pub disconnect: Option<Hotkey>,
+    /// Switches to the next of the `speed_presets` until the connection ends
+    pub cycle_mouse_speed: Option<Hotkey>,
+    /// Releases every key and button on the remote device
+    pub reset_remote: Option<Hotkey>,
+    /// Shows or hides the network info until the connection ends
+    pub toggle_network_info: Option<Hotkey>,
+    pub speed_presets: Vec<f64>
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Self {
+            capture_timeout: 500,
+            double_tap: 300,
+            long_press: 800,
+            release: None,
+            disconnect: None,
+            cycle_mouse_speed: None,
+            reset_remote: None,
+            toggle_network_info: None,
+            speed_presets: vec![0.5, 1.0, 2.0]
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Data, Lens)]
+#[serde(default)]
 pub struct Config {
     pub host_address: String,
     pub hotkey: Hotkey,
+    #[data(same_fn = "PartialEq::eq")]
+    pub hotkeys: Hotkeys,
     pub blacklist: VirtualKeySet,
     pub show_network_info: bool,
     pub network_send_rate: u32,
-    pub mouse_speed_factor: f64
+    #[data(same_fn = "PartialEq::eq")]
+    pub pointer: PointerProfile,
+    #[data(same_fn = "PartialEq::eq")]
+    pub target_pointers: BTreeMap<String, PointerProfile>,
+    pub forward_gamepad: bool,
+    /// Shows the buttons that send the sleep, wake up and power down keys while connected
+    pub show_system_control: bool,
+    /// Switches to the remote device when the pointer is pushed past an edge of the local desktop
+    #[data(same_fn = "PartialEq::eq")]
+    pub screen_layout: Option<ScreenLayout>,
+    /// Key sequences that are sent to the remote device when their hotkey is pressed
+    #[data(same_fn = "PartialEq::eq")]
+    pub macros: Vec<Macro>,
+    /// Replaced by `pointer.speed`, only read to carry over the setting of older configurations
+    #[serde(skip_serializing)]
+    #[data(ignore)]
+    #[lens(ignore)]
+    mouse_speed_factor: Option<f64>
 }
 
 impl Default for Config {
@@ -48,6 +134,7 @@ impl Default for Config {
         Self {
             host_address: "localhost:12345".to_string(),
             hotkey: Hotkey::new([VirtualKey::LControl], VirtualKey::Tab),
+            hotkeys: Hotkeys::default(),
             blacklist: VirtualKeySet::from_iter([
                 VirtualKey::VolumeDown,
                 VirtualKey::VolumeUp,
@@ -59,12 +146,23 @@ impl Default for Config {
             ]),
             show_network_info: false,
             network_send_rate: 100,
-            mouse_speed_factor: 1.0
+            pointer: PointerProfile::default(),
+            target_pointers: BTreeMap::new(),
+            forward_gamepad: false,
+            show_system_control: true,
+            screen_layout: None,
+            macros: Vec::new(),
+            mouse_speed_factor: None
         }
     }
 }
 
 impl Config {
+    /// Returns the pointer profile for `host`, falling back to the default profile
+    pub fn pointer_profile(&self, host: &str) -> &PointerProfile {
+        self.target_pointers.get(host).unwrap_or(&self.pointer)
+    }
+
     pub fn path() -> &'static Path {
         static PATH: Lazy<PathBuf> = Lazy::new(|| {
             let dirs = BaseDirs::new().expect("Can not get base dirs");
@@ -79,7 +177,11 @@ impl Config {
         let config: Self = match path.exists() {
             true => {
                 let file = std::fs::read_to_string(path)?;
-                ron::from_str(&file)?
+                let mut config: Self = ron::from_str(&file)?;
+                if let Some(speed) = config.mouse_speed_factor.take() {
+                    config.pointer.speed = speed;
+                }
+                config
             }
             false => {
                 let conf = Self::default();
@@ -114,9 +216,20 @@ pub enum ConnectionState {
 pub enum PopupType {
     Searching(Vector<SearchResult>),
     Error(String),
+    Actions(Vector<RemoteAction>),
+    ActionOutput(String),
     PressKey
 }
 
+/// A named action of the server together with the arguments the user entered for it
+#[derive(Debug, Clone, Eq, PartialEq, Data, Lens)]
+pub struct RemoteAction {
+    pub name: String,
+    pub description: String,
+    pub takes_arguments: bool,
+    pub arguments: String
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Data)]
 pub struct SearchResult {
     pub addrs: SocketAddr
@@ -133,11 +246,32 @@ pub struct NetworkInfo {
     pub recent_loss_rate: f64
 }
 
+impl From<Stats> for NetworkInfo {
+    fn from(stats: Stats) -> Self {
+        Self {
+            rtt: stats.rtt,
+            cwnd: stats.cwnd,
+            congestion_events: stats.congestion_events,
+            lost_packets: stats.lost_packets,
+            lost_bytes: stats.lost_bytes,
+            sent_packets: stats.sent_packets,
+            recent_loss_rate: stats.recent_loss_rate
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, Data, Lens)]
 pub struct AppState {
     pub config: Config,
     pub connection_state: ConnectionState,
     pub enable_shutdown: bool,
+    /// The power actions the connected server has enabled
+    #[data(same_fn = "PartialEq::eq")]
+    pub power_actions: Vec<PowerAction>,
+    /// The presence keeper of the connected server, `None` if it has none
+    #[data(same_fn = "PartialEq::eq")]
+    pub presence: Option<PresenceSettings>,
     pub network_info: Option<NetworkInfo>,
+    pub remote_warning: Option<String>,
     pub popup: Option<PopupType>
 }
@@ -3,6 +3,7 @@ use std::net::{IpAddr, SocketAddr};
 
 use druid::im::Vector;
 use druid::{EventCtx, ExtEventSink};
+use inputshare_common::{PowerAction, SystemControlCode};
 use mdns_sd::{Receiver, ServiceDaemon, ServiceEvent};
 use tracing::instrument;
 use yawi::{HookAction, InputEvent, InputHook, KeyState, VirtualKey};
@@ -19,17 +20,18 @@ pub fn initiate_connection(ctx: &mut EventCtx) {
         ConnectionState::Disconnected => {
             data.connection_state = ConnectionState::Connecting;
             let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
-            let host = data.config.host_address.clone();
-            let info = data.config.show_network_info;
-            let rate = data.config.network_send_rate as f32;
+            let config = data.config.clone();
             rt.runtime.spawn(async move {
-                let result = connection(&handle, receiver, &host, info, rate).await;
+                let result = connection(&handle, receiver, config).await;
                 handle.add_rt_callback(|rt, data| {
                     rt.hook = None;
                     rt.connection = None;
                     data.connection_state = ConnectionState::Disconnected;
                     data.enable_shutdown = false;
+                    data.power_actions.clear();
+                    data.presence = None;
                     data.network_info = None;
+                    data.remote_warning = None;
                     if let Err(err) = result {
                         tracing::warn!("could not establish connection: {:?}", err);
                         open_popup(rt, data, PopupType::Error(strip_color(&format!("{:?}", err))));
@@ -48,16 +50,64 @@ pub fn initiate_connection(ctx: &mut EventCtx) {
 }
 
 #[instrument(skip(ctx))]
-pub fn shutdown_server(ctx: &mut EventCtx) {
-    ctx.add_rt_callback(|rt, data| {
-        if !data.enable_shutdown {
-            tracing::warn!("Shutdown functions is currently not enabled");
+pub fn request_power_action(ctx: &mut EventCtx, action: PowerAction) {
+    ctx.add_rt_callback(move |rt, data| {
+        if !data.enable_shutdown || !data.power_actions.contains(&action) {
+            tracing::warn!("{:?} is currently not enabled", action);
             return;
         }
         rt.connection
             .as_ref()
-            .and_then(|sender| sender.send(ConnectionCommand::ShutdownServer).ok())
-            .unwrap_or_else(|| tracing::warn!("Failed to send shutdown signal!"));
+            .and_then(|sender| sender.send(ConnectionCommand::Power(action)).ok())
+            .unwrap_or_else(|| tracing::warn!("Failed to send power request!"));
+    });
+}
+
+#[instrument(skip(ctx))]
+pub fn toggle_presence(ctx: &mut EventCtx) {
+    ctx.add_rt_callback(|rt, data| {
+        let mut settings = match data.presence {
+            Some(settings) => settings,
+            None => {
+                tracing::warn!("The server has no presence keeper");
+                return;
+            }
+        };
+        settings.enabled = !settings.enabled;
+        rt.connection
+            .as_ref()
+            .and_then(|sender| sender.send(ConnectionCommand::SetPresence(settings)).ok())
+            .unwrap_or_else(|| tracing::warn!("Failed to change the presence keeper!"));
+    });
+}
+
+#[instrument(skip(ctx))]
+pub fn list_remote_actions(ctx: &mut EventCtx) {
+    ctx.add_rt_callback(|rt, _| {
+        rt.connection
+            .as_ref()
+            .and_then(|sender| sender.send(ConnectionCommand::ListActions).ok())
+            .unwrap_or_else(|| tracing::warn!("Failed to request the remote actions!"));
+    });
+}
+
+#[instrument(skip(ctx))]
+pub fn run_remote_action(ctx: &mut EventCtx, name: String, arguments: Vec<String>) {
+    ctx.add_rt_callback(move |rt, _| {
+        rt.connection
+            .as_ref()
+            .and_then(|sender| sender.send(ConnectionCommand::RunAction { name, arguments }).ok())
+            .unwrap_or_else(|| tracing::warn!("Failed to send remote action!"));
+    });
+}
+
+#[instrument(skip(ctx))]
+pub fn send_system_control(ctx: &mut EventCtx, button: SystemControlCode) {
+    ctx.add_rt_callback(move |rt, _| {
+        rt.connection
+            .as_ref()
+            .and_then(|sender| sender.send(ConnectionCommand::SystemControl(button)).ok())
+            .unwrap_or_else(|| tracing::warn!("Failed to send system control signal!"));
     });
 }
 
@@ -3,9 +3,10 @@ use std::time::Duration;
 
 use druid::text::ParseFormatter;
 use druid::widget::{Button, Controller, CrossAxisAlignment, Flex, Label, Scroll, Stepper, Switch, TextBox, ValueTextBox};
-use druid::{theme, Color, Data, Env, Event, EventCtx, LifeCycle, LifeCycleCtx, TimerToken, UpdateCtx, Widget, WidgetExt};
+use druid::{lens, theme, Color, Data, Env, Event, EventCtx, LensExt, LifeCycle, LifeCycleCtx, TimerToken, UpdateCtx, Widget, WidgetExt};
 use druid_material_icons::normal::action::SEARCH;
 use druid_material_icons::normal::content::ADD;
+use inputshare_client_core::pointer::{Acceleration, PointerProfile};
 use parking_lot::Mutex;
 use yawi::VirtualKey;
 
@@ -20,17 +21,44 @@ pub fn ui() -> impl Widget<Config> + 'static {
     let host = host_ui()
         .lens(Config::host_address);
     let speed = speed_ui()
-        .lens(Config::mouse_speed_factor);
-    let network = network_ui()
+        .lens(Config::pointer.then(lens!(PointerProfile, speed)));
+    let precision = switch_ui()
+        .lens(Config::pointer
+            .then(lens!(PointerProfile, acceleration))
+            .map(|acceleration| *acceleration == Acceleration::EnhancedPrecision, |acceleration, enhanced| {
+                if enhanced != (*acceleration == Acceleration::EnhancedPrecision) {
+                    *acceleration = match enhanced {
+                        true => Acceleration::EnhancedPrecision,
+                        false => Acceleration::Linear
+                    };
+                }
+            }));
+    let network = switch_ui()
         .lens(Config::show_network_info);
+    let gamepad = switch_ui()
+        .lens(Config::forward_gamepad);
+    let system_control = switch_ui()
+        .lens(Config::show_system_control);
     let small_options = Flex::row()
         .with_child(Flex::column()
             .with_child(Label::new("Mouse Speed"))
             .with_child(speed))
         .with_default_spacer()
+        .with_child(Flex::column()
+            .with_child(Label::new("Precision"))
+            .with_child(precision))
+        .with_default_spacer()
         .with_child(Flex::column()
             .with_child(Label::new("Network Info"))
-            .with_child(network));
+            .with_child(network))
+        .with_default_spacer()
+        .with_child(Flex::column()
+            .with_child(Label::new("Gamepad"))
+            .with_child(gamepad))
+        .with_default_spacer()
+        .with_child(Flex::column()
+            .with_child(Label::new("System Keys"))
+            .with_child(system_control));
     let blacklist = blacklist_ui()
         .lens(Config::blacklist);
     let hotkey = hotkey_ui()
@@ -75,7 +103,7 @@ fn speed_ui() -> impl Widget<f64> + 'static {
 }
 
 #[rustfmt::skip]
-fn network_ui() -> impl Widget<bool> + 'static {
+fn switch_ui() -> impl Widget<bool> + 'static {
     Switch::new()
         .env_scope(|env, _| {
             env.set(theme::FOREGROUND_LIGHT, env.get(theme::BACKGROUND_LIGHT));
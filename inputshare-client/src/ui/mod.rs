@@ -3,7 +3,7 @@ use druid::{Widget, WidgetExt};
 
 use crate::model::{AppState, ConnectionState};
 
-mod actions;
+pub mod actions;
 mod config;
 mod popup;
 mod status;
@@ -1,12 +1,12 @@
 use std::mem::Discriminant;
 
 use druid::im::Vector;
-use druid::widget::{BackgroundBrush, Button, Flex, Label, List, TextBox, ViewSwitcher};
-use druid::{Color, Lens, LensExt, Widget, WidgetExt};
+use druid::widget::{BackgroundBrush, Button, CrossAxisAlignment, Either, Flex, Label, List, SizedBox, TextBox, ViewSwitcher};
+use druid::{Color, Insets, Lens, LensExt, Widget, WidgetExt};
 
-use crate::model::{PopupType, SearchResult};
+use crate::model::{PopupType, RemoteAction, SearchResult};
 use crate::runtime::ExtEventSinkCallback;
-use crate::ui::actions::close_popup;
+use crate::ui::actions::{close_popup, run_remote_action};
 
 #[rustfmt::skip]
 pub fn ui() -> impl Widget<PopupType> + 'static {
@@ -21,8 +21,16 @@ pub fn ui() -> impl Widget<PopupType> + 'static {
                 key_popup_ui()
                     .boxed(),
             PopupType::Error(_) =>
-                error_popup_ui()
+                text_popup_ui("Unexpected Error")
                     .lens(error_lens())
+                    .boxed(),
+            PopupType::Actions(_) =>
+                actions_popup_ui()
+                    .lens(actions_lens())
+                    .boxed(),
+            PopupType::ActionOutput(_) =>
+                text_popup_ui("Action Output")
+                    .lens(output_lens())
                     .boxed()
         }
     )
@@ -31,13 +39,13 @@ pub fn ui() -> impl Widget<PopupType> + 'static {
     .expand()
 }
 
-fn error_popup_ui() -> impl Widget<String> + 'static {
+fn text_popup_ui(title: &str) -> impl Widget<String> + 'static {
     let error = TextBox::multiline()
         .scroll()
         .content_must_fill(true)
         .expand()
         .lens(readonly_lens());
-    let text = Label::new("Unexpected Error")
+    let text = Label::new(title)
         .with_text_size(20.0)
         .expand_width();
     let back = Button::new("Back").on_click(|ctx, _, _| ctx.add_rt_callback(close_popup));
@@ -58,6 +66,59 @@ fn error_popup_ui() -> impl Widget<String> + 'static {
         .expand()
 }
 
+fn actions_popup_ui() -> impl Widget<Vector<RemoteAction>> + 'static {
+    let list = Either::new(
+        |data: &Vector<RemoteAction>, _| data.is_empty(),
+        Label::new("The server has no actions"),
+        List::new(remote_action_ui).scroll().vertical()
+    );
+    let text = Label::new("Remote Actions")
+        .with_text_size(20.0)
+        .expand_width();
+    let back = Button::new("Back").on_click(|ctx, _, _| ctx.add_rt_callback(close_popup));
+    Flex::column()
+        .with_child(
+            Flex::row()
+                .with_flex_child(text, 1.0)
+                .with_spacer(5.0)
+                .with_child(back)
+        )
+        .with_spacer(5.0)
+        .with_flex_child(list, 1.0)
+        .expand()
+        .padding(7.0)
+        .background(druid::theme::BACKGROUND_DARK)
+        .rounded(5.0)
+        .padding(30.0)
+        .expand()
+}
+
+#[rustfmt::skip]
+fn remote_action_ui() -> impl Widget<RemoteAction> + 'static {
+    let run = Button::new("Run")
+        .on_click(|ctx, data: &mut RemoteAction, _| {
+            let arguments = data.arguments
+                .split_whitespace()
+                .map(String::from)
+                .collect();
+            run_remote_action(ctx, data.name.clone(), arguments)
+        });
+    let arguments = TextBox::new()
+        .with_placeholder("Arguments")
+        .expand_width()
+        .lens(RemoteAction::arguments);
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Flex::row()
+            .with_flex_child(Label::dynamic(|data: &RemoteAction, _| data.name.clone())
+                .expand_width(), 1.0)
+            .with_child(run))
+        .with_child(Label::dynamic(|data: &RemoteAction, _| data.description.clone())
+            .with_text_size(12.0))
+        .with_child(Either::new(|data: &RemoteAction, _| data.takes_arguments, arguments, SizedBox::empty()))
+        .padding(Insets::uniform_xy(0.0, 3.0))
+}
+
 fn key_popup_ui() -> impl Widget<PopupType> + 'static {
     Label::new("Press any key")
         .center()
@@ -109,6 +170,26 @@ fn error_lens() -> impl Lens<PopupType, String> {
     )
 }
 
+fn actions_lens() -> impl Lens<PopupType, Vector<RemoteAction>> {
+    druid::lens::Identity.map(
+        |data| match data {
+            PopupType::Actions(actions) => actions.clone(),
+            _ => unreachable!()
+        },
+        |data, actions| *data = PopupType::Actions(actions)
+    )
+}
+
+fn output_lens() -> impl Lens<PopupType, String> {
+    druid::lens::Identity.map(
+        |data| match data {
+            PopupType::ActionOutput(s) => s.clone(),
+            _ => unreachable!()
+        },
+        |data, s| *data = PopupType::ActionOutput(s)
+    )
+}
+
 fn readonly_lens() -> impl Lens<String, String> {
     druid::lens::Identity.map(|data: &String| data.clone(), |_, _| {})
 }
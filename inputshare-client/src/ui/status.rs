@@ -1,14 +1,16 @@
 use druid::theme::TEXT_COLOR;
 use druid::widget::{Button, Either, Flex, Label, Maybe, SizedBox};
 use druid::{Color, Env, Insets, Lens, LensExt, Widget, WidgetExt};
+use inputshare_common::{PowerAction, SystemControlCode};
 
 use crate::model::{AppState, ConnectionState, NetworkInfo, Side};
-use crate::ui::actions::{initiate_connection, shutdown_server};
+use crate::ui::actions::{initiate_connection, list_remote_actions, request_power_action, send_system_control, toggle_presence};
 
 #[rustfmt::skip]
 pub fn ui() -> impl Widget<AppState> + 'static {
     let status = Flex::column()
         .with_child(Maybe::or_empty(info_ui).lens(AppState::network_info))
+        .with_child(Maybe::or_empty(warning_ui).lens(AppState::remote_warning))
         .with_child(Label::dynamic(connection_status)
             .with_text_size(15.0))
         .with_child(Maybe::or_empty(side_ui).lens(side_lens()))
@@ -19,19 +21,56 @@ pub fn ui() -> impl Widget<AppState> + 'static {
     let connect_button = Button::dynamic(button_label)
         .on_click(|ctx, _, _| initiate_connection(ctx))
         .expand();
-    let shutdown_button = Button::new("Shutdown")
-        .on_click(|ctx, _, _| shutdown_server(ctx))
-        .padding(Insets::new(0.0, 3.0, 0.0, 0.0))
-        .expand_width();
     let buttons = Flex::column()
         .with_flex_child(connect_button, 1.0)
-        .with_child(Either::new(|data: &AppState, _| data.enable_shutdown, shutdown_button, SizedBox::empty()))
+        .with_child(power_button("Shutdown", PowerAction::Shutdown))
+        .with_child(power_button("Reboot", PowerAction::Reboot))
         .fix_width(100.0);
-    Flex::row()
+    let main = Flex::row()
         .with_flex_child(status, 1.0)
         .with_spacer(3.0)
         .with_child(buttons)
-        .fix_height(80.0)
+        .fix_height(80.0);
+    let actions_button = Button::new("Actions")
+        .on_click(|ctx, _, _| list_remote_actions(ctx))
+        .expand_width();
+    let system_buttons = Flex::row()
+        .with_flex_child(system_control_button("Sleep", SystemControlCode::Sleep), 1.0)
+        .with_spacer(3.0)
+        .with_flex_child(system_control_button("Wake Up", SystemControlCode::WakeUp), 1.0)
+        .with_spacer(3.0)
+        .with_flex_child(system_control_button("Power Down", SystemControlCode::PowerDown), 1.0)
+        .padding(Insets::new(0.0, 3.0, 0.0, 0.0));
+    let presence_button = Button::dynamic(presence_label)
+        .on_click(|ctx, _, _| toggle_presence(ctx))
+        .expand_width();
+    let server_buttons = Flex::row()
+        .with_flex_child(Either::new(|data: &AppState, _| data.presence.is_some(), presence_button, SizedBox::empty()), 1.0)
+        .with_spacer(3.0)
+        .with_flex_child(actions_button, 1.0)
+        .padding(Insets::new(0.0, 3.0, 0.0, 0.0));
+    Flex::column()
+        .with_child(main)
+        .with_child(Either::new(show_system_control, system_buttons, SizedBox::empty()))
+        .with_child(Either::new(|data: &AppState, _| data.enable_shutdown, server_buttons, SizedBox::empty()))
+}
+
+fn power_button(label: &str, action: PowerAction) -> impl Widget<AppState> + 'static {
+    let button = Button::new(label)
+        .on_click(move |ctx, _, _| request_power_action(ctx, action))
+        .padding(Insets::new(0.0, 3.0, 0.0, 0.0))
+        .expand_width();
+    Either::new(move |data: &AppState, _| data.enable_shutdown && data.power_actions.contains(&action), button, SizedBox::empty())
+}
+
+fn show_system_control(data: &AppState, _: &Env) -> bool {
+    data.config.show_system_control && matches!(data.connection_state, ConnectionState::Connected(_))
+}
+
+fn system_control_button(label: &str, button: SystemControlCode) -> impl Widget<AppState> + 'static {
+    Button::new(label)
+        .on_click(move |ctx, _, _| send_system_control(ctx, button))
+        .expand_width()
 }
 
 #[rustfmt::skip]
@@ -50,6 +89,13 @@ fn info_ui() -> impl Widget<NetworkInfo> + 'static {
         .with_text_size(12.0)
 }
 
+#[rustfmt::skip]
+fn warning_ui() -> impl Widget<String> + 'static {
+    Label::dynamic(|warning: &String, _| warning.clone())
+        .with_text_size(12.0)
+        .with_text_color(Color::RED)
+}
+
 fn button_label(data: &AppState, _: &Env) -> String {
     match data.connection_state {
         ConnectionState::Disconnected => "Connect",
@@ -59,6 +105,14 @@ fn button_label(data: &AppState, _: &Env) -> String {
     .to_string()
 }
 
+fn presence_label(data: &AppState, _: &Env) -> String {
+    match data.presence {
+        Some(settings) if settings.enabled => "Keep Awake: On",
+        _ => "Keep Awake: Off"
+    }
+    .to_string()
+}
+
 fn connection_status(data: &AppState, _: &Env) -> String {
     match data.connection_state {
         ConnectionState::Connected(_) => "Connected",
@@ -0,0 +1,285 @@
+use std::cmp::Reverse;
+use std::time::{Duration, Instant};
+
+use yawi::{KeyEvent, KeyState, VirtualKey};
+
+use crate::model::{Gesture, Hotkey};
+use crate::utils::keyset::VirtualKeySet;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Timing {
+    /// The longest time between the two presses of a double tap
+    pub double_tap: Duration,
+    /// The shortest time the trigger of a long press has to be held
+    pub long_press: Duration
+}
+
+/// What should happen with a key event after it went through the bindings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome<A> {
+    /// Whether the event has to be kept from everyone else
+    pub block: bool,
+    /// The action of the binding that fired
+    pub action: Option<A>
+}
+
+impl<A> Outcome<A> {
+    fn pass(action: Option<A>) -> Self {
+        Self { block: false, action }
+    }
+
+    fn block(action: Option<A>) -> Self {
+        Self { block: true, action }
+    }
+}
+
+/// Matches key events against a table of hotkeys
+///
+/// This is a pure state machine, the time of each event is supplied by the caller.
+/// Long presses fire on the first key repeat or release after the trigger was held long enough.
+#[derive(Debug)]
+pub struct Bindings<A> {
+    bindings: Vec<(Hotkey, A)>,
+    timing: Timing,
+    pressed: VirtualKeySet,
+    /// The trigger whose press was blocked, its repeats and its release get blocked as well
+    consumed: Option<VirtualKey>,
+    /// The binding and time of the first press of a possible double tap
+    tap: Option<(usize, Instant)>,
+    /// The binding and time of the press that may become a long press
+    hold: Option<(usize, Instant)>
+}
+
+impl<A: Clone> Bindings<A> {
+    pub fn new<T: IntoIterator<Item = (Hotkey, A)>>(bindings: T, timing: Timing) -> Self {
+        let mut bindings: Vec<(Hotkey, A)> = bindings.into_iter().collect();
+        // hotkeys with more modifiers are more specific, the stable sort keeps the order of equal ones
+        bindings.sort_by_key(|(hotkey, _)| Reverse(hotkey.modifiers.len()));
+        Self {
+            bindings,
+            timing,
+            pressed: VirtualKeySet::new(),
+            consumed: None,
+            tap: None,
+            hold: None
+        }
+    }
+
+    pub fn handle(&mut self, event: KeyEvent, now: Instant) -> Outcome<A> {
+        let KeyEvent { key, state } = event;
+        match state {
+            KeyState::Pressed if self.pressed.contains(key) => self.on_repeat(key, now),
+            KeyState::Pressed => {
+                self.pressed.insert(key);
+                self.on_press(key, now)
+            }
+            KeyState::Released => {
+                self.pressed.remove(key);
+                self.on_release(key, now)
+            }
+        }
+    }
+
+    fn on_press(&mut self, key: VirtualKey, now: Instant) -> Outcome<A> {
+        self.hold = None;
+        let index = self
+            .bindings
+            .iter()
+            .position(|(hotkey, _)| hotkey.trigger == key && self.pressed.is_superset(hotkey.modifiers));
+        let index = match index {
+            Some(index) => index,
+            None => {
+                self.tap = None;
+                return Outcome::pass(None);
+            }
+        };
+        let (hotkey, action) = &self.bindings[index];
+        match hotkey.gesture {
+            Gesture::Press => {
+                self.tap = None;
+                self.consumed = Some(key);
+                Outcome::block(Some(action.clone()))
+            }
+            Gesture::DoubleTap => match self.tap.take() {
+                Some((tapped, at)) if tapped == index && now.duration_since(at) <= self.timing.double_tap => {
+                    self.consumed = Some(key);
+                    Outcome::block(Some(action.clone()))
+                }
+                _ => {
+                    self.tap = Some((index, now));
+                    Outcome::pass(None)
+                }
+            },
+            Gesture::LongPress => {
+                self.tap = None;
+                self.hold = Some((index, now));
+                Outcome::pass(None)
+            }
+        }
+    }
+
+    fn on_repeat(&mut self, key: VirtualKey, now: Instant) -> Outcome<A> {
+        match self.consumed == Some(key) {
+            true => Outcome::block(None),
+            false => Outcome::pass(self.finish_hold(key, now))
+        }
+    }
+
+    fn on_release(&mut self, key: VirtualKey, now: Instant) -> Outcome<A> {
+        if self.consumed == Some(key) {
+            self.consumed = None;
+            return Outcome::block(None);
+        }
+        let action = self.finish_hold(key, now);
+        if matches!(self.hold, Some((index, _)) if self.bindings[index].0.trigger == key) {
+            self.hold = None;
+        }
+        Outcome::pass(action)
+    }
+
+    fn finish_hold(&mut self, key: VirtualKey, now: Instant) -> Option<A> {
+        match self.hold {
+            Some((index, start)) if self.bindings[index].0.trigger == key && now.duration_since(start) >= self.timing.long_press => {
+                self.hold = None;
+                Some(self.bindings[index].1.clone())
+            }
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMING: Timing = Timing {
+        double_tap: Duration::from_millis(300),
+        long_press: Duration::from_millis(800)
+    };
+
+    fn hotkey<const N: usize>(modifiers: [VirtualKey; N], trigger: VirtualKey, gesture: Gesture) -> Hotkey {
+        Hotkey {
+            gesture,
+            ..Hotkey::new(modifiers, trigger)
+        }
+    }
+
+    fn press(key: VirtualKey) -> KeyEvent {
+        KeyEvent {
+            key,
+            state: KeyState::Pressed
+        }
+    }
+
+    fn release(key: VirtualKey) -> KeyEvent {
+        KeyEvent {
+            key,
+            state: KeyState::Released
+        }
+    }
+
+    fn ms(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn press_fires_and_swallows_the_trigger() {
+        let mut bindings = Bindings::new([(hotkey([VirtualKey::LControl], VirtualKey::Tab, Gesture::Press), 1)], TIMING);
+        let now = Instant::now();
+        assert_eq!(bindings.handle(press(VirtualKey::LControl), now), Outcome::pass(None));
+        assert_eq!(bindings.handle(press(VirtualKey::Tab), now), Outcome::block(Some(1)));
+        assert_eq!(bindings.handle(press(VirtualKey::Tab), now), Outcome::block(None));
+        assert_eq!(bindings.handle(release(VirtualKey::Tab), now), Outcome::block(None));
+        assert_eq!(bindings.handle(release(VirtualKey::LControl), now), Outcome::pass(None));
+    }
+
+    #[test]
+    fn press_requires_the_modifiers() {
+        let mut bindings = Bindings::new([(hotkey([VirtualKey::LControl], VirtualKey::Tab, Gesture::Press), 1)], TIMING);
+        let now = Instant::now();
+        assert_eq!(bindings.handle(press(VirtualKey::Tab), now), Outcome::pass(None));
+        assert_eq!(bindings.handle(release(VirtualKey::Tab), now), Outcome::pass(None));
+    }
+
+    #[test]
+    fn more_modifiers_take_precedence() {
+        let mut bindings = Bindings::new(
+            [
+                (hotkey([VirtualKey::LControl], VirtualKey::Tab, Gesture::Press), 1),
+                (hotkey([VirtualKey::LControl, VirtualKey::LShift], VirtualKey::Tab, Gesture::Press), 2)
+            ],
+            TIMING
+        );
+        let now = Instant::now();
+        bindings.handle(press(VirtualKey::LControl), now);
+        bindings.handle(press(VirtualKey::LShift), now);
+        assert_eq!(bindings.handle(press(VirtualKey::Tab), now).action, Some(2));
+        bindings.handle(release(VirtualKey::Tab), now);
+        bindings.handle(release(VirtualKey::LShift), now);
+        assert_eq!(bindings.handle(press(VirtualKey::Tab), now).action, Some(1));
+    }
+
+    #[test]
+    fn double_tap_fires_on_second_press() {
+        let mut bindings = Bindings::new([(hotkey([], VirtualKey::RControl, Gesture::DoubleTap), 1)], TIMING);
+        let start = Instant::now();
+        assert_eq!(bindings.handle(press(VirtualKey::RControl), start), Outcome::pass(None));
+        assert_eq!(bindings.handle(release(VirtualKey::RControl), ms(start, 50)), Outcome::pass(None));
+        assert_eq!(bindings.handle(press(VirtualKey::RControl), ms(start, 200)), Outcome::block(Some(1)));
+        assert_eq!(bindings.handle(release(VirtualKey::RControl), ms(start, 250)), Outcome::block(None));
+        // a third press starts over
+        assert_eq!(bindings.handle(press(VirtualKey::RControl), ms(start, 300)), Outcome::pass(None));
+    }
+
+    #[test]
+    fn double_tap_expires() {
+        let mut bindings = Bindings::new([(hotkey([], VirtualKey::RControl, Gesture::DoubleTap), 1)], TIMING);
+        let start = Instant::now();
+        bindings.handle(press(VirtualKey::RControl), start);
+        bindings.handle(release(VirtualKey::RControl), ms(start, 50));
+        assert_eq!(bindings.handle(press(VirtualKey::RControl), ms(start, 400)), Outcome::pass(None));
+        bindings.handle(release(VirtualKey::RControl), ms(start, 450));
+        assert_eq!(bindings.handle(press(VirtualKey::RControl), ms(start, 500)), Outcome::block(Some(1)));
+    }
+
+    #[test]
+    fn double_tap_is_interrupted_by_other_keys() {
+        let mut bindings = Bindings::new([(hotkey([], VirtualKey::RControl, Gesture::DoubleTap), 1)], TIMING);
+        let start = Instant::now();
+        bindings.handle(press(VirtualKey::RControl), start);
+        bindings.handle(release(VirtualKey::RControl), ms(start, 20));
+        bindings.handle(press(VirtualKey::KeyA), ms(start, 40));
+        bindings.handle(release(VirtualKey::KeyA), ms(start, 60));
+        assert_eq!(bindings.handle(press(VirtualKey::RControl), ms(start, 80)), Outcome::pass(None));
+    }
+
+    #[test]
+    fn long_press_fires_once_held_long_enough() {
+        let mut bindings = Bindings::new([(hotkey([], VirtualKey::Scroll, Gesture::LongPress), 1)], TIMING);
+        let start = Instant::now();
+        assert_eq!(bindings.handle(press(VirtualKey::Scroll), start), Outcome::pass(None));
+        assert_eq!(bindings.handle(press(VirtualKey::Scroll), ms(start, 500)), Outcome::pass(None));
+        assert_eq!(bindings.handle(press(VirtualKey::Scroll), ms(start, 850)), Outcome::pass(Some(1)));
+        assert_eq!(bindings.handle(press(VirtualKey::Scroll), ms(start, 900)), Outcome::pass(None));
+        assert_eq!(bindings.handle(release(VirtualKey::Scroll), ms(start, 1000)), Outcome::pass(None));
+    }
+
+    #[test]
+    fn long_press_fires_on_release_without_repeats() {
+        let mut bindings = Bindings::new([(hotkey([], VirtualKey::Scroll, Gesture::LongPress), 1)], TIMING);
+        let start = Instant::now();
+        bindings.handle(press(VirtualKey::Scroll), start);
+        assert_eq!(bindings.handle(release(VirtualKey::Scroll), ms(start, 900)), Outcome::pass(Some(1)));
+    }
+
+    #[test]
+    fn short_or_interrupted_presses_are_not_long_presses() {
+        let mut bindings = Bindings::new([(hotkey([], VirtualKey::Scroll, Gesture::LongPress), 1)], TIMING);
+        let start = Instant::now();
+        bindings.handle(press(VirtualKey::Scroll), start);
+        assert_eq!(bindings.handle(release(VirtualKey::Scroll), ms(start, 100)), Outcome::pass(None));
+        bindings.handle(press(VirtualKey::Scroll), ms(start, 200));
+        bindings.handle(press(VirtualKey::KeyA), ms(start, 300));
+        assert_eq!(bindings.handle(release(VirtualKey::Scroll), ms(start, 1200)), Outcome::pass(None));
+    }
+}
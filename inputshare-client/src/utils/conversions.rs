@@ -1,8 +1,38 @@
-use inputshare_common::{ConsumerDeviceCode, HidButtonCode, HidKeyCode};
-use yawi::{VirtualKey, WindowsScanCode};
+use inputshare_common::{ConsumerDeviceCode, GamepadButton, GamepadHat, GamepadState, HidButtonCode, HidKeyCode, Vec2};
+use yawi::{ControllerButton, ControllerState, VirtualKey, WindowsScanCode};
 
-pub fn f32_to_i8(v: f32) -> i8 {
-    v.round().clamp(i8::MIN as f32, i8::MAX as f32) as i8
+pub fn controller_to_gamepad(controller: ControllerState) -> GamepadState {
+    const BUTTONS: [(ControllerButton, GamepadButton); 11] = [
+        (ControllerButton::A, GamepadButton::South),
+        (ControllerButton::B, GamepadButton::East),
+        (ControllerButton::X, GamepadButton::West),
+        (ControllerButton::Y, GamepadButton::North),
+        (ControllerButton::LeftShoulder, GamepadButton::LeftBumper),
+        (ControllerButton::RightShoulder, GamepadButton::RightBumper),
+        (ControllerButton::Back, GamepadButton::Select),
+        (ControllerButton::Start, GamepadButton::Start),
+        (ControllerButton::LeftThumb, GamepadButton::LeftStick),
+        (ControllerButton::RightThumb, GamepadButton::RightStick),
+        (ControllerButton::Guide, GamepadButton::Mode)
+    ];
+    let mut state = GamepadState::default();
+    for (from, to) in BUTTONS {
+        state.set_pressed(to, controller.is_pressed(from));
+    }
+    state.hat = GamepadHat::from_directions(
+        controller.is_pressed(ControllerButton::DPadUp),
+        controller.is_pressed(ControllerButton::DPadDown),
+        controller.is_pressed(ControllerButton::DPadLeft),
+        controller.is_pressed(ControllerButton::DPadRight)
+    );
+    // XInput points the y axis up while HID points it down
+    let (lx, ly) = controller.left_thumb;
+    let (rx, ry) = controller.right_thumb;
+    state.left_stick = Vec2::new(lx, ly.saturating_neg());
+    state.right_stick = Vec2::new(rx, ry.saturating_neg());
+    state.left_trigger = controller.left_trigger;
+    state.right_trigger = controller.right_trigger;
+    state
 }
 
 pub fn vk_to_mb(key: VirtualKey) -> Option<HidButtonCode> {
@@ -16,6 +46,21 @@ pub fn vk_to_mb(key: VirtualKey) -> Option<HidButtonCode> {
     }
 }
 
+/// The HID code of the modifier `key`, the generic virtual keys have no side and are not mapped
+pub fn vk_to_modifier(key: VirtualKey) -> Option<HidKeyCode> {
+    match key {
+        VirtualKey::LControl => Some(HidKeyCode::LeftCtrl),
+        VirtualKey::LShift => Some(HidKeyCode::LeftShift),
+        VirtualKey::LMenu => Some(HidKeyCode::LeftAlt),
+        VirtualKey::LWin => Some(HidKeyCode::LeftMeta),
+        VirtualKey::RControl => Some(HidKeyCode::RightCtrl),
+        VirtualKey::RShift => Some(HidKeyCode::RightShift),
+        VirtualKey::RMenu => Some(HidKeyCode::RightAlt),
+        VirtualKey::RWin => Some(HidKeyCode::RightMeta),
+        _ => None
+    }
+}
+
 pub fn wsc_to_hkc(scancode: WindowsScanCode) -> Option<HidKeyCode> {
     match scancode {
         0x1 => Some(HidKeyCode::Escape),
@@ -1,20 +1,70 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use tokio::sync::mpsc::UnboundedSender;
-use yawi::{send_inputs, HookAction, HookFn, Input, InputEvent, KeyEvent, KeyState, VirtualKey};
+use yawi::{send_inputs, HookAction, HookFn, Input, InputEvent, KeyEvent, KeyState};
 
-use crate::model::Config;
+use inputshare_common::edge::Rect;
+use inputshare_common::{HidKeyCode, Vec2};
+
+use crate::macros::MacroStep;
+use crate::model::{Config, ConnectionCommand};
+use crate::utils::bindings::{Bindings, Timing};
+use crate::utils::conversions::vk_to_modifier;
 use crate::utils::keyset::VirtualKeySet;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub enum HookEvent {
     Captured(bool),
-    Input(InputEvent)
+    /// The pointer was pushed past the edge of the screen layout, at the given position along the edge
+    EdgeCrossed(f64),
+    Input(InputEvent),
+    /// A triggered macro and the modifiers that are held down on the remote device
+    Macro(Arc<[MacroStep]>, Vec<HidKeyCode>)
 }
 
-const CAPTURE_TIMEOUT: Duration = Duration::from_millis(500);
+#[derive(Debug, Clone)]
+enum HotkeyAction {
+    ToggleCapture,
+    Release,
+    Command(ConnectionCommand),
+    Macro(Arc<[MacroStep]>)
+}
 
-pub fn create_callback(config: &Config, sender: UnboundedSender<HookEvent>) -> HookFn {
+fn bindings(config: &Config) -> Bindings<HotkeyAction> {
+    let hotkeys = &config.hotkeys;
+    let optional = [
+        (&hotkeys.release, HotkeyAction::Release),
+        (&hotkeys.disconnect, HotkeyAction::Command(ConnectionCommand::Disconnect)),
+        (&hotkeys.cycle_mouse_speed, HotkeyAction::Command(ConnectionCommand::CycleMouseSpeed)),
+        (&hotkeys.reset_remote, HotkeyAction::Command(ConnectionCommand::ResetRemote)),
+        (&hotkeys.toggle_network_info, HotkeyAction::Command(ConnectionCommand::ToggleNetworkInfo))
+    ]
+    .into_iter()
+    .filter_map(|(hotkey, action)| hotkey.clone().map(|hotkey| (hotkey, action)));
+    let macros = config
+        .macros
+        .iter()
+        .map(|m| (m.hotkey.clone(), HotkeyAction::Macro(m.steps.as_slice().into())));
+    let timing = Timing {
+        double_tap: Duration::from_millis(hotkeys.double_tap),
+        long_press: Duration::from_millis(hotkeys.long_press)
+    };
+    Bindings::new(
+        std::iter::once((config.hotkey.clone(), HotkeyAction::ToggleCapture))
+            .chain(optional)
+            .chain(macros),
+        timing
+    )
+}
+
+/// Creates the hook that captures the local input
+///
+/// `capture` is shared with the connection, which releases the input when the pointer returns from the remote screen.
+pub fn create_callback(
+    config: &Config, sender: UnboundedSender<HookEvent>, commands: UnboundedSender<ConnectionCommand>, capture: Arc<AtomicBool>
+) -> HookFn {
     let send = move |event| {
         sender
             .send(event)
@@ -24,17 +74,19 @@ pub fn create_callback(config: &Config, sender: UnboundedSender<HookEvent>) -> H
     let mut old_mouse_pos = yawi::get_cursor_pos();
 
     let blacklist = config.blacklist;
-    let modifiers = config.hotkey.modifiers;
-    let trigger = config.hotkey.trigger;
+    let mut bindings = bindings(config);
+    let capture_timeout = Duration::from_millis(config.hotkeys.capture_timeout);
+    let layout = config.screen_layout.clone();
+    let desktop = local_desktop();
 
-    let mut captured = false;
     let mut pressed_keys = VirtualKeySet::new();
-    let mut hotkey_pressed = false;
 
     let mut last_swap = Instant::now();
 
-    send(HookEvent::Captured(captured));
+    capture.store(false, Ordering::Relaxed);
+    send(HookEvent::Captured(false));
     HookFn::new(move |event| {
+        let mut captured = capture.load(Ordering::Relaxed);
         let key_event = event.to_key_event();
         if is_blacklisted(blacklist, key_event) {
             return HookAction::Continue;
@@ -42,25 +94,49 @@ pub fn create_callback(config: &Config, sender: UnboundedSender<HookEvent>) -> H
 
         let should_handle = is_repeated_event(&mut pressed_keys, key_event);
 
-        if should_handle {
-            if let Some(KeyEvent { key, state }) = key_event {
-                if pressed_keys.is_superset(modifiers) && key == trigger && state == KeyState::Pressed {
-                    hotkey_pressed = true;
-                    if last_swap.elapsed() >= CAPTURE_TIMEOUT {
-                        if !captured {
-                            try_release_all(pressed_keys, trigger);
-                        }
-                        captured = !captured;
-                        last_swap = Instant::now();
-                        send(HookEvent::Captured(captured));
+        if let Some(key_event) = key_event {
+            let outcome = bindings.handle(key_event, Instant::now());
+            match outcome.action {
+                Some(HotkeyAction::ToggleCapture) if last_swap.elapsed() >= capture_timeout => {
+                    if !captured {
+                        let mut held = pressed_keys;
+                        held.remove(key_event.key);
+                        try_release_all(held);
                     }
-                    return HookAction::Block;
+                    captured = !captured;
+                    capture.store(captured, Ordering::Relaxed);
+                    last_swap = Instant::now();
+                    send(HookEvent::Captured(captured));
+                }
+                Some(HotkeyAction::Release) if captured => {
+                    captured = false;
+                    capture.store(captured, Ordering::Relaxed);
+                    last_swap = Instant::now();
+                    send(HookEvent::Captured(captured));
                 }
-                if hotkey_pressed && key == trigger && state == KeyState::Released {
-                    hotkey_pressed = false;
-                    return HookAction::Block;
+                Some(HotkeyAction::ToggleCapture | HotkeyAction::Release) => {}
+                Some(HotkeyAction::Command(command)) => commands
+                    .send(command)
+                    .unwrap_or_else(|err| tracing::warn!("Could not send command: {}", err)),
+                Some(HotkeyAction::Macro(steps)) => {
+                    let held = match captured {
+                        true => pressed_keys
+                            .iter()
+                            .filter(|key| *key != key_event.key)
+                            .filter_map(vk_to_modifier)
+                            .collect(),
+                        false => Vec::new()
+                    };
+                    send(HookEvent::Macro(steps, held));
                 }
+                None => {}
             }
+            if outcome.block {
+                return HookAction::Block;
+            }
+        }
+
+        if should_handle {
             if captured {
                 if let InputEvent::MouseMoveEvent(x, y) = event {
                     let (ox, oy) = old_mouse_pos;
@@ -71,7 +147,21 @@ pub fn create_callback(config: &Config, sender: UnboundedSender<HookEvent>) -> H
                     send(HookEvent::Input(event));
                 }
             } else if let InputEvent::MouseMoveEvent(x, y) = event {
-                old_mouse_pos = (x, y);
+                let crossing = layout
+                    .as_ref()
+                    .and_then(|layout| layout.local_crossing(desktop, Vec2::new(x.into(), y.into())));
+                match crossing {
+                    Some(along) if last_swap.elapsed() >= capture_timeout => {
+                        try_release_all(pressed_keys);
+                        captured = true;
+                        capture.store(captured, Ordering::Relaxed);
+                        last_swap = Instant::now();
+                        send(HookEvent::EdgeCrossed(along));
+                        // blocking the movement keeps the cursor at `old_mouse_pos`
+                        return HookAction::Block;
+                    }
+                    _ => old_mouse_pos = (x, y)
+                }
             }
         }
 
@@ -103,10 +193,15 @@ fn is_repeated_event(pressed_keys: &mut VirtualKeySet, event: Option<KeyEvent>)
     }
 }
 
-fn try_release_all(keys: VirtualKeySet, trigger: VirtualKey) {
+/// The bounds of the desktop spanning all monitors
+pub fn local_desktop() -> Rect {
+    let (x, y, width, height) = yawi::get_virtual_screen();
+    Rect::new(x.into(), y.into(), width.into(), height.into())
+}
+
+fn try_release_all(keys: VirtualKeySet) {
     send_inputs(
         keys.iter()
-            .filter(|k| *k != trigger)
             .map(|k| match k.is_mouse_button() {
                 true => Input::MouseButtonInput(k, KeyState::Released),
                 false => Input::KeyboardKeyInput(k, KeyState::Released)
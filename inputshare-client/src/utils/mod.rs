@@ -1,29 +1,47 @@
-use std::sync::Arc;
-
 use druid::ExtEventSink;
+use inputshare_client_core::sender::{detents_to_scroll, InputSender};
 use yawi::{InputEvent, KeyState, ScrollDirection};
 
+use crate::edge::EdgeSwitch;
+use crate::macros::MacroPlayer;
 use crate::model::{AppState, ConnectionState, Side};
-use crate::sender::InputSender;
-use crate::utils::conversions::{f32_to_i8, vk_to_mb, wsc_to_cdc, wsc_to_hkc};
+use crate::utils::conversions::{vk_to_mb, wsc_to_cdc, wsc_to_hkc};
 use crate::utils::hook::HookEvent;
 
+pub mod bindings;
 pub mod conversions;
 pub mod error;
 pub mod hook;
 pub mod keyset;
 
-pub fn process_hook_event(sender: &mut InputSender, sink: &ExtEventSink, event: HookEvent) {
+pub fn set_captured(sender: &mut InputSender, sink: &ExtEventSink, captured: bool) {
+    sink.add_idle_callback(move |data: &mut AppState| {
+        data.connection_state = ConnectionState::Connected(match captured {
+            true => Side::Remote,
+            false => Side::Local
+        });
+    });
+    sender.reset();
+}
+
+pub fn process_hook_event(sender: &mut InputSender, player: &mut MacroPlayer, edges: Option<&mut EdgeSwitch>, sink: &ExtEventSink, event: HookEvent) {
     match event {
         HookEvent::Captured(captured) => {
-            sink.add_idle_callback(move |data: &mut AppState| {
-                data.connection_state = ConnectionState::Connected(match captured {
-                    true => Side::Remote,
-                    false => Side::Local
-                });
-            });
-            sender.reset();
+            set_captured(sender, sink, captured);
+            player.forget_held();
+            if let Some(edges) = edges.filter(|_| captured) {
+                edges.resume(sender);
+            }
         }
+        HookEvent::EdgeCrossed(along) => {
+            set_captured(sender, sink, true);
+            player.forget_held();
+            match edges {
+                Some(edges) => edges.enter(sender, along),
+                None => tracing::warn!("Crossed an edge without a screen layout")
+            }
+        }
+        HookEvent::Macro(steps, held) => player.play(&steps, &held),
         HookEvent::Input(event) => match event {
             InputEvent::MouseMoveEvent(x, y) => {
                 sender.move_mouse(x as i64, y as i64);
@@ -31,7 +49,10 @@ pub fn process_hook_event(sender: &mut InputSender, sink: &ExtEventSink, event:
             InputEvent::KeyboardKeyEvent(vk, sc, ks) => match wsc_to_hkc(sc) {
                 Some(kc) => match ks {
                     KeyState::Pressed => sender.press_key(kc),
-                    KeyState::Released => sender.release_key(kc)
+                    KeyState::Released => {
+                        player.released(kc);
+                        sender.release_key(kc)
+                    }
                 },
                 None => match wsc_to_cdc(sc) {
                     Some(cdc) => match ks {
@@ -53,26 +74,9 @@ pub fn process_hook_event(sender: &mut InputSender, sink: &ExtEventSink, event:
                 None => tracing::warn!("Unknown mouse button: {}", mb)
             },
             InputEvent::MouseWheelEvent(sd) => match sd {
-                ScrollDirection::Horizontal(amount) => sender.scroll_horizontal(f32_to_i8(amount)),
-                ScrollDirection::Vertical(amount) => sender.scroll_vertical(f32_to_i8(amount))
+                ScrollDirection::Horizontal(amount) => sender.scroll_horizontal(detents_to_scroll(amount)),
+                ScrollDirection::Vertical(amount) => sender.scroll_vertical(detents_to_scroll(amount))
             }
         }
     }
 }
-
-pub struct SkipServerVerification;
-
-impl SkipServerVerification {
-    pub fn new() -> Arc<Self> {
-        Arc::new(Self)
-    }
-}
-
-impl rustls::client::ServerCertVerifier for SkipServerVerification {
-    fn verify_server_cert(
-        &self, _end_entity: &rustls::Certificate, _intermediates: &[rustls::Certificate], _server_name: &rustls::ServerName,
-        _scts: &mut dyn Iterator<Item = &[u8]>, _ocsp_response: &[u8], _now: std::time::SystemTime
-    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::ServerCertVerified::assertion())
-    }
-}
@@ -0,0 +1,43 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use inputshare_common::ActionOutput;
+use tokio::process::Command;
+use tracing::instrument;
+
+/// The number of bytes that are kept of stdout and stderr each
+const MAX_OUTPUT_LEN: usize = 16 * 1024;
+
+/// Runs `command` and collects its output
+///
+/// The command is killed if it is still running after `timeout`
+#[instrument]
+pub async fn run(command: &str, args: &[&str], timeout: Duration) -> Result<ActionOutput> {
+    let output = Command::new(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(timeout, output)
+        .await
+        .map_err(|_| anyhow!("{} did not finish within {:?}", command, timeout))?
+        .with_context(|| format!("Could not run {}", command))?;
+    Ok(ActionOutput {
+        exit_code: output.status.code(),
+        stdout: truncate(String::from_utf8_lossy(&output.stdout).into_owned()),
+        stderr: truncate(String::from_utf8_lossy(&output.stderr).into_owned())
+    })
+}
+
+fn truncate(mut output: String) -> String {
+    if output.len() > MAX_OUTPUT_LEN {
+        let mut end = MAX_OUTPUT_LEN;
+        while !output.is_char_boundary(end) {
+            end -= 1;
+        }
+        output.truncate(end);
+        output.push_str("\n[truncated]");
+    }
+    output
+}
@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{ensure, Context, Result};
+use inputshare_common::edge::{Edge, Rect, RemotePointer};
+use inputshare_common::{MouseType, PresencePattern, PresenceSettings};
+use serde::Deserialize;
+use tokio::sync::watch;
+
+use crate::remap::RemapConfig;
+
+/// The optional configuration file of the server
+///
+/// Every field can be omitted, in which case its default is used
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub power: PowerConfig,
+    /// Named commands that clients can run, keyed by their name
+    pub actions: BTreeMap<String, ActionConfig>,
+    pub presence: PresenceConfig,
+    /// Can be reloaded at runtime by sending SIGHUP to the server
+    pub remap: RemapConfig,
+    /// Where the received input goes, every output gets all events
+    ///
+    /// Uses the usb gadget if empty
+    pub outputs: Vec<OutputConfig>,
+    pub return_edge: Option<ReturnEdgeConfig>
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::read_to_string(path).with_context(|| format!("Could not read {}", path.display()))?;
+        let config: Self = ron::from_str(&file).with_context(|| format!("Could not parse {}", path.display()))?;
+        for (name, action) in &config.actions {
+            // clients are not authenticated, so anyone on the network could run the command with arbitrary arguments
+            ensure!(
+                !action.allow_arguments || action.clients.is_some(),
+                "The action {:?} allows arguments, so it has to list the clients that may run it",
+                name
+            );
+        }
+        Ok(config)
+    }
+}
+
+/// Reloads the remapping profiles from `path` whenever the server receives SIGHUP
+#[cfg(unix)]
+pub async fn reload_on_hangup(path: PathBuf, remap: watch::Sender<Arc<RemapConfig>>) {
+    use tokio::signal::unix::*;
+    let mut hangup = signal(SignalKind::hangup()).expect("Could not register signal");
+    while hangup.recv().await.is_some() {
+        match Config::load(&path) {
+            Ok(config) => {
+                tracing::info!("Reloaded the remapping profiles from {}", path.display());
+                remap.send_replace(Arc::new(config.remap));
+            }
+            Err(err) => tracing::error!("Could not reload the config, keeping the current remapping: {:#}", err)
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn reload_on_hangup(_path: PathBuf, _remap: watch::Sender<Arc<RemapConfig>>) {
+    tracing::debug!("Reloading the config is only supported on unix");
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum OutputConfig {
+    /// Emulates the devices through a usb gadget
+    ConfigFs,
+    /// Prints every event to the console
+    Log,
+    /// Logs how many events of each kind arrived, once a minute
+    Metrics,
+    /// Appends every event to a file, together with the time since the server started
+    Record { path: PathBuf },
+    /// Injects the input into this machine through virtual devices, linux only
+    Uinput
+}
+
+/// Tells the client to take back the input when the pointer is pushed past an edge of the target's screen
+///
+/// The position of the pointer is estimated from the movements, so `screen_size` has to match the resolution of the target
+#[derive(Debug, Copy, Clone, Deserialize)]
+pub struct ReturnEdgeConfig {
+    pub edge: Edge,
+    pub screen_size: (MouseType, MouseType)
+}
+
+impl ReturnEdgeConfig {
+    pub fn pointer(&self) -> RemotePointer {
+        RemotePointer::new(Rect::screen(self.screen_size), self.edge)
+    }
+}
+
+/// What the server does when a client requests one of the power actions
+///
+/// Every client that can connect may request them, so they stay disabled unless the config enables them
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct PowerConfig {
+    pub shutdown: PowerCommand,
+    pub reboot: PowerCommand
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub enum PowerCommand {
+    #[default]
+    Disabled,
+    Poweroff,
+    Reboot,
+    Custom { command: String, args: Vec<String> }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ActionConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub description: String,
+    /// Seconds after which the command gets killed
+    #[serde(default = "default_action_timeout")]
+    pub timeout: u64,
+    /// Lets clients append their own arguments to `args`, requires `clients`
+    #[serde(default)]
+    pub allow_arguments: bool,
+    /// The addresses of the clients that may see and run the action, every client if omitted
+    #[serde(default)]
+    pub clients: Option<Vec<IpAddr>>
+}
+
+fn default_action_timeout() -> u64 {
+    60
+}
+
+impl ActionConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout)
+    }
+
+    pub fn permits(&self, client: IpAddr) -> bool {
+        match &self.clients {
+            // a server bound to [::] sees ipv4 clients as ipv4-mapped ipv6 addresses
+            Some(clients) => clients.contains(&client.to_canonical()),
+            None => true
+        }
+    }
+}
+
+/// Keeps the target from going idle while no input arrives
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PresenceConfig {
+    /// Whether the keeper starts enabled, clients can change this at runtime
+    pub enabled: bool,
+    pub pattern: PresencePattern,
+    /// Seconds without input after which the keeper acts
+    pub interval: u64,
+    /// Restricts the keeper to certain local times, always active if omitted
+    pub schedule: Option<Schedule>
+}
+
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            pattern: PresencePattern::Wiggle,
+            interval: 60,
+            schedule: None
+        }
+    }
+}
+
+impl PresenceConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval.max(1))
+    }
+
+    pub fn settings(&self) -> PresenceSettings {
+        PresenceSettings {
+            enabled: self.enabled,
+            pattern: self.pattern
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Schedule {
+    pub start: TimeOfDay,
+    /// An `end` before `start` describes a range that spans midnight
+    pub end: TimeOfDay,
+    #[serde(default = "every_day")]
+    pub days: Vec<Weekday>
+}
+
+fn every_day() -> Vec<Weekday> {
+    Weekday::ALL.to_vec()
+}
+
+impl Schedule {
+    pub fn contains(&self, day: Weekday, time: TimeOfDay) -> bool {
+        let in_range = match self.start <= self.end {
+            true => self.start <= time && time < self.end,
+            false => self.start <= time || time < self.end
+        };
+        in_range && self.days.contains(&day)
+    }
+}
+
+/// A local time in minutes since midnight, written as `"HH:MM"`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay(u16);
+
+impl TimeOfDay {
+    pub fn new(hour: u16, minute: u16) -> Option<Self> {
+        (hour < 24 && minute < 60).then_some(Self(hour * 60 + minute))
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        value
+            .split_once(':')
+            .and_then(|(hour, minute)| TimeOfDay::new(hour.parse().ok()?, minute.parse().ok()?))
+            .ok_or_else(|| format!("{:?} is not a valid time, expected HH:MM", value))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub enum Weekday {
+    Sunday,
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday
+}
+
+impl Weekday {
+    /// Ordered like `tm_wday`, starting with sunday
+    pub const ALL: [Weekday; 7] = [
+        Weekday::Sunday,
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(value: &str) -> TimeOfDay {
+        TimeOfDay::try_from(value.to_string()).unwrap()
+    }
+
+    fn schedule(start: &str, end: &str, days: &[Weekday]) -> Schedule {
+        Schedule {
+            start: time(start),
+            end: time(end),
+            days: days.to_vec()
+        }
+    }
+
+    #[test]
+    fn time_of_day_parses_hours_and_minutes() {
+        assert_eq!(time("00:00"), TimeOfDay::new(0, 0).unwrap());
+        assert_eq!(time("08:30"), TimeOfDay::new(8, 30).unwrap());
+        assert_eq!(time("7:05"), TimeOfDay::new(7, 5).unwrap());
+        assert_eq!(time("23:59"), TimeOfDay::new(23, 59).unwrap());
+        for invalid in ["24:00", "12:60", "12", "12:", ":30", "ab:cd", "-1:30", "", "12:30:00"] {
+            assert!(TimeOfDay::try_from(invalid.to_string()).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn schedule_within_a_day() {
+        let schedule = schedule("09:00", "17:30", &Weekday::ALL);
+        assert!(!schedule.contains(Weekday::Monday, time("08:59")));
+        assert!(schedule.contains(Weekday::Monday, time("09:00")));
+        assert!(schedule.contains(Weekday::Monday, time("17:29")));
+        assert!(!schedule.contains(Weekday::Monday, time("17:30")));
+        assert!(!schedule.contains(Weekday::Monday, time("23:00")));
+    }
+
+    #[test]
+    fn schedule_across_midnight() {
+        let schedule = schedule("22:00", "06:00", &Weekday::ALL);
+        assert!(schedule.contains(Weekday::Friday, time("22:00")));
+        assert!(schedule.contains(Weekday::Friday, time("23:59")));
+        assert!(schedule.contains(Weekday::Saturday, time("00:00")));
+        assert!(schedule.contains(Weekday::Saturday, time("05:59")));
+        assert!(!schedule.contains(Weekday::Saturday, time("06:00")));
+        assert!(!schedule.contains(Weekday::Saturday, time("12:00")));
+        assert!(!schedule.contains(Weekday::Saturday, time("21:59")));
+    }
+
+    #[test]
+    fn schedule_respects_days() {
+        let schedule = schedule("22:00", "06:00", &[Weekday::Friday]);
+        assert!(schedule.contains(Weekday::Friday, time("23:00")));
+        // the day is the one of the time that is checked, not the one on which the range started
+        assert!(!schedule.contains(Weekday::Saturday, time("01:00")));
+        assert!(!schedule.contains(Weekday::Thursday, time("23:00")));
+    }
+
+    #[test]
+    fn empty_schedule_never_matches() {
+        let schedule = schedule("10:00", "10:00", &Weekday::ALL);
+        assert!(!schedule.contains(Weekday::Monday, time("10:00")));
+        assert!(!schedule.contains(Weekday::Monday, time("11:00")));
+    }
+
+    #[test]
+    fn schedule_defaults_to_every_day() {
+        let schedule: Schedule = ron::from_str(r#"(start: "08:00", end: "18:00")"#).unwrap();
+        for day in Weekday::ALL {
+            assert!(schedule.contains(day, time("12:00")), "{:?}", day);
+        }
+        assert!(ron::from_str::<Schedule>(r#"(start: "8am", end: "18:00")"#).is_err());
+    }
+}
@@ -1,14 +1,18 @@
-use std::num::NonZeroU8;
+use std::collections::VecDeque;
 #[cfg(unix)]
 use std::os::unix;
-use std::path::Path;
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
 use std::{env, fs};
 
 use anyhow::{anyhow, Result};
-use tokio::fs::{File, OpenOptions};
-use tokio::io::AsyncWriteExt;
-use tokio::task::spawn_blocking;
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::{watch, Notify};
+use tokio::task::{spawn_blocking, JoinHandle};
+use tokio::time::{sleep, timeout, Instant};
 
 #[cfg(windows)]
 mod unix {
@@ -80,14 +84,70 @@ const MOUSE_REPORT_DESC: &[u8] = &[
     0x75, 0x10, //     Report Size (16)
     0x95, 0x02, //     Report Count (2)
     0x81, 0x06, //     Input (Data,Var,Rel,No Wrap,Linear,Preferred State,No Null Position)
-    0x09, 0x38, //     Usage (Wheel)
+    0xA1, 0x02, //     Collection (Logical)
+    0x09, 0x48, //       Usage (Resolution Multiplier)
+    0x15, 0x00, //       Logical Minimum (0)
+    0x25, 0x01, //       Logical Maximum (1)
+    0x35, 0x01, //       Physical Minimum (1)
+    0x45, 0x78, //       Physical Maximum (120)
+    0x75, 0x02, //       Report Size (2)
+    0x95, 0x01, //       Report Count (1)
+    0xB1, 0x02, //       Feature (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Non-volatile)
+    0x09, 0x38, //       Usage (Wheel)
+    0x35, 0x00, //       Physical Minimum (0)
+    0x45, 0x00, //       Physical Maximum (0)
+    0x16, 0x01, 0x80, //       Logical Minimum (-32767)
+    0x26, 0xFF, 0x7F, //       Logical Maximum (32767)
+    0x75, 0x10, //       Report Size (16)
+    0x81, 0x06, //       Input (Data,Var,Rel,No Wrap,Linear,Preferred State,No Null Position)
+    0xC0, //     End Collection
+    0xA1, 0x02, //     Collection (Logical)
+    0x09, 0x48, //       Usage (Resolution Multiplier)
+    0x15, 0x00, //       Logical Minimum (0)
+    0x25, 0x01, //       Logical Maximum (1)
+    0x35, 0x01, //       Physical Minimum (1)
+    0x45, 0x78, //       Physical Maximum (120)
+    0x75, 0x02, //       Report Size (2)
+    0xB1, 0x02, //       Feature (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Non-volatile)
+    0x35, 0x00, //       Physical Minimum (0)
+    0x45, 0x00, //       Physical Maximum (0)
+    0x75, 0x04, //       Report Size (4)
+    0xB1, 0x03, //       Feature (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position,Non-volatile)
+    0x05, 0x0C, //       Usage Page (Consumer)
+    0x0A, 0x38, 0x02, //       Usage (AC Pan)
+    0x16, 0x01, 0x80, //       Logical Minimum (-32767)
+    0x26, 0xFF, 0x7F, //       Logical Maximum (32767)
+    0x75, 0x10, //       Report Size (16)
+    0x81, 0x06, //       Input (Data,Var,Rel,No Wrap,Linear,Preferred State,No Null Position)
+    0xC0, //     End Collection
+    0xC0, //   End Collection
+    0xC0  // End Collection
+];
+
+const BOOT_MOUSE_REPORT_DESC: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop Ctrls)
+    0x09, 0x02, // Usage (Mouse)
+    0xA1, 0x01, // Collection (Application)
+    0x09, 0x01, //   Usage (Pointer)
+    0xA1, 0x00, //   Collection (Physical)
+    0x05, 0x09, //     Usage Page (Button)
+    0x19, 0x01, //     Usage Minimum (0x01)
+    0x29, 0x03, //     Usage Maximum (0x03)
+    0x15, 0x00, //     Logical Minimum (0)
+    0x25, 0x01, //     Logical Maximum (1)
+    0x95, 0x03, //     Report Count (3)
+    0x75, 0x01, //     Report Size (1)
+    0x81, 0x02, //     Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x95, 0x01, //     Report Count (1)
+    0x75, 0x05, //     Report Size (5)
+    0x81, 0x03, //     Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x05, 0x01, //     Usage Page (Generic Desktop Ctrls)
+    0x09, 0x30, //     Usage (X)
+    0x09, 0x31, //     Usage (Y)
     0x15, 0x81, //     Logical Minimum (-127)
     0x25, 0x7F, //     Logical Maximum (127)
-    0x95, 0x01, //     Report Count (1)
     0x75, 0x08, //     Report Size (8)
-    0x81, 0x06, //     Input (Data,Var,Rel,No Wrap,Linear,Preferred State,No Null Position)
-    0x05, 0x0C, //     Usage Page (Consumer)
-    0x0A, 0x38, 0x02, //     Usage (AC Pan)
+    0x95, 0x02, //     Report Count (2)
     0x81, 0x06, //     Input (Data,Var,Rel,No Wrap,Linear,Preferred State,No Null Position)
     0xC0, //   End Collection
     0xC0  // End Collection
@@ -99,31 +159,93 @@ const CONSUMER_REPORT_DESC: &[u8] = &[
     0xA1, 0x01, // Collection (Application)
     0x05, 0x0C, //   Usage Page (Consumer)
     0x15, 0x00, //   Logical Minimum (0)
+    0x27, 0xFF, 0xFF, 0x00, 0x00, //   Logical Maximum (65535)
+    0x19, 0x00, //   Usage Minimum (Unassigned)
+    0x2A, 0xFF, 0xFF, //   Usage Maximum (0xFFFF)
+    0x75, 0x10, //   Report Size (16)
+    0x95, 0x04, //   Report Count (4)
+    0x81, 0x00, //   Input (Data,Array,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0xC0  // End Collection
+];
+
+const SYSTEM_CONTROL_REPORT_DESC: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop Ctrls)
+    0x09, 0x80, // Usage (Sys Control)
+    0xA1, 0x01, // Collection (Application)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x03, //   Report Count (3)
+    0x09, 0x81, //   Usage (Sys Power Down)
+    0x09, 0x82, //   Usage (Sys Sleep)
+    0x09, 0x83, //   Usage (Sys Wake Up)
+    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x95, 0x01, //   Report Count (1)
+    0x75, 0x05, //   Report Size (5)
+    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0xC0  // End Collection
+];
+
+const GAMEPAD_REPORT_DESC: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop Ctrls)
+    0x09, 0x05, // Usage (Game Pad)
+    0xA1, 0x01, // Collection (Application)
+    0x05, 0x09, //   Usage Page (Button)
+    0x19, 0x01, //   Usage Minimum (0x01)
+    0x29, 0x10, //   Usage Maximum (0x10)
+    0x15, 0x00, //   Logical Minimum (0)
     0x25, 0x01, //   Logical Maximum (1)
     0x75, 0x01, //   Report Size (1)
     0x95, 0x10, //   Report Count (16)
-    0x09, 0xB5, //   Usage (Scan Next Track)
-    0x09, 0xB6, //   Usage (Scan Previous Track)
-    0x09, 0xB7, //   Usage (Stop)
-    0x09, 0xCD, //   Usage (Play / Pause)
-    0x09, 0xE2, //   Usage (Mute)
-    0x09, 0xE9, //   Usage (Volume Up)
-    0x09, 0xEA, //   Usage (Volume Down)
-    0x0A, 0x23, 0x02, //   Usage (WWW Home)
-    0x0A, 0x94, 0x01, //   Usage (My Computer)
-    0x0A, 0x92, 0x01, //   Usage (Calculator)
-    0x0A, 0x2A, 0x02, //   Usage (WWW fav)
-    0x0A, 0x21, 0x02, //   Usage (WWW search)
-    0x0A, 0x26, 0x02, //   Usage (WWW stop)
-    0x0A, 0x24, 0x02, //   Usage (WWW back)
-    0x0A, 0x83, 0x01, //   Usage (Media sel)
-    0x0A, 0x8A, 0x01, //   Usage (Mail)
+    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x05, 0x01, //   Usage Page (Generic Desktop Ctrls)
+    0x09, 0x39, //   Usage (Hat switch)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x07, //   Logical Maximum (7)
+    0x35, 0x00, //   Physical Minimum (0)
+    0x46, 0x3B, 0x01, //   Physical Maximum (315)
+    0x65, 0x14, //   Unit (System: English Rotation, Length: Centimeter)
+    0x75, 0x04, //   Report Size (4)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x42, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,Null State)
+    0x65, 0x00, //   Unit (None)
+    0x45, 0x00, //   Physical Maximum (0)
+    0x75, 0x04, //   Report Size (4)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x03, //   Input (Const,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x09, 0x30, //   Usage (X)
+    0x09, 0x31, //   Usage (Y)
+    0x09, 0x33, //   Usage (Rx)
+    0x09, 0x34, //   Usage (Ry)
+    0x16, 0x01, 0x80, //   Logical Minimum (-32767)
+    0x26, 0xFF, 0x7F, //   Logical Maximum (32767)
+    0x75, 0x10, //   Report Size (16)
+    0x95, 0x04, //   Report Count (4)
+    0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    0x09, 0x32, //   Usage (Z)
+    0x09, 0x35, //   Usage (Rz)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x02, //   Report Count (2)
     0x81, 0x02, //   Input (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
     0xC0  // End Collection
 ];
 
-fn enable_hid() -> Result<()> {
-    tracing::debug!("Enabling HID device");
+/// The report format used by the emulated keyboard and mouse
+///
+/// The kernel handles `SET_PROTOCOL` requests on its own without telling us, so the mode has to be picked up front.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum ProtocolMode {
+    /// Full featured report descriptors with 16-bit mouse movement and scrolling
+    #[default]
+    Report,
+    /// Boot protocol compatible interfaces that can be used by BIOS/UEFI setups, bootloaders and simple KVMs
+    Boot
+}
+
+fn enable_hid(mode: ProtocolMode) -> Result<()> {
+    tracing::debug!("Enabling HID device ({:?} mode)", mode);
 
     env::set_current_dir("/sys/kernel/config/usb_gadget/")?;
     fs::create_dir("g1")?;
@@ -145,7 +267,8 @@ fn enable_hid() -> Result<()> {
 
     fs::create_dir_all("configs/c.1/strings/0x409")?;
     fs::write("configs/c.1/strings/0x409/configuration", "Config 1: Keyboard")?;
-    fs::write("configs/c.1/bmAttributes", "0x80")?;
+    // bus powered + remote wakeup
+    fs::write("configs/c.1/bmAttributes", "0xa0")?;
     fs::write("configs/c.1/MaxPower", "250")?;
 
     fs::create_dir_all("functions/hid.usb0")?;
@@ -156,36 +279,65 @@ fn enable_hid() -> Result<()> {
     unix::fs::symlink("functions/hid.usb0", "configs/c.1/hid.usb0")?;
 
     fs::create_dir_all("functions/hid.usb1")?;
-    fs::write("functions/hid.usb1/protocol", "1")?;
-    fs::write("functions/hid.usb1/subclass", "1")?;
-    fs::write("functions/hid.usb1/report_length", "7")?;
-    fs::write("functions/hid.usb1/report_desc", MOUSE_REPORT_DESC)?;
+    fs::write("functions/hid.usb1/protocol", "2")?;
+    match mode {
+        ProtocolMode::Report => {
+            fs::write("functions/hid.usb1/subclass", "0")?;
+            fs::write("functions/hid.usb1/report_length", "9")?;
+            fs::write("functions/hid.usb1/report_desc", MOUSE_REPORT_DESC)?;
+            // lets the host set the resolution multiplier through SET_REPORT (available since linux 5.19)
+            if Path::new("functions/hid.usb1/no_out_endpoint").exists() {
+                fs::write("functions/hid.usb1/no_out_endpoint", "1")?;
+            }
+        }
+        ProtocolMode::Boot => {
+            fs::write("functions/hid.usb1/subclass", "1")?;
+            fs::write("functions/hid.usb1/report_length", "3")?;
+            fs::write("functions/hid.usb1/report_desc", BOOT_MOUSE_REPORT_DESC)?;
+        }
+    }
     unix::fs::symlink("functions/hid.usb1", "configs/c.1/hid.usb1")?;
 
     fs::create_dir_all("functions/hid.usb2")?;
-    fs::write("functions/hid.usb2/protocol", "1")?;
-    fs::write("functions/hid.usb2/subclass", "1")?;
-    fs::write("functions/hid.usb2/report_length", "2")?;
+    fs::write("functions/hid.usb2/protocol", "0")?;
+    fs::write("functions/hid.usb2/subclass", "0")?;
+    fs::write("functions/hid.usb2/report_length", "8")?;
     fs::write("functions/hid.usb2/report_desc", CONSUMER_REPORT_DESC)?;
     unix::fs::symlink("functions/hid.usb2", "configs/c.1/hid.usb2")?;
 
+    fs::create_dir_all("functions/hid.usb3")?;
+    fs::write("functions/hid.usb3/protocol", "0")?;
+    fs::write("functions/hid.usb3/subclass", "0")?;
+    fs::write("functions/hid.usb3/report_length", "1")?;
+    fs::write("functions/hid.usb3/report_desc", SYSTEM_CONTROL_REPORT_DESC)?;
+    unix::fs::symlink("functions/hid.usb3", "configs/c.1/hid.usb3")?;
+
+    fs::create_dir_all("functions/hid.usb4")?;
+    fs::write("functions/hid.usb4/protocol", "0")?;
+    fs::write("functions/hid.usb4/subclass", "0")?;
+    fs::write("functions/hid.usb4/report_length", "13")?;
+    fs::write("functions/hid.usb4/report_desc", GAMEPAD_REPORT_DESC)?;
+    unix::fs::symlink("functions/hid.usb4", "configs/c.1/hid.usb4")?;
+
     fs::write("os_desc/use", "1")?;
     fs::write("os_desc/b_vendor_code", "0xcd")?;
     fs::write("os_desc/qw_sign", "MSFT100")?;
     unix::fs::symlink("configs/c.1", "os_desc/c.1")?;
 
-    let udc_name = Path::new("/sys/class/udc/")
+    fs::write("UDC", find_udc()?)?;
+
+    Ok(())
+}
+
+fn find_udc() -> Result<String> {
+    Ok(Path::new("/sys/class/udc/")
         .read_dir()?
         .filter_map(|r| r.map(|e| e.file_name()).ok())
         .next()
         .ok_or_else(|| anyhow!("No UDC found"))?
         .to_str()
         .ok_or_else(|| anyhow!("UDC has an invalid name"))?
-        .to_string();
-
-    fs::write("UDC", udc_name)?;
-
-    Ok(())
+        .to_string())
 }
 
 fn disable_hid() -> Result<()> {
@@ -199,12 +351,16 @@ fn disable_hid() -> Result<()> {
     fs::remove_file("configs/c.1/hid.usb0")?;
     fs::remove_file("configs/c.1/hid.usb1")?;
     fs::remove_file("configs/c.1/hid.usb2")?;
+    fs::remove_file("configs/c.1/hid.usb3")?;
+    fs::remove_file("configs/c.1/hid.usb4")?;
 
     fs::remove_dir("configs/c.1/strings/0x409")?;
     fs::remove_dir("configs/c.1")?;
     fs::remove_dir("functions/hid.usb0")?;
     fs::remove_dir("functions/hid.usb1")?;
     fs::remove_dir("functions/hid.usb2")?;
+    fs::remove_dir("functions/hid.usb3")?;
+    fs::remove_dir("functions/hid.usb4")?;
     fs::remove_dir("strings/0x409")?;
 
     env::set_current_dir("..")?;
@@ -219,14 +375,14 @@ static CONFIG_FS_REF_COUNT: Mutex<u32> = Mutex::new(0);
 struct ConfigFsHandle;
 
 impl ConfigFsHandle {
-    #[allow(unreachable_code)]
-    fn new() -> Result<Self> {
+    #[allow(unreachable_code, unused_variables)]
+    fn new(mode: ProtocolMode) -> Result<Self> {
         #[cfg(windows)]
         panic!("Not supported on windows");
 
         let mut guard = CONFIG_FS_REF_COUNT.lock().expect("Could not acquire lock");
         if *guard == 0 {
-            enable_hid()?;
+            enable_hid(mode)?;
         }
         *guard += 1;
         assert_ne!(*guard, 0);
@@ -247,6 +403,148 @@ impl Drop for ConfigFsHandle {
     }
 }
 
+/// The state of the USB device controller as reported by the kernel
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum UdcState {
+    NotAttached,
+    Attached,
+    Powered,
+    Default,
+    Addressed,
+    Configured,
+    Suspended,
+    Unknown
+}
+
+impl From<&str> for UdcState {
+    fn from(value: &str) -> Self {
+        match value.trim() {
+            "not attached" => UdcState::NotAttached,
+            "attached" => UdcState::Attached,
+            "powered" => UdcState::Powered,
+            "default" => UdcState::Default,
+            "addressed" => UdcState::Addressed,
+            "configured" => UdcState::Configured,
+            "suspended" => UdcState::Suspended,
+            _ => UdcState::Unknown
+        }
+    }
+}
+
+/// A host that does not resume gets asked again after this long at the earliest
+const WAKEUP_COOLDOWN: Duration = Duration::from_secs(3);
+const UDC_STATE_INTERVAL: Duration = Duration::from_millis(250);
+const HIGH_SPEED_POLLING_INTERVAL: Duration = Duration::from_millis(1);
+const FULL_SPEED_POLLING_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone)]
+pub struct Udc {
+    path: PathBuf
+}
+
+impl Udc {
+    pub fn find() -> Result<Self> {
+        Ok(Self {
+            path: Path::new("/sys/class/udc/").join(find_udc()?)
+        })
+    }
+
+    pub async fn state(&self) -> Result<UdcState> {
+        let state = tokio::fs::read_to_string(self.path.join("state")).await?;
+        Ok(UdcState::from(state.as_str()))
+    }
+
+    /// The interval in which the host polls the interrupt endpoints of the hid functions
+    ///
+    /// f_hid uses a `bInterval` of 10 frames for full speed and 4 microframes (2^3 * 125µs) for faster connections
+    pub async fn polling_interval(&self) -> Result<Duration> {
+        let speed = tokio::fs::read_to_string(self.path.join("current_speed")).await?;
+        Ok(match speed.trim() {
+            "high-speed" | "super-speed" | "super-speed-plus" => HIGH_SPEED_POLLING_INTERVAL,
+            _ => FULL_SPEED_POLLING_INTERVAL
+        })
+    }
+
+    /// Sends the remote wakeup signal without waiting for the host to resume
+    pub async fn request_wakeup(&self) -> Result<()> {
+        tokio::fs::write(self.path.join("srp"), "1").await?;
+        Ok(())
+    }
+}
+
+/// Follows the state of the udc on a background task, so that the event path never has to read it
+#[derive(Debug)]
+pub struct UdcMonitor {
+    udc: Udc,
+    state: watch::Receiver<UdcState>,
+    last_wakeup: Option<Instant>,
+    task: JoinHandle<()>
+}
+
+impl UdcMonitor {
+    pub async fn start(udc: Udc) -> Result<Self> {
+        let (sender, state) = watch::channel(udc.state().await?);
+        let task = tokio::spawn({
+            let udc = udc.clone();
+            async move {
+                loop {
+                    sleep(UDC_STATE_INTERVAL).await;
+                    match udc.state().await {
+                        Ok(state) => {
+                            sender.send_if_modified(|current| std::mem::replace(current, state) != state);
+                        }
+                        Err(err) => tracing::warn!("Could not read the state of the udc: {}", err)
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            udc,
+            state,
+            last_wakeup: None,
+            task
+        })
+    }
+
+    pub fn udc(&self) -> &Udc {
+        &self.udc
+    }
+
+    /// The last known state
+    pub fn state(&self) -> UdcState {
+        *self.state.borrow()
+    }
+
+    /// Waits for the next state change
+    pub async fn changed(&mut self) -> UdcState {
+        match self.state.changed().await {
+            Ok(()) => *self.state.borrow_and_update(),
+            Err(_) => std::future::pending().await
+        }
+    }
+
+    /// Asks a suspended host to resume, at most once per `WAKEUP_COOLDOWN`
+    ///
+    /// Does nothing if the host is not suspended
+    pub async fn wake_up_host(&mut self) -> Result<()> {
+        let cooling_down = self
+            .last_wakeup
+            .is_some_and(|last| last.elapsed() < WAKEUP_COOLDOWN);
+        if self.state() != UdcState::Suspended || cooling_down {
+            return Ok(());
+        }
+        tracing::debug!("Host is suspended, sending remote wakeup signal");
+        self.last_wakeup = Some(Instant::now());
+        self.udc.request_wakeup().await
+    }
+}
+
+impl Drop for UdcMonitor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
 pub async fn asyncify<F, T>(f: F) -> Result<T>
 where
     F: FnOnce() -> Result<T> + Send + 'static,
@@ -258,101 +556,389 @@ where
     }
 }
 
+/// Shared by all devices to report which of them the host stopped reading from
+pub type StatusSender = Arc<watch::Sender<DeviceStatus>>;
+
+const WRITE_TIMEOUT: Duration = Duration::from_millis(250);
+const WRITE_QUEUE_CAPACITY: usize = 32;
+
+#[derive(Debug, Default)]
+struct WriterState {
+    reports: VecDeque<Vec<u8>>,
+    stalled: bool,
+    error: Option<std::io::Error>
+}
+
+#[derive(Debug, Default)]
+struct WriterShared {
+    state: Mutex<WriterState>,
+    notify: Notify
+}
+
+impl WriterShared {
+    fn lock(&self) -> MutexGuard<'_, WriterState> {
+        self.state.lock().expect("Could not acquire lock")
+    }
+}
+
+/// Folds a report into the last queued report and returns `false` if both have to be written
+type MergeFn = fn(&mut [u8], &[u8]) -> bool;
+
+/// Reports that carry the whole state of a function only have to be written when the state changes
+fn skip_repeated(last: &mut [u8], report: &[u8]) -> bool {
+    last == report
+}
+
+impl WriterState {
+    fn push(&mut self, report: &[u8], merge: MergeFn) {
+        if self.stalled {
+            if let Some(last) = self.reports.back_mut() {
+                if merge(last, report) {
+                    return;
+                }
+            }
+        }
+        if self.reports.len() >= WRITE_QUEUE_CAPACITY {
+            self.reports.pop_front();
+        }
+        self.reports.push_back(report.to_vec());
+    }
+}
+
+/// Writes the reports of a single hid function on its own task
+///
+/// `write_all` blocks for as long as the host does not read the endpoint, for example because the interface got suspended.
+/// With a task per function a stuck function does not hold up the others.
+/// After a write timed out the function counts as stalled and new reports get merged into the queue until the host reads again,
+/// so that presses and releases survive while repeated states and separate movements do not pile up.
+/// A host that reads too slowly only loses the oldest reports, so the newest state always reaches it.
+///
+/// Keeps the gadget alive until the function is closed.
 #[derive(Debug)]
-pub struct Keyboard {
-    _handle: ConfigFsHandle,
-    device: File,
-    pressed_keys: Vec<HidKeyCode>,
-    pressed_modifiers: HidModifierKeys
+pub struct ReportWriter {
+    shared: Arc<WriterShared>,
+    task: JoinHandle<()>,
+    _handle: Option<ConfigFsHandle>
 }
 
-impl Keyboard {
-    pub async fn new() -> Result<Self> {
-        let _handle = asyncify(ConfigFsHandle::new).await?;
+impl ReportWriter {
+    async fn open(mode: ProtocolMode, path: &'static str, flag: DeviceStatus, status: StatusSender) -> Result<Self> {
+        let handle = asyncify(move || ConfigFsHandle::new(mode)).await?;
         let device = OpenOptions::new()
             .write(true)
             .append(true)
-            .open("/dev/hidg0")
+            .open(path)
             .await?;
-        Ok(Self {
-            _handle,
-            device,
+        Ok(Self::new(device, Some(handle), flag, status))
+    }
+
+    /// Writes every report to a channel instead of a device file
+    #[cfg(test)]
+    pub fn in_memory(flag: DeviceStatus, status: StatusSender) -> (Self, tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>) {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        (Self::new(MemoryDevice(sender), None, flag, status), receiver)
+    }
+
+    fn new<W>(device: W, handle: Option<ConfigFsHandle>, flag: DeviceStatus, status: StatusSender) -> Self
+    where
+        W: AsyncWrite + Unpin + Send + 'static
+    {
+        let shared = Arc::new(WriterShared::default());
+        let task = tokio::spawn(Self::run(device, shared.clone(), flag, status));
+        Self {
+            shared,
+            task,
+            _handle: handle
+        }
+    }
+
+    /// Queues a report that carries the whole state of the function
+    ///
+    /// Returns the error of a previous write if the device failed
+    fn write(&self, report: &[u8]) -> Result<()> {
+        self.write_with(report, skip_repeated)
+    }
+
+    /// Queues a report and uses `merge` to fold it into the queue while the function is stalled
+    fn write_with(&self, report: &[u8], merge: MergeFn) -> Result<()> {
+        let mut state = self.shared.lock();
+        if let Some(err) = state.error.take() {
+            return Err(err.into());
+        }
+        state.push(report, merge);
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+
+    async fn run<W: AsyncWrite + Unpin>(mut device: W, shared: Arc<WriterShared>, flag: DeviceStatus, status: StatusSender) {
+        loop {
+            let notified = shared.notify.notified();
+            let report = shared.lock().reports.pop_front();
+            let Some(report) = report else {
+                notified.await;
+                continue;
+            };
+            let result = timeout(WRITE_TIMEOUT, async {
+                device.write_all(&report).await?;
+                device.flush().await
+            })
+            .await;
+            let stalled = match result {
+                Ok(Ok(())) => false,
+                Ok(Err(err)) => {
+                    shared.lock().error = Some(err);
+                    break;
+                }
+                Err(_) => true
+            };
+            let mut state = shared.lock();
+            if state.stalled != stalled {
+                match stalled {
+                    true => tracing::warn!("The host stopped reading {:?} reports", flag),
+                    false => tracing::info!("The host is reading {:?} reports again", flag)
+                }
+                state.stalled = stalled;
+                status.send_modify(|status| status.set(flag, stalled));
+            }
+        }
+    }
+}
+
+impl Drop for ReportWriter {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Hands every write to the test as one report
+#[cfg(test)]
+struct MemoryDevice(tokio::sync::mpsc::UnboundedSender<Vec<u8>>);
+
+#[cfg(test)]
+impl AsyncWrite for MemoryDevice {
+    fn poll_write(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>, buf: &[u8]) -> std::task::Poll<std::io::Result<usize>> {
+        let result = match self.0.send(buf.to_vec()) {
+            Ok(()) => Ok(buf.len()),
+            Err(_) => Err(std::io::ErrorKind::BrokenPipe.into())
+        };
+        std::task::Poll::Ready(result)
+    }
+
+    fn poll_flush(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+/// The keys that are held down on the keyboard
+#[derive(Debug, Clone)]
+pub struct KeyboardState {
+    pressed_keys: Vec<HidKeyCode>,
+    pressed_modifiers: HidModifierKeys
+}
+
+impl Default for KeyboardState {
+    fn default() -> Self {
+        Self {
             pressed_keys: Vec::new(),
             pressed_modifiers: HidModifierKeys::empty()
-        })
+        }
     }
+}
 
-    async fn send_report(&mut self) -> Result<()> {
+impl KeyboardState {
+    pub fn reset(&mut self) {
+        self.pressed_keys.clear();
+        self.pressed_modifiers = HidModifierKeys::empty();
+    }
+
+    pub fn press(&mut self, key: HidKeyCode) {
+        match key.try_into() {
+            Ok(modifier) => self.pressed_modifiers.insert(modifier),
+            Err(_) => self.pressed_keys.push(key)
+        }
+    }
+
+    pub fn release(&mut self, key: HidKeyCode) {
+        match key.try_into() {
+            Ok(modifier) => self.pressed_modifiers.remove(modifier),
+            Err(_) => self.pressed_keys.retain(|k| *k != key)
+        }
+    }
+
+    pub fn report(&self) -> [u8; 8] {
         let mut report = [0u8; 8];
         report[0] = self.pressed_modifiers.bits();
 
         for (i, key) in self.pressed_keys.iter().enumerate().take(6) {
             report[2 + i] = (*key).into()
         }
+        report
+    }
+}
+
+#[derive(Debug)]
+pub struct Keyboard {
+    writer: ReportWriter,
+    state: KeyboardState
+}
+
+impl Keyboard {
+    pub async fn new(mode: ProtocolMode, status: StatusSender) -> Result<Self> {
+        let writer = ReportWriter::open(mode, "/dev/hidg0", DeviceStatus::KEYBOARD_STALLED, status).await?;
+        Ok(Self::with_writer(writer))
+    }
+
+    pub fn with_writer(writer: ReportWriter) -> Self {
+        Self {
+            writer,
+            state: KeyboardState::default()
+        }
+    }
+
+    async fn send_report(&mut self) -> Result<()> {
+        let report = self.state.report();
         tracing::trace!("Wring keyboard report: {:?}", &report);
-        self.device.write_all(&report).await?;
+        self.writer.write(&report)?;
         Ok(())
     }
 
     pub async fn reset(&mut self) -> Result<()> {
-        self.pressed_keys.clear();
-        self.pressed_modifiers = HidModifierKeys::empty();
+        self.state.reset();
         self.send_report().await
     }
 
     pub async fn press_key(&mut self, key: HidKeyCode) -> Result<()> {
-        match key.try_into() {
-            Ok(modifier) => self.pressed_modifiers.insert(modifier),
-            Err(_) => self.pressed_keys.push(key)
-        }
+        self.state.press(key);
         self.send_report().await
     }
 
     pub async fn release_key(&mut self, key: HidKeyCode) -> Result<()> {
-        match key.try_into() {
-            Ok(modifier) => self.pressed_modifiers.remove(modifier),
-            Err(_) => self.pressed_keys.retain(|k| *k != key)
-        }
+        self.state.release(key);
         self.send_report().await
     }
 }
 
+/// The usages that are held down on the consumer device
+#[derive(Debug, Default, Clone)]
+pub struct ConsumerDeviceState {
+    pressed_keys: Vec<u16>
+}
+
+impl ConsumerDeviceState {
+    pub fn reset(&mut self) {
+        self.pressed_keys.clear();
+    }
+
+    /// Returns `false` if the key was already pressed
+    pub fn press(&mut self, key: ConsumerDeviceCode) -> bool {
+        let usage = u16::from(key);
+        if usage == 0 || self.pressed_keys.contains(&usage) {
+            return false;
+        }
+        self.pressed_keys.push(usage);
+        true
+    }
+
+    /// Returns `false` if the key was not pressed
+    pub fn release(&mut self, key: ConsumerDeviceCode) -> bool {
+        let usage = u16::from(key);
+        if !self.pressed_keys.contains(&usage) {
+            return false;
+        }
+        self.pressed_keys.retain(|k| *k != usage);
+        true
+    }
+
+    pub fn report(&self) -> [u8; 8] {
+        let mut report = [0u8; 8];
+        for (i, key) in self.pressed_keys.iter().enumerate().take(4) {
+            report[(2 * i)..(2 * i + 2)].copy_from_slice(&key.to_le_bytes());
+        }
+        report
+    }
+}
+
 #[derive(Debug)]
 pub struct ConsumerDevice {
-    _handle: ConfigFsHandle,
-    device: File,
-    pressed_keys: ConsumerDeviceButtons
+    writer: ReportWriter,
+    state: ConsumerDeviceState
 }
 
 impl ConsumerDevice {
-    pub async fn new() -> Result<Self> {
-        let _handle = asyncify(ConfigFsHandle::new).await?;
-        let device = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open("/dev/hidg2")
-            .await?;
-        Ok(Self {
-            _handle,
-            device,
-            pressed_keys: ConsumerDeviceButtons::empty()
-        })
+    pub async fn new(mode: ProtocolMode, status: StatusSender) -> Result<Self> {
+        let writer = ReportWriter::open(mode, "/dev/hidg2", DeviceStatus::CONSUMER_DEVICE_STALLED, status).await?;
+        Ok(Self::with_writer(writer))
+    }
+
+    pub fn with_writer(writer: ReportWriter) -> Self {
+        Self {
+            writer,
+            state: ConsumerDeviceState::default()
+        }
     }
 
     async fn send_report(&mut self) -> Result<()> {
-        tracing::trace!("Wring consumer device report: {:?}", &self.pressed_keys.bits().to_le_bytes());
-        self.device
-            .write_all(&self.pressed_keys.bits().to_le_bytes())
-            .await?;
+        let report = self.state.report();
+        tracing::trace!("Wring consumer device report: {:?}", &report);
+        self.writer.write(&report)?;
         Ok(())
     }
 
     pub async fn reset(&mut self) -> Result<()> {
-        self.pressed_keys = ConsumerDeviceButtons::empty();
+        self.state.reset();
         self.send_report().await
     }
 
     pub async fn press_key(&mut self, key: ConsumerDeviceCode) -> Result<()> {
+        match self.state.press(key) {
+            true => self.send_report().await,
+            false => Ok(())
+        }
+    }
+
+    pub async fn release_key(&mut self, key: ConsumerDeviceCode) -> Result<()> {
+        match self.state.release(key) {
+            true => self.send_report().await,
+            false => Ok(())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SystemControl {
+    writer: ReportWriter,
+    pressed_keys: SystemControlButtons
+}
+
+impl SystemControl {
+    pub async fn new(mode: ProtocolMode, status: StatusSender) -> Result<Self> {
+        let writer = ReportWriter::open(mode, "/dev/hidg3", DeviceStatus::SYSTEM_CONTROL_STALLED, status).await?;
+        Ok(Self::with_writer(writer))
+    }
+
+    pub fn with_writer(writer: ReportWriter) -> Self {
+        Self {
+            writer,
+            pressed_keys: SystemControlButtons::empty()
+        }
+    }
+
+    async fn send_report(&mut self) -> Result<()> {
+        tracing::trace!("Wring system control report: {:?}", &self.pressed_keys.bits());
+        self.writer.write(&[self.pressed_keys.bits()])?;
+        Ok(())
+    }
+
+    pub async fn reset(&mut self) -> Result<()> {
+        self.pressed_keys = SystemControlButtons::empty();
+        self.send_report().await
+    }
+
+    pub async fn press_key(&mut self, key: SystemControlCode) -> Result<()> {
         match key.try_into() {
             Ok(key) => {
                 self.pressed_keys.insert(key);
@@ -362,7 +948,7 @@ impl ConsumerDevice {
         }
     }
 
-    pub async fn release_key(&mut self, key: ConsumerDeviceCode) -> Result<()> {
+    pub async fn release_key(&mut self, key: SystemControlCode) -> Result<()> {
         match key.try_into() {
             Ok(key) => {
                 self.pressed_keys.remove(key);
@@ -373,52 +959,99 @@ impl ConsumerDevice {
     }
 }
 
+pub fn gamepad_report(state: &GamepadState) -> [u8; 13] {
+    let mut report = [0u8; 13];
+    report[0..=1].copy_from_slice(&state.buttons.to_le_bytes());
+    report[2] = u8::from(state.hat);
+    report[3..=4].copy_from_slice(&state.left_stick.x.max(-i16::MAX).to_le_bytes());
+    report[5..=6].copy_from_slice(&state.left_stick.y.max(-i16::MAX).to_le_bytes());
+    report[7..=8].copy_from_slice(&state.right_stick.x.max(-i16::MAX).to_le_bytes());
+    report[9..=10].copy_from_slice(&state.right_stick.y.max(-i16::MAX).to_le_bytes());
+    report[11] = state.left_trigger;
+    report[12] = state.right_trigger;
+    report
+}
+
+#[derive(Debug)]
+pub struct Gamepad {
+    writer: ReportWriter
+}
+
+impl Gamepad {
+    pub async fn new(mode: ProtocolMode, status: StatusSender) -> Result<Self> {
+        let writer = ReportWriter::open(mode, "/dev/hidg4", DeviceStatus::GAMEPAD_STALLED, status).await?;
+        Ok(Self::with_writer(writer))
+    }
+
+    pub fn with_writer(writer: ReportWriter) -> Self {
+        Self { writer }
+    }
+
+    pub async fn send_state(&mut self, state: &GamepadState) -> Result<()> {
+        let report = gamepad_report(state);
+        tracing::trace!("Wring gamepad report: {:?}", &report);
+        self.writer.write(&report)?;
+        Ok(())
+    }
+
+    pub async fn reset(&mut self) -> Result<()> {
+        self.send_state(&GamepadState::default()).await
+    }
+}
+
 #[derive(Debug)]
 pub struct Mouse {
-    _handle: ConfigFsHandle,
-    device: File,
+    writer: ReportWriter,
     pressed_buttons: HidMouseButtons,
-    tess_factor: i16
+    mode: ProtocolMode,
+    pacer: MovementPacer,
+    multipliers: Arc<AtomicU8>,
+    multiplier_listener: Option<JoinHandle<()>>,
+    scroll_remainder: Vec2<i16>
 }
 
 impl Mouse {
-    pub async fn new(tess_factor: NonZeroU8) -> Result<Self> {
-        let _handle = asyncify(ConfigFsHandle::new).await?;
-        let device = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open("/dev/hidg1")
-            .await?;
-        Ok(Self {
-            _handle,
-            device,
-            pressed_buttons: HidMouseButtons::empty(),
-            tess_factor: i16::from(tess_factor.get())
-        })
+    pub async fn new(mode: ProtocolMode, status: StatusSender) -> Result<Self> {
+        let writer = ReportWriter::open(mode, "/dev/hidg1", DeviceStatus::MOUSE_STALLED, status).await?;
+        let mut mouse = Self::with_writer(mode, writer);
+        if mode == ProtocolMode::Report {
+            mouse.multiplier_listener = Some(tokio::spawn(listen_for_multipliers(mouse.multipliers.clone())));
+        }
+        Ok(mouse)
     }
 
-    async fn send_report(&mut self, dx: i16, dy: i16, dv: i8, dh: i8) -> Result<()> {
-        let mut report = [0u8; 7];
-        report[0] = self.pressed_buttons.bits();
-
-        report[1..=2].copy_from_slice(&dx.to_le_bytes());
-        report[3..=4].copy_from_slice(&dy.to_le_bytes());
-        report[5..=5].copy_from_slice(&dv.to_le_bytes());
-        report[6..=6].copy_from_slice(&dh.to_le_bytes());
+    /// Creates a mouse that only scrolls in whole detents, as it does not learn about the resolution multipliers
+    pub fn with_writer(mode: ProtocolMode, writer: ReportWriter) -> Self {
+        Self {
+            writer,
+            pressed_buttons: HidMouseButtons::empty(),
+            mode,
+            pacer: MovementPacer::new(mode),
+            multipliers: Arc::new(AtomicU8::new(0)),
+            multiplier_listener: None,
+            scroll_remainder: Vec2::new(0, 0)
+        }
+    }
 
-        tracing::trace!("Wring mouse report: {:?}", &report);
-        self.device.write_all(&report).await?;
+    async fn send_report(&mut self, dx: i16, dy: i16, dv: i16, dh: i16) -> Result<()> {
+        let mut buffer = [0u8; 9];
+        let report = mouse_report(&mut buffer, self.mode, self.pressed_buttons, dx, dy, dv, dh);
+        tracing::trace!("Wring mouse report: {:?}", report);
+        self.writer.write_with(report, merge_mouse_reports)?;
         Ok(())
     }
 
     pub async fn reset(&mut self) -> Result<()> {
         self.pressed_buttons = HidMouseButtons::empty();
+        self.scroll_remainder = Vec2::new(0, 0);
+        self.pacer.reset();
         self.send_report(0, 0, 0, 0).await
     }
 
     pub async fn press_button(&mut self, button: HidButtonCode) -> Result<()> {
         match button.try_into() {
             Ok(button) => {
+                self.flush_movement().await?;
                 self.pressed_buttons.insert(button);
                 self.send_report(0, 0, 0, 0).await
             }
@@ -429,6 +1062,7 @@ impl Mouse {
     pub async fn release_button(&mut self, button: HidButtonCode) -> Result<()> {
         match button.try_into() {
             Ok(button) => {
+                self.flush_movement().await?;
                 self.pressed_buttons.remove(button);
                 self.send_report(0, 0, 0, 0).await
             }
@@ -436,38 +1070,311 @@ impl Mouse {
         }
     }
 
-    pub async fn move_by(&mut self, mut dx: i16, mut dy: i16) -> Result<()> {
-        let sx = abs_max(dx / self.tess_factor, dx.signum());
-        let sy = abs_max(dy / self.tess_factor, dy.signum());
-        while dx != 0 || dy != 0 {
-            let tx = abs_min(dx, sx);
-            let ty = abs_min(dy, sy);
-            self.send_report(tx, ty, 0, 0).await?;
-            dx -= tx;
-            dy -= ty;
+    /// Falls back to whole detents until the host enables the resolution multipliers again
+    ///
+    /// A host only sets them while configuring the device, so they have to be forgotten once it is no longer configured.
+    pub fn reset_multipliers(&self) {
+        if self.multipliers.swap(0, Ordering::Relaxed) != 0 {
+            tracing::debug!("Resetting the resolution multipliers");
+        }
+    }
+
+    pub fn set_polling_interval(&mut self, interval: Duration) {
+        if self.pacer.polling_interval != interval {
+            tracing::debug!("Pacing mouse reports every {:?}", interval);
+            self.pacer.polling_interval = interval;
+        }
+    }
+
+    /// Returns `true` while a movement is still being spread over multiple reports
+    pub fn is_moving(&self) -> bool {
+        self.pacer.is_moving()
+    }
+
+    /// The point in time at which the next step of the pending movement should be sent
+    pub fn next_step(&self) -> Instant {
+        self.pacer.next_step
+    }
+
+    /// Spreads a movement over multiple reports that are sent once per polling interval
+    pub async fn move_by(&mut self, dx: i64, dy: i64) -> Result<()> {
+        match self.pacer.move_by(Instant::now(), dx, dy) {
+            true => self.step().await,
+            false => Ok(())
+        }
+    }
+
+    /// Sends the next report of the pending movement
+    pub async fn step(&mut self) -> Result<()> {
+        match self.pacer.step(Instant::now()) {
+            Some(step) => self.send_report(step.x, step.y, 0, 0).await,
+            None => Ok(())
+        }
+    }
+
+    /// Sends the rest of the pending movement right away
+    ///
+    /// Clicks and scrolls have to reach the host after the movement that came before them.
+    async fn flush_movement(&mut self) -> Result<()> {
+        while let Some(step) = self.pacer.flush(Instant::now()) {
+            self.send_report(step.x, step.y, 0, 0).await?;
         }
         Ok(())
     }
 
-    pub async fn scroll_vertical(&mut self, amount: i8) -> Result<()> {
-        self.send_report(0, 0, amount, 0).await
+    /// Scrolls by `amount / SCROLL_RESOLUTION` detents
+    ///
+    /// Sends high resolution scroll events if the host enabled the resolution multiplier of the wheel
+    /// and whole detents otherwise.
+    pub async fn scroll_vertical(&mut self, amount: i16) -> Result<()> {
+        if self.mode == ProtocolMode::Boot {
+            tracing::trace!("Ignoring scroll event in boot mode");
+            return Ok(());
+        }
+        let high_res = self.multipliers.load(Ordering::Relaxed) & WHEEL_MULTIPLIER != 0;
+        let amount = accumulate_detents(&mut self.scroll_remainder.y, amount, high_res);
+        match amount {
+            0 => Ok(()),
+            amount => {
+                self.flush_movement().await?;
+                self.send_report(0, 0, amount, 0).await
+            }
+        }
+    }
+
+    /// Scrolls by `amount / SCROLL_RESOLUTION` detents
+    ///
+    /// Sends high resolution scroll events if the host enabled the resolution multiplier of the pan axis
+    /// and whole detents otherwise.
+    pub async fn scroll_horizontal(&mut self, amount: i16) -> Result<()> {
+        if self.mode == ProtocolMode::Boot {
+            tracing::trace!("Ignoring scroll event in boot mode");
+            return Ok(());
+        }
+        let high_res = self.multipliers.load(Ordering::Relaxed) & PAN_MULTIPLIER != 0;
+        let amount = accumulate_detents(&mut self.scroll_remainder.x, amount, high_res);
+        match amount {
+            0 => Ok(()),
+            amount => {
+                self.flush_movement().await?;
+                self.send_report(0, 0, 0, amount).await
+            }
+        }
+    }
+}
+
+impl Drop for Mouse {
+    fn drop(&mut self) {
+        if let Some(listener) = self.multiplier_listener.take() {
+            listener.abort();
+        }
+    }
+}
+
+/// Writes the report into `buffer` and returns the part of it that belongs to `mode`
+///
+/// Boot protocol reports only have room for three buttons and small movements
+pub fn mouse_report(buffer: &mut [u8; 9], mode: ProtocolMode, buttons: HidMouseButtons, dx: i16, dy: i16, dv: i16, dh: i16) -> &[u8] {
+    match mode {
+        ProtocolMode::Report => {
+            buffer[0] = buttons.bits();
+            buffer[1..=2].copy_from_slice(&dx.to_le_bytes());
+            buffer[3..=4].copy_from_slice(&dy.to_le_bytes());
+            buffer[5..=6].copy_from_slice(&dv.to_le_bytes());
+            buffer[7..=8].copy_from_slice(&dh.to_le_bytes());
+            &buffer[..]
+        }
+        ProtocolMode::Boot => {
+            buffer[0] = (buttons & HidMouseButtons::BOOT_BUTTONS).bits();
+            buffer[1] = dx.clamp(-127, 127) as i8 as u8;
+            buffer[2] = dy.clamp(-127, 127) as i8 as u8;
+            &buffer[..3]
+        }
+    }
+}
+
+/// Adds the movement and scrolling of `report` to `last` as long as the buttons are the same and the sums fit
+fn merge_mouse_reports(last: &mut [u8], report: &[u8]) -> bool {
+    if last.len() != report.len() || last.first() != report.first() {
+        return false;
+    }
+    let mut merged = [0u8; 9];
+    merged[0] = report[0];
+    match report.len() {
+        9 => {
+            for i in (1..9).step_by(2) {
+                let a = i16::from_le_bytes([last[i], last[i + 1]]);
+                let b = i16::from_le_bytes([report[i], report[i + 1]]);
+                match a.checked_add(b) {
+                    Some(sum) => merged[i..=i + 1].copy_from_slice(&sum.to_le_bytes()),
+                    None => return false
+                }
+            }
+        }
+        3 => {
+            for i in 1..3 {
+                match (last[i] as i8).checked_add(report[i] as i8) {
+                    Some(sum) if sum != i8::MIN => merged[i] = sum as u8,
+                    _ => return false
+                }
+            }
+        }
+        _ => return false
+    }
+    last.copy_from_slice(&merged[..report.len()]);
+    true
+}
+
+const DEFAULT_PACKET_INTERVAL: Duration = Duration::from_millis(10);
+const MAX_PACKET_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Decides how a movement gets split into reports and when they are due, independent of the device
+#[derive(Debug, Clone)]
+struct MovementPacer {
+    limit: i32,
+    polling_interval: Duration,
+    packet_interval: Duration,
+    last_move: Option<Instant>,
+    next_step: Instant,
+    pending_movement: Vec2<i32>,
+    pending_steps: i32
+}
+
+impl MovementPacer {
+    fn new(mode: ProtocolMode) -> Self {
+        Self {
+            limit: match mode {
+                ProtocolMode::Report => i16::MAX as i32,
+                ProtocolMode::Boot => i8::MAX as i32
+            },
+            polling_interval: FULL_SPEED_POLLING_INTERVAL,
+            packet_interval: DEFAULT_PACKET_INTERVAL,
+            last_move: None,
+            next_step: Instant::now(),
+            pending_movement: Vec2::new(0, 0),
+            pending_steps: 0
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pending_movement = Vec2::new(0, 0);
+        self.pending_steps = 0;
+        self.last_move = None;
     }
 
-    pub async fn scroll_horizontal(&mut self, amount: i8) -> Result<()> {
-        self.send_report(0, 0, 0, amount).await
+    fn is_moving(&self) -> bool {
+        self.pending_movement != Vec2::new(0, 0)
+    }
+
+    /// Adds a movement and returns `true` if its first step is due right away
+    ///
+    /// The number of steps depends on the size of the movement and the rate at which new movements arrive.
+    /// Movements that are still pending get merged into the new movement instead of delaying it.
+    fn move_by(&mut self, now: Instant, dx: i64, dy: i64) -> bool {
+        if let Some(elapsed) = self.last_move.map(|last| now - last) {
+            if elapsed < MAX_PACKET_INTERVAL {
+                self.packet_interval = (self.packet_interval * 3 + elapsed) / 4;
+            }
+        }
+        self.last_move = Some(now);
+
+        let was_moving = self.is_moving();
+        self.pending_movement.x = (i64::from(self.pending_movement.x) + dx).clamp(i32::MIN.into(), i32::MAX.into()) as i32;
+        self.pending_movement.y = (i64::from(self.pending_movement.y) + dy).clamp(i32::MIN.into(), i32::MAX.into()) as i32;
+        let distance = self
+            .pending_movement
+            .x
+            .unsigned_abs()
+            .max(self.pending_movement.y.unsigned_abs());
+        let available = self.packet_interval.as_micros() / self.polling_interval.as_micros().max(1);
+        self.pending_steps = (available.min(u128::from(distance)) as i32).max(1);
+        !was_moving
+    }
+
+    /// Takes the next step of the pending movement
+    fn step(&mut self, now: Instant) -> Option<Vec2<i16>> {
+        if !self.is_moving() {
+            return None;
+        }
+        let steps = self.pending_steps.max(1);
+        let sx = (self.pending_movement.x / steps).clamp(-self.limit, self.limit);
+        let sy = (self.pending_movement.y / steps).clamp(-self.limit, self.limit);
+        self.pending_movement.x -= sx;
+        self.pending_movement.y -= sy;
+        self.pending_steps = (steps - 1).max(1);
+        self.next_step = self.next_step.max(now) + self.polling_interval;
+        Some(Vec2::new(sx as i16, sy as i16))
+    }
+
+    /// Takes the rest of the pending movement in as few steps as a report allows
+    fn flush(&mut self, now: Instant) -> Option<Vec2<i16>> {
+        self.pending_steps = 1;
+        self.step(now)
     }
 }
 
-fn abs_max(a: i16, b: i16) -> i16 {
-    if a.abs() >= b.abs() { a } else { b }
+const WHEEL_MULTIPLIER: u8 = 0b0011;
+const PAN_MULTIPLIER: u8 = 0b1100;
+
+/// Returns the amount that should be reported to the host
+///
+/// Without the resolution multiplier only whole detents can be reported, so the rest is kept in `remainder`
+fn accumulate_detents(remainder: &mut i16, amount: i16, high_res: bool) -> i16 {
+    if high_res {
+        *remainder = 0;
+        return amount;
+    }
+    let total = i32::from(*remainder) + i32::from(amount);
+    let detents = total / i32::from(SCROLL_RESOLUTION);
+    *remainder = (total % i32::from(SCROLL_RESOLUTION)) as i16;
+    detents as i16
 }
 
-fn abs_min(a: i16, b: i16) -> i16 {
-    if a.abs() <= b.abs() { a } else { b }
+/// Keeps track of the resolution multiplier feature report
+///
+/// The host sets the report with SET_REPORT requests, which f_hid forwards to the device file
+/// when the function was created without an out endpoint.
+#[cfg(unix)]
+async fn listen_for_multipliers(multipliers: Arc<AtomicU8>) {
+    use std::io::Read;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    use tokio::io::unix::AsyncFd;
+    use tokio::io::Interest;
+
+    let result: Result<()> = async {
+        let device = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/hidg1")?;
+        let device = AsyncFd::with_interest(device, Interest::READABLE)?;
+        let mut buffer = [0u8; 64];
+        loop {
+            let mut guard = device.readable().await?;
+            match guard.try_io(|device| device.get_ref().read(&mut buffer)) {
+                Err(_would_block) => continue,
+                // an empty read does not clear the readiness, so it has to be done by hand to not spin
+                Ok(Ok(0)) => guard.clear_ready(),
+                Ok(Ok(_)) => {
+                    tracing::debug!("Host changed the resolution multipliers to {:#06b}", buffer[0]);
+                    multipliers.store(buffer[0], Ordering::Relaxed);
+                }
+                Ok(Err(err)) => return Err(err.into())
+            }
+        }
+    }
+    .await;
+    if let Err(err) = result {
+        tracing::warn!("Stopped listening for resolution multiplier changes: {}", err);
+    }
 }
 
-pub use flags::{ConsumerDeviceButtons, HidModifierKeys, HidMouseButtons};
-use inputshare_common::{ConsumerDeviceCode, HidButtonCode, HidKeyCode};
+#[cfg(not(unix))]
+async fn listen_for_multipliers(_: Arc<AtomicU8>) {}
+
+
+pub use flags::{HidModifierKeys, HidMouseButtons, SystemControlButtons};
+use inputshare_common::{ConsumerDeviceCode, DeviceStatus, GamepadState, HidButtonCode, HidKeyCode, SystemControlCode, Vec2, SCROLL_RESOLUTION};
 
 //#[allow(non_upper_case_globals)]
 pub mod flags {
@@ -492,52 +1399,28 @@ pub mod flags {
             const MButton = 0x04;
             const Button4 = 0x08;
             const Button5 = 0x10;
+
+            const BOOT_BUTTONS = Self::LButton.bits() | Self::RButton.bits() | Self::MButton.bits();
         }
 
         #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-        pub struct ConsumerDeviceButtons: u16 {
-            const NextTrack        = 0x0001;
-            const PreviousTrack    = 0x0002;
-            const Stop             = 0x0004;
-            const PlayPause        = 0x0008;
-            const Mute             = 0x0010;
-            const VolumeUp         = 0x0020;
-            const VolumeDown       = 0x0040;
-            const BrowserHome      = 0x0080;
-            const MyComputer       = 0x0100;
-            const Calculator       = 0x0200;
-            const BrowserFavorites = 0x0400;
-            const BrowserSearch    = 0x0800;
-            const BrowserStop      = 0x1000;
-            const BrowserBack      = 0x2000;
-            const MediaSelect      = 0x4000;
-            const Mail             = 0x8000;
-        }
-    }
-}
-
-impl TryFrom<ConsumerDeviceCode> for ConsumerDeviceButtons {
+        pub struct SystemControlButtons: u8 {
+            const PowerDown = 0x01;
+            const Sleep     = 0x02;
+            const WakeUp    = 0x04;
+        }
+    }
+}
+
+impl TryFrom<SystemControlCode> for SystemControlButtons {
     type Error = ();
 
-    fn try_from(value: ConsumerDeviceCode) -> std::result::Result<Self, Self::Error> {
+    fn try_from(value: SystemControlCode) -> std::result::Result<Self, Self::Error> {
         match value {
-            ConsumerDeviceCode::NextTrack => Ok(ConsumerDeviceButtons::NextTrack),
-            ConsumerDeviceCode::PreviousTrack => Ok(ConsumerDeviceButtons::PreviousTrack),
-            ConsumerDeviceCode::Stop => Ok(ConsumerDeviceButtons::Stop),
-            ConsumerDeviceCode::PlayPause => Ok(ConsumerDeviceButtons::PlayPause),
-            ConsumerDeviceCode::Mute => Ok(ConsumerDeviceButtons::Mute),
-            ConsumerDeviceCode::VolumeUp => Ok(ConsumerDeviceButtons::VolumeUp),
-            ConsumerDeviceCode::VolumeDown => Ok(ConsumerDeviceButtons::VolumeDown),
-            ConsumerDeviceCode::MediaSelect => Ok(ConsumerDeviceButtons::MediaSelect),
-            ConsumerDeviceCode::Mail => Ok(ConsumerDeviceButtons::Mail),
-            ConsumerDeviceCode::Calculator => Ok(ConsumerDeviceButtons::Calculator),
-            ConsumerDeviceCode::MyComputer => Ok(ConsumerDeviceButtons::MyComputer),
-            ConsumerDeviceCode::BrowserSearch => Ok(ConsumerDeviceButtons::BrowserSearch),
-            ConsumerDeviceCode::BrowserHome => Ok(ConsumerDeviceButtons::BrowserHome),
-            ConsumerDeviceCode::BrowserBack => Ok(ConsumerDeviceButtons::BrowserBack),
-            ConsumerDeviceCode::BrowserStop => Ok(ConsumerDeviceButtons::BrowserStop),
-            ConsumerDeviceCode::BrowserFavorites => Ok(ConsumerDeviceButtons::BrowserFavorites),
-            _ => Err(())
+            SystemControlCode::None => Err(()),
+            SystemControlCode::PowerDown => Ok(SystemControlButtons::PowerDown),
+            SystemControlCode::Sleep => Ok(SystemControlButtons::Sleep),
+            SystemControlCode::WakeUp => Ok(SystemControlButtons::WakeUp)
         }
     }
 }
@@ -574,3 +1457,178 @@ impl TryFrom<HidKeyCode> for HidModifierKeys {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_detents_are_accumulated() {
+        let mut remainder = 0;
+        assert_eq!(accumulate_detents(&mut remainder, 60, false), 0);
+        assert_eq!(remainder, 60);
+        assert_eq!(accumulate_detents(&mut remainder, 90, false), 1);
+        assert_eq!(remainder, 30);
+        assert_eq!(accumulate_detents(&mut remainder, -150, false), -1);
+        assert_eq!(remainder, 0);
+        assert_eq!(accumulate_detents(&mut remainder, i16::MAX, false), i16::MAX / SCROLL_RESOLUTION);
+        assert_eq!(remainder, i16::MAX % SCROLL_RESOLUTION);
+    }
+
+    #[test]
+    fn high_resolution_scrolling_is_passed_through() {
+        let mut remainder = 90;
+        assert_eq!(accumulate_detents(&mut remainder, 45, true), 45);
+        assert_eq!(remainder, 0);
+        assert_eq!(accumulate_detents(&mut remainder, -7, true), -7);
+        assert_eq!(remainder, 0);
+    }
+
+    fn pacer(mode: ProtocolMode, polling_interval: Duration, now: Instant) -> MovementPacer {
+        MovementPacer {
+            polling_interval,
+            next_step: now,
+            ..MovementPacer::new(mode)
+        }
+    }
+
+    fn steps(pacer: &mut MovementPacer, now: Instant) -> Vec<Vec2<i16>> {
+        std::iter::from_fn(|| pacer.step(now)).collect()
+    }
+
+    #[test]
+    fn movement_is_spread_over_the_packet_interval() {
+        let now = Instant::now();
+        let mut pacer = pacer(ProtocolMode::Report, HIGH_SPEED_POLLING_INTERVAL, now);
+        assert!(pacer.move_by(now, 100, -50));
+        assert_eq!(steps(&mut pacer, now), [Vec2::new(10, -5); 10]);
+        assert!(!pacer.is_moving());
+
+        assert!(pacer.move_by(now, 3, 0));
+        assert_eq!(steps(&mut pacer, now), [Vec2::new(1, 0); 3]);
+    }
+
+    #[test]
+    fn full_speed_movement_is_not_split() {
+        let now = Instant::now();
+        let mut pacer = pacer(ProtocolMode::Report, FULL_SPEED_POLLING_INTERVAL, now);
+        assert!(pacer.move_by(now, 100, -50));
+        assert_eq!(steps(&mut pacer, now), [Vec2::new(100, -50)]);
+    }
+
+    #[test]
+    fn steps_are_due_once_per_polling_interval() {
+        let now = Instant::now();
+        let mut pacer = pacer(ProtocolMode::Report, HIGH_SPEED_POLLING_INTERVAL, now);
+        pacer.move_by(now, 100, 0);
+        pacer.step(now);
+        assert_eq!(pacer.next_step, now + HIGH_SPEED_POLLING_INTERVAL);
+        pacer.step(now);
+        assert_eq!(pacer.next_step, now + 2 * HIGH_SPEED_POLLING_INTERVAL);
+    }
+
+    #[test]
+    fn pending_movement_is_merged_into_new_movements() {
+        let now = Instant::now();
+        let mut pacer = pacer(ProtocolMode::Report, HIGH_SPEED_POLLING_INTERVAL, now);
+        pacer.move_by(now, 100, 0);
+        assert_eq!(pacer.step(now), Some(Vec2::new(10, 0)));
+
+        // the faster movements arrive, the fewer steps are left to spread them over
+        assert!(!pacer.move_by(now + Duration::from_millis(1), 20, 0));
+        let rest = steps(&mut pacer, now);
+        assert_eq!(rest.len(), 7);
+        assert_eq!(rest.iter().map(|step| step.x).sum::<i16>(), 110);
+    }
+
+    #[test]
+    fn flush_sends_the_rest_within_the_report_limit() {
+        let now = Instant::now();
+        let mut pacer = pacer(ProtocolMode::Boot, HIGH_SPEED_POLLING_INTERVAL, now);
+        pacer.move_by(now, 300, -20);
+        let flushed: Vec<_> = std::iter::from_fn(|| pacer.flush(now)).collect();
+        assert_eq!(flushed, [Vec2::new(127, -20), Vec2::new(127, 0), Vec2::new(46, 0)]);
+    }
+
+    fn stalled_writer() -> WriterState {
+        WriterState {
+            stalled: true,
+            ..Default::default()
+        }
+    }
+
+    fn mouse(buttons: HidMouseButtons, dx: i16, dy: i16, dv: i16) -> Vec<u8> {
+        let mut buffer = [0u8; 9];
+        mouse_report(&mut buffer, ProtocolMode::Report, buttons, dx, dy, dv, 0).to_vec()
+    }
+
+    #[test]
+    fn stalled_writer_keeps_state_transitions() {
+        let mut state = stalled_writer();
+        for report in [[0x02, 0, 0x04, 0, 0, 0, 0, 0], [0x02, 0, 0x04, 0, 0, 0, 0, 0], [0; 8], [0; 8]] {
+            state.push(&report, skip_repeated);
+        }
+        assert_eq!(state.reports, [vec![0x02, 0, 0x04, 0, 0, 0, 0, 0], vec![0; 8]]);
+    }
+
+    #[test]
+    fn stalled_writer_merges_mouse_movement() {
+        let none = HidMouseButtons::empty();
+        let left = HidMouseButtons::LButton;
+        let mut state = stalled_writer();
+        for report in [
+            mouse(none, 10, -5, 0),
+            mouse(none, 3, 4, 0),
+            mouse(left, 0, 0, 0),
+            mouse(left, 1, 1, -1),
+            mouse(none, 0, 0, 0),
+            mouse(none, i16::MAX, 0, 0)
+        ] {
+            state.push(&report, merge_mouse_reports);
+        }
+        assert_eq!(
+            state.reports,
+            [mouse(none, 13, -1, 0), mouse(left, 1, 1, -1), mouse(none, i16::MAX, 0, 0)]
+        );
+        // movements that no longer fit into a single report start a new one
+        state.push(&mouse(none, 1, 0, 0), merge_mouse_reports);
+        assert_eq!(state.reports.len(), 4);
+        assert_eq!(state.reports.back(), Some(&mouse(none, 1, 0, 0)));
+    }
+
+    #[test]
+    fn boot_mouse_reports_are_merged_within_their_range() {
+        let mut last = [0x01, 100, 0x81];
+        assert!(merge_mouse_reports(&mut last, &[0x01, 20, 5]));
+        assert_eq!(last, [0x01, 120, 0x86]);
+        assert!(!merge_mouse_reports(&mut last, &[0x01, 10, 0]));
+        assert!(!merge_mouse_reports(&mut last, &[0x00, 1, 0]));
+        assert_eq!(last, [0x01, 120, 0x86]);
+    }
+
+    #[test]
+    fn writer_drops_the_oldest_reports_when_full() {
+        let mut state = WriterState::default();
+        for i in 0..=WRITE_QUEUE_CAPACITY as u8 {
+            state.push(&[i], skip_repeated);
+        }
+        assert_eq!(state.reports.len(), WRITE_QUEUE_CAPACITY);
+        assert_eq!(state.reports.front(), Some(&vec![1]));
+    }
+
+    #[test]
+    fn report_mouse_layout() {
+        let mut buffer = [0u8; 9];
+        let buttons = HidMouseButtons::LButton | HidMouseButtons::Button5;
+        let report = mouse_report(&mut buffer, ProtocolMode::Report, buttons, -2, 300, 120, -32767);
+        assert_eq!(report, [0x11, 0xFE, 0xFF, 0x2C, 0x01, 0x78, 0x00, 0x01, 0x80]);
+    }
+
+    #[test]
+    fn boot_mouse_layout() {
+        let mut buffer = [0u8; 9];
+        let buttons = HidMouseButtons::RButton | HidMouseButtons::Button4;
+        let report = mouse_report(&mut buffer, ProtocolMode::Boot, buttons, -300, 5, 120, 120);
+        assert_eq!(report, [0x02, 0x81, 0x05]);
+    }
+}
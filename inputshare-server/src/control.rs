@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use inputshare_common::{
+    decode, encode, ActionOutput, ControlRequest, ControlResponse, PowerAction, PresenceSettings, RemoteActionInfo, MAX_CONTROL_MESSAGE_SIZE
+};
+use quinn::{RecvStream, SendStream};
+use tokio::sync::{watch, Semaphore};
+use tracing::instrument;
+
+use crate::actions;
+use crate::config::{ActionConfig, PowerCommand, PowerConfig};
+
+/// The log target for everything a client asked the server itself to do
+const AUDIT: &str = "inputshare_server::audit";
+
+/// How long a power command may take before it is considered failed
+const POWER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The number of named actions that can run at the same time, across all clients
+const MAX_RUNNING_ACTIONS: usize = 4;
+
+/// Answers the requests that clients send on the control channel
+#[derive(Debug)]
+pub struct ControlHandler {
+    power: PowerConfig,
+    actions: BTreeMap<String, ActionConfig>,
+    running: Semaphore,
+    presence: watch::Sender<PresenceSettings>
+}
+
+impl ControlHandler {
+    pub fn new(power: PowerConfig, actions: BTreeMap<String, ActionConfig>, presence: watch::Sender<PresenceSettings>) -> Self {
+        Self {
+            power,
+            actions,
+            running: Semaphore::new(MAX_RUNNING_ACTIONS),
+            presence
+        }
+    }
+
+    /// The power actions that are not disabled in the config
+    pub fn power_actions(&self) -> Vec<PowerAction> {
+        [PowerAction::Shutdown, PowerAction::Reboot]
+            .into_iter()
+            .filter(|action| !matches!(self.power_command(*action), PowerCommand::Disabled))
+            .collect()
+    }
+
+    fn power_command(&self, action: PowerAction) -> &PowerCommand {
+        match action {
+            PowerAction::Shutdown => &self.power.shutdown,
+            PowerAction::Reboot => &self.power.reboot
+        }
+    }
+
+    /// Runs the command that is configured for `action` on behalf of `client`
+    pub async fn power(&self, action: PowerAction, client: SocketAddr) -> Result<()> {
+        let (command, args): (&str, Vec<&str>) = match self.power_command(action) {
+            PowerCommand::Disabled => {
+                tracing::warn!(target: AUDIT, "{} requested {:?}, denied because it is disabled", client, action);
+                bail!("{:?} is disabled on this server", action);
+            }
+            PowerCommand::Poweroff => ("shutdown", vec!["-h", "now"]),
+            PowerCommand::Reboot => ("shutdown", vec!["-r", "now"]),
+            PowerCommand::Custom { command, args } => (command, args.iter().map(String::as_str).collect())
+        };
+        tracing::info!(target: AUDIT, "{} requested {:?}, running {} {:?}", client, action, command, args);
+        let result = actions::run(command, &args, POWER_TIMEOUT)
+            .await
+            .and_then(|output| match output.success() {
+                true => Ok(()),
+                false => bail!("{} failed with {:?}: {}", command, output.exit_code, output.stderr.trim())
+            });
+        if let Err(err) = &result {
+            tracing::error!(target: AUDIT, "{:?} requested by {} failed: {:#}", action, client, err);
+        }
+        result
+    }
+
+    /// The named actions `client` is allowed to see and run
+    pub fn list_actions(&self, client: IpAddr) -> Vec<RemoteActionInfo> {
+        self.actions
+            .iter()
+            .filter(|(_, action)| action.permits(client))
+            .map(|(name, action)| RemoteActionInfo {
+                name: name.clone(),
+                description: action.description.clone(),
+                takes_arguments: action.allow_arguments
+            })
+            .collect()
+    }
+
+    /// Runs the named action on behalf of `client`
+    ///
+    /// Actions that are hidden from the client are reported as missing
+    pub async fn run_action(&self, name: &str, arguments: Vec<String>, client: SocketAddr) -> Result<ActionOutput> {
+        let action = match self.actions.get(name) {
+            Some(action) if action.permits(client.ip()) => action,
+            _ => {
+                tracing::warn!(target: AUDIT, "{} requested {:?}, denied because it is missing or not permitted", client, name);
+                bail!("There is no action called {:?}", name);
+            }
+        };
+        if !arguments.is_empty() && !action.allow_arguments {
+            tracing::warn!(target: AUDIT, "{} requested {:?} with arguments, denied because it takes none", client, name);
+            bail!("{:?} does not accept arguments", name);
+        }
+        let Ok(_permit) = self.running.try_acquire() else {
+            tracing::warn!(target: AUDIT, "{} requested {:?}, denied because too many actions are running", client, name);
+            bail!("Too many actions are running already, try again later");
+        };
+        let args: Vec<&str> = action
+            .args
+            .iter()
+            .chain(arguments.iter())
+            .map(String::as_str)
+            .collect();
+        tracing::info!(target: AUDIT, "{} requested {:?}, running {} {:?}", client, name, action.command, args);
+        let result = actions::run(&action.command, &args, action.timeout()).await;
+        match &result {
+            Ok(output) => tracing::info!(target: AUDIT, "{:?} requested by {} exited with {:?}", name, client, output.exit_code),
+            Err(err) => tracing::error!(target: AUDIT, "{:?} requested by {} failed: {:#}", name, client, err)
+        }
+        result
+    }
+
+    pub async fn handle(&self, request: ControlRequest, client: SocketAddr) -> ControlResponse {
+        match request {
+            ControlRequest::Hello => ControlResponse::Hello {
+                power_actions: self.power_actions(),
+                presence: *self.presence.borrow()
+            },
+            ControlRequest::Power(action) => match self.power(action, client).await {
+                Ok(()) => ControlResponse::Done,
+                Err(err) => ControlResponse::Failed(format!("{:#}", err))
+            },
+            ControlRequest::ListActions => ControlResponse::Actions(self.list_actions(client.ip())),
+            ControlRequest::RunAction { name, arguments } => match self.run_action(&name, arguments, client).await {
+                Ok(output) => ControlResponse::ActionFinished(output),
+                Err(err) => ControlResponse::Failed(format!("{:#}", err))
+            },
+            ControlRequest::SetPresence(settings) => {
+                tracing::info!(target: AUDIT, "{} changed the presence keeper to {:?}", client, settings);
+                self.presence.send_replace(settings);
+                ControlResponse::Done
+            }
+        }
+    }
+}
+
+/// Reads a single request from the stream and answers it
+#[instrument(skip(handler, send, recv))]
+pub async fn serve_request(handler: Arc<ControlHandler>, mut send: SendStream, recv: RecvStream, client: SocketAddr) -> Result<()> {
+    let request = recv
+        .read_to_end(MAX_CONTROL_MESSAGE_SIZE)
+        .await
+        .context("Could not read the request")?;
+    let request: ControlRequest = decode(&request).context("Received an invalid request")?;
+    tracing::debug!("Received {:?}", request);
+    let response = handler.handle(request, client).await;
+    send.write_all(&encode(&response)).await?;
+    send.finish().await?;
+    Ok(())
+}
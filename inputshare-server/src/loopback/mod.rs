@@ -0,0 +1,382 @@
+//! Runs a real client session against the server on 127.0.0.1
+//!
+//! A `Collector` sink hands the events to the test instead of writing them to devices,
+//! tests that care about the reports feed the events to in-memory `HidDevices`.
+//! `Proxy` sits between the two to test the acknowledgement window with lost, reordered and duplicated packets.
+
+mod proxy;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use inputshare_client_core::sender::InputSender;
+use inputshare_client_core::{Session, SessionOptions};
+use inputshare_common::edge::Edge;
+use inputshare_common::{ConsumerDeviceCode, DeviceStatus, HidButtonCode, HidKeyCode, SystemControlCode, SCROLL_RESOLUTION};
+use quinn::Endpoint;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{sleep, timeout};
+
+use self::proxy::{Faults, Proxy};
+use crate::config::{PowerConfig, ReturnEdgeConfig};
+use crate::configfs::ProtocolMode;
+use crate::control::ControlHandler;
+use crate::receiver::InputEvent;
+use crate::sink::{self, Collector, HidDevices};
+use crate::util::server_config;
+use crate::{handle_connection, Safeguards};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the server has to stay quiet before a test considers it done
+const IDLE_TIME: Duration = Duration::from_millis(250);
+
+/// A server whose events end up in the test
+struct Server {
+    address: SocketAddr,
+    status: watch::Sender<DeviceStatus>,
+    events: mpsc::UnboundedReceiver<InputEvent>,
+    /// How each connection ended, in the order they were accepted
+    connections: mpsc::UnboundedReceiver<anyhow::Result<()>>,
+    _endpoint: Endpoint
+}
+
+impl Server {
+    async fn start() -> Self {
+        Self::with_return_edge(None).await
+    }
+
+    async fn with_return_edge(return_edge: Option<ReturnEdgeConfig>) -> Self {
+        let endpoint = Endpoint::server(server_config(None, 1).unwrap(), "127.0.0.1:0".parse().unwrap()).unwrap();
+        let address = endpoint.local_addr().unwrap();
+        let (collector, events, status) = Collector::new();
+        let processor = sink::start(Box::new(collector));
+        let (presence, _) = watch::channel(Default::default());
+        let control = Arc::new(ControlHandler::new(PowerConfig::default(), Default::default(), presence));
+        let (_, remap) = watch::channel(Default::default());
+        let safeguards = Safeguards {
+            release_timeout: None,
+            max_hold_time: None
+        };
+        let (results, connections) = mpsc::unbounded_channel();
+        tokio::spawn({
+            let endpoint = endpoint.clone();
+            async move {
+                while let Some(connecting) = endpoint.accept().await {
+                    let result = handle_connection(processor.clone(), control.clone(), remap.clone(), connecting, safeguards, return_edge).await;
+                    // the test might have finished already
+                    results.send(result).ok();
+                }
+            }
+        });
+        Self {
+            address,
+            status,
+            events,
+            connections,
+            _endpoint: endpoint
+        }
+    }
+
+    /// Waits until the server is done with the next connection and checks that it ended without an error
+    async fn assert_connection_closed(&mut self) {
+        timeout(TIMEOUT, self.connections.recv())
+            .await
+            .expect("The connection did not end in time")
+            .expect("The server stopped accepting connections")
+            .expect("The connection failed");
+    }
+
+    async fn next_event(&mut self) -> InputEvent {
+        timeout(TIMEOUT, self.events.recv())
+            .await
+            .expect("No event arrived in time")
+            .expect("The input processor stopped")
+    }
+
+    async fn events(&mut self, count: usize) -> Vec<InputEvent> {
+        let mut events = Vec::with_capacity(count);
+        while events.len() < count {
+            events.push(self.next_event().await);
+        }
+        events
+    }
+
+    async fn assert_idle(&mut self) {
+        sleep(IDLE_TIME).await;
+        if let Ok(event) = self.events.try_recv() {
+            panic!("Unexpected event: {:?}", event);
+        }
+    }
+}
+
+async fn connect(address: SocketAddr) -> Session {
+    let host = address.to_string();
+    timeout(TIMEOUT, Session::connect(&host, SessionOptions::default()))
+        .await
+        .expect("Could not connect in time")
+        .expect("Could not connect")
+}
+
+/// Queues the inputs and waits until the server acknowledged them
+async fn send(session: &Session, f: impl FnOnce(&mut InputSender)) {
+    session.update(f);
+    timeout(TIMEOUT, session.flush())
+        .await
+        .expect("The server did not acknowledge the input in time")
+        .expect("The session stopped");
+}
+
+/// Checks that a function wrote exactly the `expected` reports
+async fn assert_reports(reports: &mut mpsc::UnboundedReceiver<Vec<u8>>, expected: &[&[u8]]) {
+    for expected in expected {
+        let report = timeout(TIMEOUT, reports.recv())
+            .await
+            .expect("No report arrived in time")
+            .expect("The function stopped");
+        assert_eq!(report, *expected);
+    }
+    sleep(IDLE_TIME).await;
+    if let Ok(report) = reports.try_recv() {
+        panic!("Unexpected report: {:?}", report);
+    }
+}
+
+#[tokio::test]
+async fn scripted_input() {
+    let mut server = Server::start().await;
+    let session = connect(server.address).await;
+
+    send(&session, |sender| {
+        sender.press_key(HidKeyCode::LeftShift);
+        sender.press_key(HidKeyCode::KeyA);
+    })
+    .await;
+    send(&session, |sender| {
+        sender.release_key(HidKeyCode::KeyA);
+        sender.release_key(HidKeyCode::LeftShift);
+    })
+    .await;
+    send(&session, |sender| sender.warp_mouse(10, -5)).await;
+    send(&session, |sender| {
+        sender.press_mouse_button(HidButtonCode::RButton);
+        sender.scroll_vertical(-SCROLL_RESOLUTION);
+        sender.release_mouse_button(HidButtonCode::RButton);
+    })
+    .await;
+    send(&session, |sender| {
+        sender.press_consumer_device(ConsumerDeviceCode::VolumeUp);
+        sender.press_system_control(SystemControlCode::Sleep);
+        sender.release_consumer_device(ConsumerDeviceCode::VolumeUp);
+    })
+    .await;
+    session.close().await.expect("The session failed");
+
+    let events = server.events(12).await;
+    server.assert_connection_closed().await;
+    server.assert_idle().await;
+    assert_eq!(
+        events,
+        [
+            InputEvent::KeyPress(HidKeyCode::LeftShift),
+            InputEvent::KeyPress(HidKeyCode::KeyA),
+            InputEvent::KeyRelease(HidKeyCode::KeyA),
+            InputEvent::KeyRelease(HidKeyCode::LeftShift),
+            InputEvent::MouseMove(10, -5),
+            InputEvent::MouseButtonPress(HidButtonCode::RButton),
+            InputEvent::VerticalScrolling(-SCROLL_RESOLUTION),
+            InputEvent::MouseButtonRelease(HidButtonCode::RButton),
+            InputEvent::ConsumerDevicePress(ConsumerDeviceCode::VolumeUp),
+            InputEvent::SystemControlPress(SystemControlCode::Sleep),
+            InputEvent::ConsumerDeviceRelease(ConsumerDeviceCode::VolumeUp),
+            // the server releases everything once the client is gone
+            InputEvent::Reset
+        ]
+    );
+
+    // the functions write on their own tasks, so only the order of the reports of each function is fixed
+    let (mut devices, mut reports) = HidDevices::in_memory(ProtocolMode::Report);
+    for event in events {
+        devices.apply(event).await.unwrap();
+    }
+    assert_eq!(devices.next_step(), None);
+    assert_reports(
+        &mut reports.keyboard,
+        &[
+            &[0x02, 0, 0, 0, 0, 0, 0, 0],
+            &[0x02, 0, 0x04, 0, 0, 0, 0, 0],
+            &[0x02, 0, 0, 0, 0, 0, 0, 0],
+            &[0; 8],
+            &[0; 8]
+        ]
+    )
+    .await;
+    // without the resolution multipliers of a host the mouse scrolls in whole detents
+    assert_reports(
+        &mut reports.mouse,
+        &[
+            &[0, 10, 0, 0xFB, 0xFF, 0, 0, 0, 0],
+            &[0x02, 0, 0, 0, 0, 0, 0, 0, 0],
+            &[0x02, 0, 0, 0, 0, 0xFF, 0xFF, 0, 0],
+            &[0; 9],
+            &[0; 9]
+        ]
+    )
+    .await;
+    assert_reports(&mut reports.consumer_device, &[&[0xE9, 0, 0, 0, 0, 0, 0, 0], &[0; 8], &[0; 8]]).await;
+    assert_reports(&mut reports.system_control, &[&[0x02], &[0]]).await;
+    assert_reports(&mut reports.gamepad, &[&[0, 0, 0x08, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]]).await;
+}
+
+#[tokio::test]
+async fn device_status_reaches_the_client() {
+    let server = Server::start().await;
+    let session = connect(server.address).await;
+    // the connection might not be listening for changes yet, in which case the status comes with the next acknowledgement
+    session.set_keepalive(true);
+    server.status.send_replace(DeviceStatus::MOUSE_STALLED);
+
+    let mut state = session.state();
+    let status = async {
+        while state.borrow_and_update().remote_status != DeviceStatus::MOUSE_STALLED {
+            state.changed().await.expect("The session stopped");
+        }
+    };
+    timeout(TIMEOUT, status)
+        .await
+        .expect("The status did not arrive in time");
+}
+
+#[tokio::test]
+async fn pointer_leaves_through_the_return_edge() {
+    let return_edge = ReturnEdgeConfig {
+        edge: Edge::Left,
+        screen_size: (1280, 720)
+    };
+    let server = Server::with_return_edge(Some(return_edge)).await;
+    let session = connect(server.address).await;
+
+    // finds the pointer in the top left corner like the client does when it switches over
+    send(&session, |sender| sender.warp_mouse(-2560, -1440)).await;
+    send(&session, |sender| sender.warp_mouse(10, 360)).await;
+    send(&session, |sender| sender.warp_mouse(-10, 0)).await;
+    assert_eq!(session.update(InputSender::take_edge_return), None);
+    send(&session, |sender| sender.warp_mouse(-1, 0)).await;
+    let along = session
+        .update(InputSender::take_edge_return)
+        .expect("The server did not report the return");
+    assert!((along - 0.5).abs() < 0.01, "{}", along);
+    session.close().await.expect("The session failed");
+}
+
+#[tokio::test]
+async fn flush_returns_while_the_mouse_keeps_moving() {
+    let mut server = Server::start().await;
+    let session = connect(server.address).await;
+
+    session.update(|sender| sender.press_key(HidKeyCode::KeyA));
+    // the remote pointer never catches up with the local one while it is moving
+    let moving = async {
+        loop {
+            session.update(|sender| sender.warp_mouse(1, 0));
+            tokio::task::yield_now().await;
+        }
+    };
+    tokio::select! {
+        _ = moving => unreachable!(),
+        result = timeout(TIMEOUT, session.flush()) => result
+            .expect("The server did not acknowledge the input in time")
+            .expect("The session stopped")
+    }
+
+    loop {
+        match server.next_event().await {
+            InputEvent::MouseMove(..) => {}
+            event => break assert_eq!(event, InputEvent::KeyPress(HidKeyCode::KeyA))
+        }
+    }
+    session.close().await.expect("The session failed");
+    server.assert_connection_closed().await;
+}
+
+/// Types over a faulty link and checks that every input arrives exactly once and in order
+async fn type_through(faults: Faults, seed: u64) -> proxy::ProxyStats {
+    const KEYS: u8 = 200;
+    let mut server = Server::start().await;
+    let proxy = Proxy::start(server.address, seed).await.unwrap();
+    let session = connect(proxy.address()).await;
+    proxy.set_faults(faults);
+
+    let mut expected = Vec::new();
+    for i in 0..KEYS {
+        let key = HidKeyCode::from(u8::from(HidKeyCode::KeyA) + i % 26);
+        session.update(|sender| {
+            sender.press_key(key);
+            sender.release_key(key);
+            sender.warp_mouse(1, -1);
+        });
+        expected.extend([InputEvent::KeyPress(key), InputEvent::KeyRelease(key)]);
+        if i % 4 == 0 {
+            sleep(Duration::from_millis(5)).await;
+        }
+    }
+    timeout(TIMEOUT, session.flush())
+        .await
+        .expect("The server did not acknowledge the input in time")
+        .expect("The session stopped");
+
+    // movements are merged depending on which packets got through, so only their sum is predictable
+    let mut keys = Vec::new();
+    let mut movement = (0, 0);
+    while keys.len() < expected.len() {
+        match server.next_event().await {
+            InputEvent::MouseMove(x, y) => movement = (movement.0 + x, movement.1 + y),
+            event => keys.push(event)
+        }
+    }
+    server.assert_idle().await;
+    assert_eq!(keys, expected);
+    assert_eq!(movement, (i64::from(KEYS), -i64::from(KEYS)));
+    proxy.stats()
+}
+
+#[tokio::test]
+async fn lost_packets_are_resent() {
+    let faults = Faults {
+        loss: 0.3,
+        ..Faults::default()
+    };
+    let stats = type_through(faults, 1).await;
+    assert!(stats.dropped > 0, "{:?}", stats);
+}
+
+#[tokio::test]
+async fn reordered_packets_are_ignored() {
+    let faults = Faults {
+        reorder: 0.3,
+        ..Faults::default()
+    };
+    let stats = type_through(faults, 2).await;
+    assert!(stats.reordered > 0, "{:?}", stats);
+}
+
+#[tokio::test]
+async fn duplicated_packets_are_ignored() {
+    let faults = Faults {
+        duplicate: 0.3,
+        ..Faults::default()
+    };
+    let stats = type_through(faults, 3).await;
+    assert!(stats.duplicated > 0, "{:?}", stats);
+}
+
+#[tokio::test]
+async fn unreliable_network() {
+    let faults = Faults {
+        loss: 0.2,
+        reorder: 0.2,
+        duplicate: 0.2
+    };
+    let stats = type_through(faults, 4).await;
+    assert!(stats.dropped > 0 && stats.reordered > 0 && stats.duplicated > 0, "{:?}", stats);
+}
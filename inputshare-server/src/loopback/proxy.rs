@@ -0,0 +1,144 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::Result;
+use tokio::net::UdpSocket;
+use tokio::select;
+use tokio::task::JoinHandle;
+
+/// The chance of each fault, applied to every datagram in both directions
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Faults {
+    pub loss: f64,
+    /// Holds a datagram back until the next one in the same direction went through
+    pub reorder: f64,
+    pub duplicate: f64
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ProxyStats {
+    pub forwarded: u64,
+    pub dropped: u64,
+    pub reordered: u64,
+    pub duplicated: u64
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Direction {
+    ToServer = 0,
+    ToClient = 1
+}
+
+#[derive(Debug)]
+struct State {
+    faults: Faults,
+    stats: ProxyStats,
+    held: [Option<Vec<u8>>; 2],
+    rng: u64
+}
+
+impl State {
+    /// A xorshift generator, so that every run sees the same faults
+    fn chance(&mut self, probability: f64) -> bool {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let sample = (self.rng >> 11) as f64 / (1u64 << 53) as f64;
+        sample < probability
+    }
+
+    /// Returns the datagrams that should be passed on in place of `datagram`
+    fn route(&mut self, direction: Direction, datagram: &[u8]) -> Vec<Vec<u8>> {
+        let faults = self.faults;
+        if self.chance(faults.loss) {
+            self.stats.dropped += 1;
+            return Vec::new();
+        }
+        if self.held[direction as usize].is_none() && self.chance(faults.reorder) {
+            self.held[direction as usize] = Some(datagram.to_vec());
+            self.stats.reordered += 1;
+            return Vec::new();
+        }
+        let mut result = vec![datagram.to_vec()];
+        if self.chance(faults.duplicate) {
+            result.push(datagram.to_vec());
+            self.stats.duplicated += 1;
+        }
+        result.extend(self.held[direction as usize].take());
+        self.stats.forwarded += result.len() as u64;
+        result
+    }
+}
+
+/// Forwards the datagrams of a single client to `server` while injecting faults
+pub struct Proxy {
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<Result<()>>
+}
+
+impl Proxy {
+    pub async fn start(server: SocketAddr, seed: u64) -> Result<Self> {
+        let client_side = UdpSocket::bind("127.0.0.1:0").await?;
+        let server_side = UdpSocket::bind("127.0.0.1:0").await?;
+        server_side.connect(server).await?;
+        let address = client_side.local_addr()?;
+        let state = Arc::new(Mutex::new(State {
+            faults: Faults::default(),
+            stats: ProxyStats::default(),
+            held: [None, None],
+            rng: seed.max(1)
+        }));
+        let task = tokio::spawn(run(client_side, server_side, state.clone()));
+        Ok(Self { address, state, task })
+    }
+
+    /// The address that the client should connect to
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn set_faults(&self, faults: Faults) {
+        self.lock().faults = faults;
+    }
+
+    pub fn stats(&self) -> ProxyStats {
+        self.lock().stats
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("Could not acquire lock")
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn run(client_side: UdpSocket, server_side: UdpSocket, state: Arc<Mutex<State>>) -> Result<()> {
+    let mut client = None;
+    let mut upstream = [0u8; 2048];
+    let mut downstream = [0u8; 2048];
+    loop {
+        select! {
+            received = client_side.recv_from(&mut upstream) => {
+                let (len, from) = received?;
+                client = Some(from);
+                let datagrams = state.lock().expect("Could not acquire lock").route(Direction::ToServer, &upstream[..len]);
+                for datagram in datagrams {
+                    server_side.send(&datagram).await?;
+                }
+            },
+            received = server_side.recv(&mut downstream) => {
+                let len = received?;
+                let Some(client) = client else { continue };
+                let datagrams = state.lock().expect("Could not acquire lock").route(Direction::ToClient, &downstream[..len]);
+                for datagram in datagrams {
+                    client_side.send_to(&datagram, client).await?;
+                }
+            }
+        }
+    }
+}
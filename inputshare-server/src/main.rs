@@ -1,19 +1,30 @@
+mod actions;
+mod config;
 mod configfs;
+mod control;
+#[cfg(test)]
+mod loopback;
+mod presence;
+mod queue;
 mod receiver;
+mod remap;
+mod sink;
+#[cfg(target_os = "linux")]
+mod uinput;
 mod util;
 
-use std::future::pending;
-use std::num::NonZeroU8;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::{bail, ensure, Context, Result};
+use anyhow::{bail, ensure, Result};
 use bytes::Bytes;
 use clap::{arg, command, Parser};
+use inputshare_common::{DeviceStatus, PresencePattern, PROTOCOL};
 use mdns_sd::Error;
-use quinn::{Connecting, ConnectionError, Endpoint};
-use tokio::process::Command;
-use tokio::sync::mpsc::UnboundedSender;
-use tokio::time::sleep;
+use quinn::{Connecting, Connection, ConnectionError, Endpoint};
+use tokio::sync::watch;
+use tokio::time::{interval, sleep, Instant};
 use tokio::{select, spawn};
 use tracing::{instrument, Instrument, Span};
 use tracing_subscriber::filter::{LevelFilter, Targets};
@@ -21,34 +32,83 @@ use tracing_subscriber::fmt::layer;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
+use crate::config::{reload_on_hangup, Config, OutputConfig, ReturnEdgeConfig};
+use crate::control::{serve_request, ControlHandler};
+use crate::queue::EventSender;
 use crate::receiver::{InputEvent, InputReceiver};
-use crate::util::{quit, self_signed_config, start_mdns};
+use crate::remap::{RemapConfig, Remapper};
+use crate::util::{negotiated_protocol, quit, server_config, start_mdns};
+
+/// The number of events that can wait for the input processor before new movements get merged into older ones
+const EVENT_QUEUE_CAPACITY: usize = 64;
 
 /// The server for inputshare
 #[derive(Parser, Debug)]
 #[command(about, version, author)]
 struct Args {
-    /// When set automatically moves the mouse every x seconds without input
-    #[arg(short, long)]
-    auto_movement_timeout: Option<u64>,
-
-    /// Split each mouse movement command in up to x usb packets
-    /// Higher values mean smoother movement but carry a higher risk of saturating the usb connection
-    #[arg(short, long, default_value_t = 5)]
-    mouse_tesselation_factor: u8,
-
     /// The interface that should be bound
     #[arg(short, long, default_value = "0.0.0.0:60067")]
     interface: String,
 
-    /// Replace the actual HID emulation with a simple console logger
+    /// Replace the outputs of the configuration with a simple console logger
     /// Useful for debugging or testing on windows
     #[arg(short, long)]
     console: bool,
 
     /// Disabled the mDNS service that is use for service discovery
     #[arg(short, long)]
-    no_mdns: bool
+    no_mdns: bool,
+
+    /// Emulate boot protocol compatible devices (3-byte mouse reports, no scrolling)
+    /// Required for BIOS/UEFI setups, bootloaders and some KVMs
+    #[arg(short, long)]
+    boot_mode: bool,
+
+    /// Release all held inputs when the client sent no packet for x milliseconds (0 disables this)
+    /// Requires a client that keeps sending packets while input is captured
+    #[arg(short, long, default_value_t = 1000)]
+    release_timeout: u64,
+
+    /// Release any key or button that has been held down for longer than x milliseconds
+    #[arg(long)]
+    max_hold_time: Option<u64>,
+
+    /// The configuration file (ron) for power actions, named actions, the presence keeper and remapping
+    /// Sending SIGHUP reloads the remapping profiles from it
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// A PEM file with the certificate and private key of the server, created with a self-signed certificate if missing
+    /// Without it the server uses a new certificate on every start, which clients can not pin
+    #[arg(long)]
+    certificate: Option<PathBuf>,
+
+    /// Deprecated and ignored, mouse movements are paced at the polling interval of the host
+    #[arg(short, long, hide = true)]
+    mouse_tesselation_factor: Option<u8>,
+
+    /// Deprecated, enables the presence keeper with a wiggle every x seconds without input
+    #[arg(short, long, hide = true)]
+    auto_movement_timeout: Option<u64>
+}
+
+/// How often a connection checks for inputs that have to be released
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Limits that keep inputs from getting stuck on the target
+#[derive(Debug, Copy, Clone)]
+struct Safeguards {
+    /// Release everything when the client went silent for this long while inputs are held
+    release_timeout: Option<Duration>,
+    /// Release every key or button that has been held for this long
+    max_hold_time: Option<Duration>
+}
+
+/// The handle that connections use to talk to the input processor
+#[derive(Debug, Clone)]
+struct InputProcessor {
+    events: EventSender,
+    status: watch::Receiver<DeviceStatus>
 }
 
 #[instrument]
@@ -66,9 +126,29 @@ async fn main() -> Result<()> {
         .try_init()?;
 
     let args = Args::parse();
+    if args.mouse_tesselation_factor.is_some() {
+        tracing::warn!("--mouse-tesselation-factor is deprecated and has no effect");
+    }
+    let mut config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default()
+    };
+    if let Some(interval) = args.auto_movement_timeout {
+        tracing::warn!("--auto-movement-timeout is deprecated, use the presence section of the configuration instead");
+        config.presence.enabled = true;
+        config.presence.pattern = PresencePattern::Wiggle;
+        config.presence.interval = interval;
+    }
+    tracing::debug!("Using {:?}", config);
+    let (presence, presence_settings) = watch::channel(config.presence.settings());
+    let control = Arc::new(ControlHandler::new(config.power, config.actions, presence));
+    let (remap_sender, remap) = watch::channel(Arc::new(config.remap));
+    if let Some(path) = args.config.clone() {
+        spawn(reload_on_hangup(path, remap_sender));
+    }
     let interface = args.interface.parse()?;
     tracing::debug!("Attempting to bind {}", interface);
-    let endpoint = Endpoint::server(self_signed_config(1)?, interface)?;
+    let endpoint = Endpoint::server(server_config(args.certificate.as_deref(), 1)?, interface)?;
 
     let mdns = match args.no_mdns {
         true => None,
@@ -86,15 +166,30 @@ async fn main() -> Result<()> {
         }
     });
 
-    let processor = match args.console {
-        true => log_input_processor().await?,
-        false => configfs_input_processor(args.mouse_tesselation_factor.try_into()?, args.auto_movement_timeout).await?
+    let outputs = match args.console {
+        true => vec![OutputConfig::Log],
+        false => config.outputs
+    };
+    let mode = match args.boot_mode {
+        true => configfs::ProtocolMode::Boot,
+        false => configfs::ProtocolMode::Report
+    };
+    let processor = sink::start(sink::open(&outputs, mode, &config.presence, presence_settings).await?);
+
+    let safeguards = Safeguards {
+        release_timeout: Some(args.release_timeout)
+            .filter(|timeout| *timeout != 0)
+            .map(Duration::from_millis),
+        max_hold_time: args.max_hold_time.map(Duration::from_millis)
     };
+    let return_edge = config.return_edge;
 
     while let Some(conn) = endpoint.accept().await {
         let processor = processor.clone();
+        let control = control.clone();
+        let remap = remap.clone();
         spawn(async move {
-            handle_connection(processor, conn)
+            handle_connection(processor, control, remap, conn, safeguards, return_edge)
                 .await
                 .unwrap_or_else(|err| tracing::error!("Connection crashed!\n{:?}", err))
         });
@@ -112,124 +207,106 @@ async fn main() -> Result<()> {
 }
 
 #[instrument(skip_all, fields(addrs = tracing::field::Empty))]
-async fn handle_connection(processor: UnboundedSender<InputEvent>, connecting: Connecting) -> Result<()> {
+async fn handle_connection(
+    mut processor: InputProcessor, control: Arc<ControlHandler>, remap: watch::Receiver<Arc<RemapConfig>>, connecting: Connecting,
+    safeguards: Safeguards, return_edge: Option<ReturnEdgeConfig>
+) -> Result<()> {
     let connection = connecting.await?;
     let span = Span::current();
     span.record("addrs", connection.remote_address().to_string());
+    if negotiated_protocol(&connection).as_deref() != Some(PROTOCOL) {
+        connection.close(0u8.into(), b"Incompatible protocol version");
+        bail!("The client uses an incompatible protocol version");
+    }
     tracing::debug!("Established connection");
+    let remapper = Remapper::new(connection.remote_address().ip(), remap);
     let mut receiver = InputReceiver::new();
-
-    loop {
-        let msg = match connection.read_datagram().await {
-            Ok(msg) => msg,
-            Err(ConnectionError::ApplicationClosed(close)) => {
-                tracing::debug!("Connection closed: {}", close);
-                return Ok(());
-            }
-            Err(ConnectionError::LocallyClosed) => {
-                tracing::debug!("Closing Connection");
-                return Ok(());
-            }
-            Err(err) => return Err(err.into())
-        };
-        if let Some(packet) = receiver.process_packet(&msg)? {
-            ensure!(packet.len() <= connection.max_datagram_size().unwrap());
-            connection.send_datagram(Bytes::copy_from_slice(packet))?;
-        }
-        while let Some(event) = receiver.get_event() {
-            processor
-                .send(event)
-                .context("The input processor seems to be gone")?;
-        }
+    if let Some(return_edge) = return_edge {
+        receiver.track_pointer(return_edge.pointer());
+    }
+    let result = receive_input(&mut processor, &control, remapper, receiver, &connection, safeguards).await;
+    tracing::debug!("Releasing everything that is still held by the session");
+    if let Err(err) = processor.events.send(InputEvent::Reset).await {
+        tracing::warn!("Could not release held inputs: {}", err);
     }
+    result
 }
 
-#[instrument]
-async fn log_input_processor() -> Result<UnboundedSender<InputEvent>> {
-    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
-    tracing::debug!("Starting print processor");
-    spawn(
-        async move {
-            while let Some(event) = receiver.recv().await {
-                tracing::info!("New input event: {:?}", event);
-            }
-            tracing::debug!("Stopping print processor");
-        }
-        .instrument(Span::current())
-    );
-    Ok(sender)
-}
+async fn receive_input(
+    processor: &mut InputProcessor, control: &Arc<ControlHandler>, mut remapper: Remapper, mut receiver: InputReceiver, connection: &Connection,
+    safeguards: Safeguards
+) -> Result<()> {
+    let client = connection.remote_address();
+    receiver.set_status(*processor.status.borrow_and_update());
+    let mut last_packet = Instant::now();
+    let mut watchdog = interval(WATCHDOG_INTERVAL);
+    let watchdog_enabled = safeguards.release_timeout.is_some() || safeguards.max_hold_time.is_some();
 
-#[instrument]
-async fn configfs_input_processor(tess_factor: NonZeroU8, timeout: Option<u64>) -> Result<UnboundedSender<InputEvent>> {
-    use configfs::*;
-    let mut keyboard = Keyboard::new().await?;
-    let mut mouse = Mouse::new(tess_factor).await?;
-    let mut consumer_device = ConsumerDevice::new().await?;
-    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
-    tracing::debug!("Starting configfs processor");
-    spawn(
-        async move {
-            let mut idle_move_x = -10;
-            loop {
-                let timeout = async move {
-                    match timeout {
-                        Some(timeout) => sleep(Duration::from_secs(timeout)).await,
-                        None => pending().await
-                    };
+    loop {
+        select! {
+            msg = connection.read_datagram() => {
+                let msg = match msg {
+                    Ok(msg) => msg,
+                    Err(err) => {
+                        tracing::debug!("Event queue: {:?}", processor.events.stats());
+                        return connection_closed(err);
+                    }
+                };
+                last_packet = Instant::now();
+                if let Some(packet) = receiver.process_packet(&msg)? {
+                    ensure!(packet.len() <= connection.max_datagram_size().unwrap());
+                    connection.send_datagram(Bytes::copy_from_slice(packet))?;
+                }
+            },
+            stream = connection.accept_bi() => {
+                let (send, recv) = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => return connection_closed(err)
                 };
-                select! {
-                    event = receiver.recv() => match event {
-                        Some(event) => {
-                            let result = match event {
-                                InputEvent::MouseMove(x, y) => mouse.move_by(x as i16, y as i16).await,
-                                InputEvent::KeyPress(key) => keyboard.press_key(key).await,
-                                InputEvent::KeyRelease(key) => keyboard.release_key(key).await,
-                                InputEvent::MouseButtonPress(button) => mouse.press_button(button).await,
-                                InputEvent::MouseButtonRelease(button) => mouse.release_button(button).await,
-                                InputEvent::ConsumerDevicePress(button) => consumer_device.press_key(button).await,
-                                InputEvent::ConsumerDeviceRelease(button) => consumer_device.release_key(button).await,
-                                InputEvent::HorizontalScrolling(amount) => mouse.scroll_horizontal(amount).await,
-                                InputEvent::VerticalScrolling(amount) => mouse.scroll_vertical(amount).await,
-                                InputEvent::Reset => {
-                                    async {
-                                        keyboard.reset().await?;
-                                        mouse.reset().await?;
-                                        consumer_device.reset().await?;
-                                        Ok(())
-                                    }
-                                    .await
-                                },
-                                InputEvent::Shutdown => run_command("shutdown", &["-r", "now"]).await,
-                            };
-                            if let Err(err) = result {
-                                tracing::error!("Could not write hid command: {}", err);
-                                break;
-                            }
-                        },
-                        None => break
-                    },
-                    _ = timeout => {
-                        if let Err(err) = mouse.move_by(idle_move_x, 0).await {
-                             tracing::error!("Could not write hid command: {}", err);
-                             break;
+                let control = control.clone();
+                spawn(
+                    async move {
+                        if let Err(err) = serve_request(control, send, recv, client).await {
+                            tracing::warn!("Could not answer control request: {:#}", err);
                         }
-                        idle_move_x *= -1;
                     }
-                };
+                    .in_current_span()
+                );
+            },
+            Ok(()) = processor.status.changed() => {
+                receiver.set_status(*processor.status.borrow());
+                connection.send_datagram(Bytes::copy_from_slice(receiver.write_ack()?))?;
+            },
+            _ = watchdog.tick(), if watchdog_enabled => {
+                if let Some(timeout) = safeguards.release_timeout {
+                    if last_packet.elapsed() >= timeout && receiver.has_held_inputs() {
+                        tracing::warn!("No packet for {:?}, releasing all held inputs", timeout);
+                        receiver.release_all();
+                    }
+                }
+                if let Some(max_hold_time) = safeguards.max_hold_time {
+                    receiver.release_expired(max_hold_time);
+                }
             }
-            tracing::debug!("Stopping configfs processor");
         }
-        .instrument(Span::current())
-    );
-    Ok(sender)
+        while let Some(event) = receiver.get_event() {
+            for event in remapper.apply(event) {
+                processor.events.send(event).await?;
+            }
+        }
+    }
 }
 
-#[instrument]
-async fn run_command(command: &str, args: &[&str]) -> Result<()> {
-    let output = Command::new(command).args(args).output().await?;
-    if output.status.success() && output.stderr.is_empty() {
-        return Ok(());
+fn connection_closed(err: ConnectionError) -> Result<()> {
+    match err {
+        ConnectionError::ApplicationClosed(close) => {
+            tracing::debug!("Connection closed: {}", close);
+            Ok(())
+        }
+        ConnectionError::LocallyClosed => {
+            tracing::debug!("Closing Connection");
+            Ok(())
+        }
+        err => Err(err.into())
     }
-    bail!("{}", String::from_utf8_lossy(&output.stderr));
 }
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use inputshare_common::{PresencePattern, PresenceSettings};
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use crate::config::{PresenceConfig, Schedule, TimeOfDay, Weekday};
+
+/// Decides when the target should receive a harmless input to keep it from going idle
+///
+/// Real input always postpones the next nudge by the full interval
+#[derive(Debug)]
+pub struct PresenceKeeper {
+    interval: Duration,
+    schedule: Option<Schedule>,
+    settings: watch::Receiver<PresenceSettings>,
+    last_activity: Instant,
+    direction: i8
+}
+
+impl PresenceKeeper {
+    pub fn new(config: &PresenceConfig, settings: watch::Receiver<PresenceSettings>) -> Self {
+        Self {
+            interval: config.interval(),
+            schedule: config.schedule.clone(),
+            settings,
+            last_activity: Instant::now(),
+            direction: 1
+        }
+    }
+
+    pub fn input_received(&mut self) {
+        self.last_activity = Instant::now();
+    }
+
+    /// The time of the next nudge or `None` while the keeper is disabled
+    pub fn deadline(&self) -> Option<Instant> {
+        self.settings
+            .borrow()
+            .enabled
+            .then_some(self.last_activity + self.interval)
+    }
+
+    /// Completes once a client changed the settings
+    pub async fn changed(&mut self) -> Result<(), watch::error::RecvError> {
+        self.settings.changed().await
+    }
+
+    /// Starts the next interval and returns the pattern to perform along with its direction
+    ///
+    /// Returns `None` if the keeper is disabled or outside of its schedule
+    pub fn next_nudge(&mut self) -> Option<(PresencePattern, i8)> {
+        self.last_activity = Instant::now();
+        let settings = *self.settings.borrow();
+        if !settings.enabled {
+            return None;
+        }
+        if let Some(schedule) = &self.schedule {
+            let (day, time) = local_time();
+            if !schedule.contains(day, time) {
+                return None;
+            }
+        }
+        self.direction = -self.direction;
+        Some((settings.pattern, self.direction))
+    }
+}
+
+#[cfg(unix)]
+fn local_time() -> (Weekday, TimeOfDay) {
+    // SAFETY: `localtime_r` only writes to the provided `tm`
+    let tm = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        tm
+    };
+    let time = TimeOfDay::new(tm.tm_hour as u16, tm.tm_min as u16).expect("localtime returned an invalid time");
+    (Weekday::ALL[tm.tm_wday as usize % 7], time)
+}
+
+/// Falls back to UTC
+#[cfg(not(unix))]
+fn local_time() -> (Weekday, TimeOfDay) {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let minutes = (seconds % 86400) / 60;
+    let time = TimeOfDay::new((minutes / 60) as u16, (minutes % 60) as u16).expect("Invalid time");
+    // the epoch was a thursday
+    (Weekday::ALL[((seconds / 86400 + 4) % 7) as usize], time)
+}
@@ -0,0 +1,424 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use tokio::sync::Notify;
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::receiver::InputEvent;
+
+/// How often `log_stats` checks the queue
+const STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Counters that describe how the queue has been coping with the incoming events
+///
+/// There is no counter for dropped events because the queue never drops any, senders wait for space instead.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct QueueStats {
+    /// The number of events that are currently waiting to be processed
+    pub depth: usize,
+    /// The highest depth that was ever reached
+    pub max_depth: usize,
+    /// The number of events that were merged into an event that was already queued
+    pub merged: u64
+}
+
+#[derive(Debug)]
+struct State {
+    events: VecDeque<InputEvent>,
+    capacity: usize,
+    stats: QueueStats,
+    senders: usize,
+    receiver_alive: bool
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    readable: Notify,
+    writable: Notify
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("Could not acquire lock")
+    }
+}
+
+/// Creates a bounded queue between the connections and an input processor
+///
+/// Consecutive movement, scroll and gamepad events are merged while they wait in the queue.
+/// Once the queue is full, movements and scrolls are also merged into the newest event of the same kind,
+/// as long as that does not move them past an event that has to stay in order.
+/// No event is ever dropped and other events are never merged or reordered. Pushing into a full queue waits until space becomes available.
+pub fn event_queue(capacity: usize) -> (EventSender, EventReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+            stats: QueueStats::default(),
+            senders: 1,
+            receiver_alive: true
+        }),
+        readable: Notify::new(),
+        writable: Notify::new()
+    });
+    (EventSender { shared: shared.clone() }, EventReceiver { shared })
+}
+
+#[derive(Debug)]
+pub struct EventSender {
+    shared: Arc<Shared>
+}
+
+impl EventSender {
+    pub async fn send(&self, event: InputEvent) -> Result<()> {
+        loop {
+            let writable = self.shared.writable.notified();
+            if self.try_send(event)? {
+                return Ok(());
+            }
+            writable.await;
+        }
+    }
+
+    /// Returns `false` instead of waiting if the queue is full and the event can not be merged
+    pub fn try_send(&self, event: InputEvent) -> Result<bool> {
+        let mut state = self.shared.lock();
+        if !state.receiver_alive {
+            bail!("The input processor seems to be gone");
+        }
+        let full = state.events.len() >= state.capacity;
+        let merged = match state.events.back_mut() {
+            Some(last) => merge(last, &event),
+            None => false
+        };
+        if merged || (full && merge_into_queue(&mut state.events, &event)) {
+            state.stats.merged += 1;
+            return Ok(true);
+        }
+        if full {
+            return Ok(false);
+        }
+        state.events.push_back(event);
+        state.stats.depth = state.events.len();
+        state.stats.max_depth = state.stats.max_depth.max(state.stats.depth);
+        self.shared.readable.notify_one();
+        Ok(true)
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        self.shared.lock().stats
+    }
+
+    /// A handle to the stats that does not keep the queue open
+    pub fn monitor(&self) -> QueueMonitor {
+        QueueMonitor {
+            shared: Arc::downgrade(&self.shared)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QueueMonitor {
+    shared: Weak<Shared>
+}
+
+impl QueueMonitor {
+    /// Returns `None` once the queue is gone
+    pub fn stats(&self) -> Option<QueueStats> {
+        self.shared.upgrade().map(|shared| shared.lock().stats)
+    }
+}
+
+/// Logs the stats of the queue whenever they changed, until the queue is gone
+pub async fn log_stats(monitor: QueueMonitor) {
+    let mut timer = interval(STATS_INTERVAL);
+    timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last = QueueStats::default();
+    loop {
+        timer.tick().await;
+        let Some(stats) = monitor.stats() else {
+            break;
+        };
+        if stats != last {
+            tracing::info!(
+                "Event queue: {} waiting, at most {} so far, {} merged",
+                stats.depth,
+                stats.max_depth,
+                stats.merged
+            );
+            last = stats;
+        }
+    }
+}
+
+impl Clone for EventSender {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl Drop for EventSender {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.shared.readable.notify_one();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EventReceiver {
+    shared: Arc<Shared>
+}
+
+impl EventReceiver {
+    /// Returns the next event or `None` once all senders are gone and the queue is empty
+    pub async fn recv(&mut self) -> Option<InputEvent> {
+        loop {
+            let readable = self.shared.readable.notified();
+            {
+                let mut state = self.shared.lock();
+                if let Some(event) = state.events.pop_front() {
+                    state.stats.depth = state.events.len();
+                    self.shared.writable.notify_waiters();
+                    return Some(event);
+                }
+                if state.senders == 0 {
+                    return None;
+                }
+            }
+            readable.await;
+        }
+    }
+}
+
+impl Drop for EventReceiver {
+    fn drop(&mut self) {
+        self.shared.lock().receiver_alive = false;
+        self.shared.writable.notify_waiters();
+    }
+}
+
+/// Events that only carry a delta, their order among each other does not change where the target ends up
+fn is_delta(event: &InputEvent) -> bool {
+    matches!(
+        event,
+        InputEvent::MouseMove(..) | InputEvent::HorizontalScrolling(_) | InputEvent::VerticalScrolling(_)
+    )
+}
+
+/// Merges a delta into the newest queued event of the same kind that is only followed by other deltas
+fn merge_into_queue(events: &mut VecDeque<InputEvent>, event: &InputEvent) -> bool {
+    if !is_delta(event) {
+        return false;
+    }
+    for queued in events.iter_mut().rev() {
+        if merge(queued, event) {
+            return true;
+        }
+        if !is_delta(queued) {
+            return false;
+        }
+    }
+    false
+}
+
+/// Tries to merge `event` into `last`, returning `true` on success
+fn merge(last: &mut InputEvent, event: &InputEvent) -> bool {
+    match (last, event) {
+        (InputEvent::MouseMove(x, y), InputEvent::MouseMove(dx, dy)) => {
+            *x = x.saturating_add(*dx);
+            *y = y.saturating_add(*dy);
+            true
+        }
+        (InputEvent::HorizontalScrolling(amount), InputEvent::HorizontalScrolling(delta))
+        | (InputEvent::VerticalScrolling(amount), InputEvent::VerticalScrolling(delta)) => match amount.checked_add(*delta) {
+            Some(sum) => {
+                *amount = sum;
+                true
+            }
+            None => false
+        },
+        (InputEvent::Gamepad(state), InputEvent::Gamepad(new)) => {
+            *state = *new;
+            true
+        }
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use inputshare_common::{HidButtonCode, HidKeyCode};
+    use tokio::time::timeout;
+
+    use super::*;
+
+    const SHORT: Duration = Duration::from_millis(50);
+
+    async fn send_all(sender: &EventSender, events: impl IntoIterator<Item = InputEvent>) {
+        for event in events {
+            sender.send(event).await.unwrap();
+        }
+    }
+
+    async fn drain(receiver: &mut EventReceiver) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        while let Ok(Some(event)) = timeout(SHORT, receiver.recv()).await {
+            events.push(event);
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn consecutive_deltas_are_merged() {
+        let (sender, mut receiver) = event_queue(8);
+        send_all(
+            &sender,
+            [
+                InputEvent::MouseMove(1, 2),
+                InputEvent::MouseMove(3, -4),
+                InputEvent::VerticalScrolling(60),
+                InputEvent::VerticalScrolling(60),
+                InputEvent::KeyPress(HidKeyCode::KeyA),
+                InputEvent::MouseMove(5, 5)
+            ]
+        )
+        .await;
+
+        assert_eq!(
+            drain(&mut receiver).await,
+            [
+                InputEvent::MouseMove(4, -2),
+                InputEvent::VerticalScrolling(120),
+                InputEvent::KeyPress(HidKeyCode::KeyA),
+                InputEvent::MouseMove(5, 5),
+            ]
+        );
+        let stats = sender.stats();
+        assert_eq!(stats.merged, 2);
+        assert_eq!(stats.max_depth, 4);
+        assert_eq!(stats.depth, 0);
+    }
+
+    #[tokio::test]
+    async fn overflowing_scroll_is_not_merged() {
+        let (sender, mut receiver) = event_queue(8);
+        send_all(&sender, [InputEvent::HorizontalScrolling(i16::MAX), InputEvent::HorizontalScrolling(1)]).await;
+        assert_eq!(
+            drain(&mut receiver).await,
+            [InputEvent::HorizontalScrolling(i16::MAX), InputEvent::HorizontalScrolling(1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn full_queue_merges_deltas_instead_of_dropping_them() {
+        let (sender, mut receiver) = event_queue(3);
+        send_all(
+            &sender,
+            [
+                InputEvent::KeyPress(HidKeyCode::KeyA),
+                InputEvent::MouseMove(1, 1),
+                InputEvent::VerticalScrolling(10)
+            ]
+        )
+        .await;
+
+        let send = sender.send(InputEvent::MouseMove(2, 3));
+        timeout(SHORT, send)
+            .await
+            .expect("A delta should never wait while it can be merged")
+            .unwrap();
+        assert_eq!(
+            drain(&mut receiver).await,
+            [
+                InputEvent::KeyPress(HidKeyCode::KeyA),
+                InputEvent::MouseMove(3, 4),
+                InputEvent::VerticalScrolling(10),
+            ]
+        );
+        assert_eq!(sender.stats().max_depth, 3);
+    }
+
+    #[tokio::test]
+    async fn deltas_are_not_merged_past_other_events() {
+        let (sender, mut receiver) = event_queue(2);
+        send_all(
+            &sender,
+            [InputEvent::MouseMove(1, 1), InputEvent::MouseButtonPress(HidButtonCode::LButton)]
+        )
+        .await;
+
+        let send = sender.send(InputEvent::MouseMove(2, 2));
+        tokio::pin!(send);
+        assert!(
+            timeout(SHORT, &mut send).await.is_err(),
+            "The movement must not end up in front of the click"
+        );
+
+        assert_eq!(receiver.recv().await, Some(InputEvent::MouseMove(1, 1)));
+        timeout(SHORT, send).await.unwrap().unwrap();
+        assert_eq!(
+            drain(&mut receiver).await,
+            [InputEvent::MouseButtonPress(HidButtonCode::LButton), InputEvent::MouseMove(2, 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn full_queue_blocks_other_events() {
+        let (sender, mut receiver) = event_queue(2);
+        send_all(
+            &sender,
+            [InputEvent::KeyPress(HidKeyCode::KeyA), InputEvent::KeyRelease(HidKeyCode::KeyA)]
+        )
+        .await;
+
+        let send = sender.send(InputEvent::KeyPress(HidKeyCode::KeyB));
+        tokio::pin!(send);
+        assert!(timeout(SHORT, &mut send).await.is_err());
+
+        assert_eq!(receiver.recv().await, Some(InputEvent::KeyPress(HidKeyCode::KeyA)));
+        timeout(SHORT, send).await.unwrap().unwrap();
+        assert_eq!(
+            drain(&mut receiver).await,
+            [InputEvent::KeyRelease(HidKeyCode::KeyA), InputEvent::KeyPress(HidKeyCode::KeyB)]
+        );
+        assert_eq!(sender.stats().merged, 0);
+    }
+
+    #[tokio::test]
+    async fn queue_closes_with_either_side() {
+        let (sender, mut receiver) = event_queue(2);
+        let second = sender.clone();
+        sender.send(InputEvent::Reset).await.unwrap();
+        drop(sender);
+        drop(second);
+        assert_eq!(receiver.recv().await, Some(InputEvent::Reset));
+        assert_eq!(receiver.recv().await, None);
+
+        let (sender, receiver) = event_queue(2);
+        drop(receiver);
+        assert!(sender.send(InputEvent::Reset).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn monitor_does_not_keep_the_queue_open() {
+        let (sender, mut receiver) = event_queue(2);
+        let monitor = sender.monitor();
+        sender.send(InputEvent::Reset).await.unwrap();
+        assert_eq!(monitor.stats().map(|stats| stats.depth), Some(1));
+
+        drop(sender);
+        assert_eq!(receiver.recv().await, Some(InputEvent::Reset));
+        assert_eq!(receiver.recv().await, None);
+        drop(receiver);
+        assert_eq!(monitor.stats(), None);
+    }
+}
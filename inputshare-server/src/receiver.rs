@@ -1,11 +1,16 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::convert::TryFrom;
 use std::io::Result;
+use std::time::{Duration, Instant};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use inputshare_common::{ConsumerDeviceCode, HidButtonCode, HidKeyCode, MessageType, MouseType, Vec2};
+use inputshare_common::edge::{EdgeReturns, RemotePointer};
+use inputshare_common::{
+    ConsumerDeviceCode, DeviceStatus, GamepadState, HidButtonCode, HidKeyCode, MessageType, MouseType, SystemControlCode, Vec2, MESSAGE_SIZE,
+    SCROLL_RESOLUTION
+};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum InputEvent {
     MouseMove(MouseType, MouseType),
     KeyPress(HidKeyCode),
@@ -14,10 +19,34 @@ pub enum InputEvent {
     MouseButtonRelease(HidButtonCode),
     ConsumerDevicePress(ConsumerDeviceCode),
     ConsumerDeviceRelease(ConsumerDeviceCode),
-    HorizontalScrolling(i8),
-    VerticalScrolling(i8),
-    Reset,
-    Shutdown
+    SystemControlPress(SystemControlCode),
+    SystemControlRelease(SystemControlCode),
+    /// In units of `1 / SCROLL_RESOLUTION` detents
+    HorizontalScrolling(i16),
+    /// In units of `1 / SCROLL_RESOLUTION` detents
+    VerticalScrolling(i16),
+    Gamepad(GamepadState),
+    Reset
+}
+
+/// An input that stays active on the target until it gets released
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+enum HeldInput {
+    Key(HidKeyCode),
+    MouseButton(HidButtonCode),
+    ConsumerDevice(ConsumerDeviceCode),
+    SystemControl(SystemControlCode)
+}
+
+impl HeldInput {
+    fn release(self) -> InputEvent {
+        match self {
+            HeldInput::Key(key) => InputEvent::KeyRelease(key),
+            HeldInput::MouseButton(button) => InputEvent::MouseButtonRelease(button),
+            HeldInput::ConsumerDevice(button) => InputEvent::ConsumerDeviceRelease(button),
+            HeldInput::SystemControl(button) => InputEvent::SystemControlRelease(button)
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -27,6 +56,12 @@ pub struct InputReceiver {
     packet_buffer: Vec<u8>,
     local_mouse_pos: Vec2<MouseType>,
     last_message: u64,
+    gamepad: GamepadState,
+    status: DeviceStatus,
+    /// Tracks the pointer of the target if it has a return edge
+    pointer: Option<RemotePointer>,
+    edge_returns: EdgeReturns,
+    held: HashMap<HeldInput, Instant>,
     events: VecDeque<InputEvent>
 }
 
@@ -38,12 +73,92 @@ impl InputReceiver {
             packet_buffer: Vec::new(),
             events: VecDeque::new(),
             last_message: 0,
+            gamepad: GamepadState::default(),
+            status: DeviceStatus::empty(),
+            pointer: None,
+            edge_returns: EdgeReturns::default(),
+            held: HashMap::new(),
             remote_sequence: 0
         }
     }
 
+    /// Sets the status that is sent along with every acknowledgement
+    pub fn set_status(&mut self, status: DeviceStatus) {
+        self.status = status;
+    }
+
+    /// Reports to the client whenever the pointer leaves the screen through the return edge of `pointer`
+    pub fn track_pointer(&mut self, pointer: RemotePointer) {
+        self.pointer = Some(pointer);
+    }
+
+    fn pointer_moved(&mut self, x: MouseType, y: MouseType) {
+        let Some(pointer) = self.pointer.as_mut() else {
+            return;
+        };
+        let screen = pointer.screen();
+        // the client pushes the pointer into a corner to find it after switching over, which must not count as leaving the screen
+        if x.abs() >= screen.width || y.abs() >= screen.height {
+            pointer.warp(x, y);
+        } else if let Some(along) = pointer.moved(x, y) {
+            tracing::debug!("The pointer left the screen through the return edge at {:.2}", along);
+            self.edge_returns.record(along);
+        }
+    }
+
     pub fn get_event(&mut self) -> Option<InputEvent> {
-        self.events.pop_front()
+        let event = self.events.pop_front()?;
+        self.track_held_inputs(&event);
+        Some(event)
+    }
+
+    fn track_held_inputs(&mut self, event: &InputEvent) {
+        let (input, pressed) = match *event {
+            InputEvent::KeyPress(key) => (HeldInput::Key(key), true),
+            InputEvent::KeyRelease(key) => (HeldInput::Key(key), false),
+            InputEvent::MouseButtonPress(button) => (HeldInput::MouseButton(button), true),
+            InputEvent::MouseButtonRelease(button) => (HeldInput::MouseButton(button), false),
+            InputEvent::ConsumerDevicePress(button) => (HeldInput::ConsumerDevice(button), true),
+            InputEvent::ConsumerDeviceRelease(button) => (HeldInput::ConsumerDevice(button), false),
+            InputEvent::SystemControlPress(button) => (HeldInput::SystemControl(button), true),
+            InputEvent::SystemControlRelease(button) => (HeldInput::SystemControl(button), false),
+            InputEvent::Reset => return self.held.clear(),
+            _ => return
+        };
+        match pressed {
+            true => {
+                self.held.entry(input).or_insert_with(Instant::now);
+            }
+            false => {
+                self.held.remove(&input);
+            }
+        }
+    }
+
+    /// Returns `true` if any key, button or gamepad input is currently active on the target
+    pub fn has_held_inputs(&self) -> bool {
+        !self.held.is_empty() || self.gamepad != GamepadState::default()
+    }
+
+    /// Queues a reset that releases every held input
+    pub fn release_all(&mut self) {
+        self.gamepad = GamepadState::default();
+        self.events.push_back(InputEvent::Reset);
+    }
+
+    /// Queues a release for every input that has been held for longer than `max`
+    pub fn release_expired(&mut self, max: Duration) {
+        let expired: Vec<HeldInput> = self
+            .held
+            .iter()
+            .filter(|(_, since)| since.elapsed() >= max)
+            .map(|(input, _)| *input)
+            .collect();
+        for input in expired {
+            tracing::warn!("{:?} was held for longer than {:?}, releasing it", input, max);
+            self.held.remove(&input);
+            self.events.push_back(input.release());
+        }
     }
 
     pub fn process_packet(&mut self, mut packet: &[u8]) -> Result<Option<&[u8]>> {
@@ -55,52 +170,84 @@ impl InputReceiver {
         let remote_mouse_pos = Vec2::new(packet.read_i64::<LittleEndian>()?, packet.read_i64::<LittleEndian>()?);
 
         if remote_mouse_pos != self.local_mouse_pos {
-            self.events.push_back(InputEvent::MouseMove(
-                remote_mouse_pos.x - self.local_mouse_pos.x,
-                remote_mouse_pos.y - self.local_mouse_pos.y
-            ));
+            let (x, y) = (remote_mouse_pos.x - self.local_mouse_pos.x, remote_mouse_pos.y - self.local_mouse_pos.y);
+            self.events.push_back(InputEvent::MouseMove(x, y));
+            self.pointer_moved(x, y);
             self.local_mouse_pos = remote_mouse_pos;
         }
 
         let start_message = packet.read_u64::<LittleEndian>()?;
         let diff = self.last_message.saturating_sub(start_message);
         let len = packet.read_u8()? as u64;
-        packet = &packet[(2 * diff as usize)..];
+        packet = &packet[(MESSAGE_SIZE * diff as usize)..];
         for i in diff..len {
             let msg_id = packet.read_u8()?;
-            let msg_arg = packet.read_u8()?;
+            let msg_arg = packet.read_u16::<LittleEndian>()?;
             match MessageType::try_from(msg_id) {
                 Ok(MessageType::KeyPress) => self
                     .events
-                    .push_back(InputEvent::KeyPress(HidKeyCode::from(msg_arg))),
+                    .push_back(InputEvent::KeyPress(HidKeyCode::from(msg_arg as u8))),
                 Ok(MessageType::KeyRelease) => self
                     .events
-                    .push_back(InputEvent::KeyRelease(HidKeyCode::from(msg_arg))),
+                    .push_back(InputEvent::KeyRelease(HidKeyCode::from(msg_arg as u8))),
                 Ok(MessageType::MouseButtonPress) => self
                     .events
-                    .push_back(InputEvent::MouseButtonPress(HidButtonCode::from(msg_arg))),
+                    .push_back(InputEvent::MouseButtonPress(HidButtonCode::from(msg_arg as u8))),
                 Ok(MessageType::MouseButtonRelease) => self
                     .events
-                    .push_back(InputEvent::MouseButtonRelease(HidButtonCode::from(msg_arg))),
+                    .push_back(InputEvent::MouseButtonRelease(HidButtonCode::from(msg_arg as u8))),
                 Ok(MessageType::ConsumerDevicePress) => self
                     .events
                     .push_back(InputEvent::ConsumerDevicePress(ConsumerDeviceCode::from(msg_arg))),
                 Ok(MessageType::ConsumerDeviceRelease) => self
                     .events
                     .push_back(InputEvent::ConsumerDeviceRelease(ConsumerDeviceCode::from(msg_arg))),
+                Ok(MessageType::SystemControlPress) => self
+                    .events
+                    .push_back(InputEvent::SystemControlPress(SystemControlCode::from(msg_arg as u8))),
+                Ok(MessageType::SystemControlRelease) => self
+                    .events
+                    .push_back(InputEvent::SystemControlRelease(SystemControlCode::from(msg_arg as u8))),
                 Ok(MessageType::HorizontalScrolling) => self
                     .events
-                    .push_back(InputEvent::HorizontalScrolling(msg_arg as i8)),
+                    .push_back(InputEvent::HorizontalScrolling(i16::from(msg_arg as u8 as i8) * SCROLL_RESOLUTION)),
                 Ok(MessageType::VerticalScrolling) => self
                     .events
-                    .push_back(InputEvent::VerticalScrolling(msg_arg as i8)),
-                Ok(MessageType::Reset) => self.events.push_back(InputEvent::Reset),
-                Ok(MessageType::Shutdown) => self.events.push_back(InputEvent::Shutdown),
+                    .push_back(InputEvent::VerticalScrolling(i16::from(msg_arg as u8 as i8) * SCROLL_RESOLUTION)),
+                Ok(MessageType::HighResHorizontalScrolling) => self
+                    .events
+                    .push_back(InputEvent::HorizontalScrolling(msg_arg as i16)),
+                Ok(MessageType::HighResVerticalScrolling) => self
+                    .events
+                    .push_back(InputEvent::VerticalScrolling(msg_arg as i16)),
+                Ok(
+                    msg @ (MessageType::GamepadButtons
+                    | MessageType::GamepadHat
+                    | MessageType::GamepadLeftStickX
+                    | MessageType::GamepadLeftStickY
+                    | MessageType::GamepadRightStickX
+                    | MessageType::GamepadRightStickY
+                    | MessageType::GamepadLeftTrigger
+                    | MessageType::GamepadRightTrigger)
+                ) => {
+                    self.gamepad.apply(msg, msg_arg);
+                    self.events.push_back(InputEvent::Gamepad(self.gamepad))
+                }
+                Ok(MessageType::Reset) => {
+                    self.gamepad = GamepadState::default();
+                    self.events.push_back(InputEvent::Reset)
+                }
+                Ok(MessageType::Shutdown) => tracing::warn!("Ignoring power request, they have to go through the control channel"),
                 Err(e) => tracing::warn!("Invalid message: {}", e)
             }
             self.last_message = start_message + i + 1;
         }
 
+        self.write_ack().map(Some)
+    }
+
+    /// Creates an acknowledgement for everything that was received so far
+    pub fn write_ack(&mut self) -> Result<&[u8]> {
         self.packet_buffer.clear();
         self.packet_buffer
             .write_u64::<LittleEndian>(self.local_sequence)?;
@@ -110,7 +257,11 @@ impl InputReceiver {
             .write_i64::<LittleEndian>(self.local_mouse_pos.y)?;
         self.packet_buffer
             .write_u64::<LittleEndian>(self.last_message)?;
+        self.packet_buffer.write_u8(self.status.bits())?;
+        self.packet_buffer.write_u8(self.edge_returns.count)?;
+        self.packet_buffer
+            .write_u16::<LittleEndian>(self.edge_returns.along)?;
         self.local_sequence += 1;
-        Ok(Some(self.packet_buffer.as_slice()))
+        Ok(self.packet_buffer.as_slice())
     }
 }
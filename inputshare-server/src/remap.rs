@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use inputshare_common::{ConsumerDeviceCode, HidButtonCode, HidKeyCode};
+use serde::Deserialize;
+use tokio::sync::watch;
+
+use crate::receiver::InputEvent;
+
+/// The remapping profiles of the server
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct RemapConfig {
+    /// Used for every client without a profile of its own
+    pub default: RemapProfile,
+    /// Profiles for specific client addresses, replacing the default profile
+    pub clients: HashMap<IpAddr, RemapProfile>
+}
+
+impl RemapConfig {
+    pub fn profile(&self, client: IpAddr) -> &RemapProfile {
+        // a server bound to [::] sees ipv4 clients as ipv4-mapped ipv6 addresses
+        self.clients
+            .get(&client.to_canonical())
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Translates the inputs of a client before they reach the devices
+///
+/// Inputs without an entry are passed through unchanged
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct RemapProfile {
+    /// The keys that are pressed instead, several keys form a combo and an empty list disables the key
+    pub keys: HashMap<HidKeyCode, Vec<HidKeyCode>>,
+    pub buttons: HashMap<HidButtonCode, HidButtonCode>,
+    pub consumer: HashMap<ConsumerDeviceCode, ConsumerDeviceCode>
+}
+
+/// Applies the current remapping profile of one client to its events
+///
+/// Every held input remembers what it was translated to, so that a reload can't leave keys stuck
+#[derive(Debug)]
+pub struct Remapper {
+    client: IpAddr,
+    config: watch::Receiver<Arc<RemapConfig>>,
+    keys: Held<HidKeyCode>,
+    buttons: Held<HidButtonCode>,
+    consumer: Held<ConsumerDeviceCode>
+}
+
+impl Remapper {
+    pub fn new(client: IpAddr, config: watch::Receiver<Arc<RemapConfig>>) -> Self {
+        Self {
+            client,
+            config,
+            keys: Held::default(),
+            buttons: Held::default(),
+            consumer: Held::default()
+        }
+    }
+
+    pub fn apply(&mut self, event: InputEvent) -> Vec<InputEvent> {
+        let config = self.config.borrow();
+        let profile = config.profile(self.client);
+        let keys = |key| profile.keys.get(&key).cloned().unwrap_or_else(|| vec![key]);
+        let buttons = |button| vec![profile.buttons.get(&button).copied().unwrap_or(button)];
+        let consumer = |code| vec![profile.consumer.get(&code).copied().unwrap_or(code)];
+        match event {
+            InputEvent::KeyPress(key) => map(self.keys.press(key, keys), InputEvent::KeyPress),
+            InputEvent::KeyRelease(key) => map(self.keys.release(key, keys), InputEvent::KeyRelease),
+            InputEvent::MouseButtonPress(button) => map(self.buttons.press(button, buttons), InputEvent::MouseButtonPress),
+            InputEvent::MouseButtonRelease(button) => map(self.buttons.release(button, buttons), InputEvent::MouseButtonRelease),
+            InputEvent::ConsumerDevicePress(code) => map(self.consumer.press(code, consumer), InputEvent::ConsumerDevicePress),
+            InputEvent::ConsumerDeviceRelease(code) => map(self.consumer.release(code, consumer), InputEvent::ConsumerDeviceRelease),
+            InputEvent::Reset => {
+                self.keys.clear();
+                self.buttons.clear();
+                self.consumer.clear();
+                vec![InputEvent::Reset]
+            }
+            event => vec![event]
+        }
+    }
+}
+
+fn map<T>(targets: Vec<T>, event: fn(T) -> InputEvent) -> Vec<InputEvent> {
+    targets.into_iter().map(event).collect()
+}
+
+/// The held inputs of one kind together with the targets they were translated to
+///
+/// A target that is held by several inputs, for example a modifier shared by two combos, is only released with the last of them.
+#[derive(Debug)]
+struct Held<T> {
+    inputs: HashMap<T, Vec<T>>,
+    targets: HashMap<T, usize>
+}
+
+impl<T> Default for Held<T> {
+    fn default() -> Self {
+        Self {
+            inputs: HashMap::new(),
+            targets: HashMap::new()
+        }
+    }
+}
+
+impl<T: Copy + Eq + Hash> Held<T> {
+    /// Returns the targets that have to be pressed, which excludes the ones that are held already
+    fn press(&mut self, input: T, targets: impl FnOnce(T) -> Vec<T>) -> Vec<T> {
+        if self.inputs.contains_key(&input) {
+            return Vec::new();
+        }
+        let targets = targets(input);
+        let pressed = targets
+            .iter()
+            .copied()
+            .filter(|target| {
+                let holders = self.targets.entry(*target).or_insert(0);
+                *holders += 1;
+                *holders == 1
+            })
+            .collect();
+        self.inputs.insert(input, targets);
+        pressed
+    }
+
+    /// Returns the targets that have to be released in reverse order, which excludes the ones that are still held by other inputs
+    fn release(&mut self, input: T, targets: impl FnOnce(T) -> Vec<T>) -> Vec<T> {
+        match self.inputs.remove(&input) {
+            Some(targets) => targets
+                .into_iter()
+                .rev()
+                .filter(|target| match self.targets.get_mut(target) {
+                    Some(holders) if *holders > 1 => {
+                        *holders -= 1;
+                        false
+                    }
+                    _ => self.targets.remove(target).is_some()
+                })
+                .collect(),
+            // the input was pressed before a reset, so only the current mapping is known
+            None => targets(input)
+                .into_iter()
+                .rev()
+                .filter(|target| !self.targets.contains_key(target))
+                .collect()
+        }
+    }
+
+    fn clear(&mut self) {
+        self.inputs.clear();
+        self.targets.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+
+    fn profile(keys: &[(HidKeyCode, &[HidKeyCode])]) -> RemapProfile {
+        RemapProfile {
+            keys: keys
+                .iter()
+                .map(|(key, targets)| (*key, targets.to_vec()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn remapper(default: RemapProfile) -> (Remapper, watch::Sender<Arc<RemapConfig>>) {
+        let (sender, receiver) = watch::channel(Arc::new(RemapConfig {
+            default,
+            clients: HashMap::new()
+        }));
+        (Remapper::new(CLIENT, receiver), sender)
+    }
+
+    fn reload(sender: &watch::Sender<Arc<RemapConfig>>, default: RemapProfile) {
+        sender.send_replace(Arc::new(RemapConfig {
+            default,
+            clients: HashMap::new()
+        }));
+    }
+
+    #[test]
+    fn unmapped_inputs_pass_through() {
+        let (mut remapper, _config) = remapper(RemapProfile::default());
+        for event in [
+            InputEvent::KeyPress(HidKeyCode::KeyA),
+            InputEvent::KeyRelease(HidKeyCode::KeyA),
+            InputEvent::MouseButtonPress(HidButtonCode::LButton),
+            InputEvent::MouseMove(3, 4),
+            InputEvent::VerticalScrolling(120)
+        ] {
+            assert_eq!(remapper.apply(event), [event]);
+        }
+    }
+
+    #[test]
+    fn combos_are_pressed_in_order_and_released_in_reverse() {
+        let (mut remapper, _config) = remapper(profile(&[(HidKeyCode::Capslock, &[HidKeyCode::LeftCtrl, HidKeyCode::KeyC])]));
+        assert_eq!(
+            remapper.apply(InputEvent::KeyPress(HidKeyCode::Capslock)),
+            [InputEvent::KeyPress(HidKeyCode::LeftCtrl), InputEvent::KeyPress(HidKeyCode::KeyC)]
+        );
+        assert_eq!(
+            remapper.apply(InputEvent::KeyRelease(HidKeyCode::Capslock)),
+            [InputEvent::KeyRelease(HidKeyCode::KeyC), InputEvent::KeyRelease(HidKeyCode::LeftCtrl)]
+        );
+    }
+
+    #[test]
+    fn shared_targets_are_released_with_their_last_holder() {
+        let (mut remapper, _config) = remapper(RemapProfile {
+            buttons: HashMap::from([(HidButtonCode::Button4, HidButtonCode::MButton)]),
+            ..profile(&[
+                (HidKeyCode::Capslock, &[HidKeyCode::LeftCtrl, HidKeyCode::KeyC]),
+                (HidKeyCode::Insert, &[HidKeyCode::LeftCtrl, HidKeyCode::KeyV])
+            ])
+        });
+        assert_eq!(
+            remapper.apply(InputEvent::KeyPress(HidKeyCode::Capslock)),
+            [InputEvent::KeyPress(HidKeyCode::LeftCtrl), InputEvent::KeyPress(HidKeyCode::KeyC)]
+        );
+        assert_eq!(
+            remapper.apply(InputEvent::KeyPress(HidKeyCode::Insert)),
+            [InputEvent::KeyPress(HidKeyCode::KeyV)]
+        );
+        assert_eq!(
+            remapper.apply(InputEvent::KeyRelease(HidKeyCode::Capslock)),
+            [InputEvent::KeyRelease(HidKeyCode::KeyC)]
+        );
+        assert_eq!(
+            remapper.apply(InputEvent::KeyRelease(HidKeyCode::Insert)),
+            [InputEvent::KeyRelease(HidKeyCode::KeyV), InputEvent::KeyRelease(HidKeyCode::LeftCtrl)]
+        );
+
+        assert_eq!(
+            remapper.apply(InputEvent::MouseButtonPress(HidButtonCode::MButton)),
+            [InputEvent::MouseButtonPress(HidButtonCode::MButton)]
+        );
+        assert!(remapper
+            .apply(InputEvent::MouseButtonPress(HidButtonCode::Button4))
+            .is_empty());
+        assert!(remapper
+            .apply(InputEvent::MouseButtonRelease(HidButtonCode::MButton))
+            .is_empty());
+        assert_eq!(
+            remapper.apply(InputEvent::MouseButtonRelease(HidButtonCode::Button4)),
+            [InputEvent::MouseButtonRelease(HidButtonCode::MButton)]
+        );
+    }
+
+    #[test]
+    fn repeated_presses_are_ignored() {
+        let (mut remapper, _config) = remapper(profile(&[(HidKeyCode::KeyA, &[HidKeyCode::KeyB])]));
+        assert_eq!(
+            remapper.apply(InputEvent::KeyPress(HidKeyCode::KeyA)),
+            [InputEvent::KeyPress(HidKeyCode::KeyB)]
+        );
+        assert!(remapper
+            .apply(InputEvent::KeyPress(HidKeyCode::KeyA))
+            .is_empty());
+        assert_eq!(
+            remapper.apply(InputEvent::KeyRelease(HidKeyCode::KeyA)),
+            [InputEvent::KeyRelease(HidKeyCode::KeyB)]
+        );
+    }
+
+    #[test]
+    fn disabled_keys_produce_nothing() {
+        let (mut remapper, _config) = remapper(profile(&[(HidKeyCode::Insert, &[])]));
+        assert!(remapper
+            .apply(InputEvent::KeyPress(HidKeyCode::Insert))
+            .is_empty());
+        assert!(remapper
+            .apply(InputEvent::KeyRelease(HidKeyCode::Insert))
+            .is_empty());
+    }
+
+    #[test]
+    fn held_inputs_are_released_as_what_they_were_pressed_as() {
+        let (mut remapper, config) = remapper(RemapProfile {
+            buttons: HashMap::from([(HidButtonCode::Button4, HidButtonCode::MButton)]),
+            ..profile(&[(HidKeyCode::KeyA, &[HidKeyCode::KeyB])])
+        });
+        assert_eq!(
+            remapper.apply(InputEvent::KeyPress(HidKeyCode::KeyA)),
+            [InputEvent::KeyPress(HidKeyCode::KeyB)]
+        );
+        assert_eq!(
+            remapper.apply(InputEvent::MouseButtonPress(HidButtonCode::Button4)),
+            [InputEvent::MouseButtonPress(HidButtonCode::MButton)]
+        );
+
+        reload(&config, profile(&[(HidKeyCode::KeyA, &[HidKeyCode::KeyC])]));
+        assert_eq!(
+            remapper.apply(InputEvent::KeyRelease(HidKeyCode::KeyA)),
+            [InputEvent::KeyRelease(HidKeyCode::KeyB)]
+        );
+        assert_eq!(
+            remapper.apply(InputEvent::MouseButtonRelease(HidButtonCode::Button4)),
+            [InputEvent::MouseButtonRelease(HidButtonCode::MButton)]
+        );
+
+        assert_eq!(
+            remapper.apply(InputEvent::KeyPress(HidKeyCode::KeyA)),
+            [InputEvent::KeyPress(HidKeyCode::KeyC)]
+        );
+        assert_eq!(
+            remapper.apply(InputEvent::MouseButtonPress(HidButtonCode::Button4)),
+            [InputEvent::MouseButtonPress(HidButtonCode::Button4)]
+        );
+    }
+
+    #[test]
+    fn reset_forgets_held_inputs() {
+        let (mut remapper, config) = remapper(RemapProfile {
+            consumer: HashMap::from([(ConsumerDeviceCode::VolumeUp, ConsumerDeviceCode::VolumeDown)]),
+            ..profile(&[(HidKeyCode::KeyA, &[HidKeyCode::KeyB])])
+        });
+        remapper.apply(InputEvent::KeyPress(HidKeyCode::KeyA));
+        assert_eq!(
+            remapper.apply(InputEvent::ConsumerDevicePress(ConsumerDeviceCode::VolumeUp)),
+            [InputEvent::ConsumerDevicePress(ConsumerDeviceCode::VolumeDown)]
+        );
+        assert_eq!(remapper.apply(InputEvent::Reset), [InputEvent::Reset]);
+
+        reload(&config, RemapProfile::default());
+        assert_eq!(
+            remapper.apply(InputEvent::KeyRelease(HidKeyCode::KeyA)),
+            [InputEvent::KeyRelease(HidKeyCode::KeyA)]
+        );
+        assert_eq!(
+            remapper.apply(InputEvent::ConsumerDeviceRelease(ConsumerDeviceCode::VolumeUp)),
+            [InputEvent::ConsumerDeviceRelease(ConsumerDeviceCode::VolumeUp)]
+        );
+    }
+
+    #[test]
+    fn client_profiles_match_ipv4_mapped_addresses() {
+        let config = RemapConfig {
+            default: RemapProfile::default(),
+            clients: HashMap::from([(CLIENT, profile(&[(HidKeyCode::KeyA, &[HidKeyCode::KeyB])]))])
+        };
+        let mapped = IpAddr::V6(Ipv4Addr::new(192, 168, 1, 20).to_ipv6_mapped());
+        let (_sender, receiver) = watch::channel(Arc::new(config));
+        let mut remapper = Remapper::new(mapped, receiver);
+        assert_eq!(
+            remapper.apply(InputEvent::KeyPress(HidKeyCode::KeyA)),
+            [InputEvent::KeyPress(HidKeyCode::KeyB)]
+        );
+
+        let other = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 21));
+        assert!(remapper.config.borrow().profile(other).keys.is_empty());
+    }
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use inputshare_common::DeviceStatus;
+use tokio::sync::{mpsc, watch, Notify};
+
+use crate::queue::EventReceiver;
+use crate::receiver::InputEvent;
+use crate::sink::{InputSink, SinkFuture};
+
+/// Hands every event to a test, which also controls the status
+pub struct Collector {
+    events: mpsc::UnboundedSender<InputEvent>,
+    status: watch::Receiver<DeviceStatus>,
+    gate: Option<Arc<Notify>>
+}
+
+impl Collector {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<InputEvent>, watch::Sender<DeviceStatus>) {
+        let (events, receiver) = mpsc::unbounded_channel();
+        let (status_sender, status) = watch::channel(DeviceStatus::empty());
+        (Self { events, status, gate: None }, receiver, status_sender)
+    }
+
+    /// A collector that does not read any event until the test opens the gate
+    pub fn gated() -> (Self, mpsc::UnboundedReceiver<InputEvent>, Arc<Notify>) {
+        let (collector, receiver, _) = Self::new();
+        let gate = Arc::new(Notify::new());
+        let collector = Self {
+            gate: Some(gate.clone()),
+            ..collector
+        };
+        (collector, receiver, gate)
+    }
+}
+
+impl InputSink for Collector {
+    fn status(&self) -> watch::Receiver<DeviceStatus> {
+        self.status.clone()
+    }
+
+    fn run(self: Box<Self>, mut events: EventReceiver) -> SinkFuture {
+        Box::pin(async move {
+            if let Some(gate) = self.gate {
+                gate.notified().await;
+            }
+            while let Some(event) = events.recv().await {
+                // the test might not care about the remaining events
+                self.events.send(event).ok();
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,399 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use inputshare_common::{DeviceStatus, HidKeyCode, PresencePattern, PresenceSettings, SCROLL_RESOLUTION};
+use tokio::select;
+#[cfg(test)]
+use tokio::sync::mpsc;
+use tokio::sync::watch;
+use tokio::time::{sleep, sleep_until, Instant};
+use tracing::{instrument, Instrument};
+
+use crate::config::PresenceConfig;
+use crate::configfs::{self, ProtocolMode, StatusSender, UdcState};
+use crate::presence::PresenceKeeper;
+use crate::queue::EventReceiver;
+use crate::receiver::InputEvent;
+use crate::sink::{InputSink, SinkFuture};
+
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(30);
+/// A processor that ran for at least this long resets the restart delay
+const HEALTHY_RUNTIME: Duration = Duration::from_secs(30);
+/// How long events are held back for a suspended host to resume before they are discarded
+const RESUME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Emulates the devices through a usb gadget, recreating the gadget if it fails
+pub struct ConfigFsSink {
+    mode: ProtocolMode,
+    presence: PresenceKeeper,
+    devices: Option<ConfigFsDevices>,
+    status: StatusSender,
+    status_receiver: watch::Receiver<DeviceStatus>
+}
+
+impl ConfigFsSink {
+    /// Creates the gadget right away, so that a missing udc shows up on startup
+    #[instrument(skip_all)]
+    pub async fn open(mode: ProtocolMode, presence: &PresenceConfig, presence_settings: watch::Receiver<PresenceSettings>) -> Result<Self> {
+        let (status, status_receiver) = watch::channel(DeviceStatus::empty());
+        let status = Arc::new(status);
+        let devices = ConfigFsDevices::open(mode, status.clone()).await?;
+        Ok(Self {
+            mode,
+            presence: PresenceKeeper::new(presence, presence_settings),
+            devices: Some(devices),
+            status,
+            status_receiver
+        })
+    }
+}
+
+impl InputSink for ConfigFsSink {
+    fn status(&self) -> watch::Receiver<DeviceStatus> {
+        self.status_receiver.clone()
+    }
+
+    fn run(self: Box<Self>, mut events: EventReceiver) -> SinkFuture {
+        let ConfigFsSink {
+            mode,
+            mut presence,
+            mut devices,
+            status,
+            ..
+        } = *self;
+        Box::pin(
+            async move {
+                tracing::debug!("Starting configfs processor");
+                let mut delay = MIN_RESTART_DELAY;
+                loop {
+                    let started = Instant::now();
+                    let result = match devices.take() {
+                        Some(devices) => Ok(devices),
+                        None => ConfigFsDevices::open(mode, status.clone()).await
+                    };
+                    let result = match result {
+                        Ok(devices) => {
+                            set_status(&status, DeviceStatus::empty());
+                            process_configfs_events(devices, &mut events, &mut presence).await
+                        }
+                        Err(err) => Err(err)
+                    };
+                    match result {
+                        Ok(()) => break,
+                        Err(err) => tracing::error!("The input processor failed: {:#}", err)
+                    }
+                    if started.elapsed() >= HEALTHY_RUNTIME {
+                        delay = MIN_RESTART_DELAY;
+                    }
+                    set_status(&status, DeviceStatus::UNAVAILABLE);
+                    tracing::info!("Restarting the input processor in {:?}", delay);
+                    if !discard_events(&mut events, delay).await {
+                        break;
+                    }
+                    delay = (delay * 2).min(MAX_RESTART_DELAY);
+                }
+                tracing::debug!("Stopping configfs processor");
+                Ok(())
+            }
+            .in_current_span()
+        )
+    }
+}
+
+fn set_status(sender: &watch::Sender<DeviceStatus>, status: DeviceStatus) {
+    sender.send_if_modified(|current| std::mem::replace(current, status) != status);
+}
+
+/// Throws away all events until `delay` has passed
+///
+/// Returns `false` if all senders are gone
+async fn discard_events(receiver: &mut EventReceiver, delay: Duration) -> bool {
+    let deadline = sleep(delay);
+    tokio::pin!(deadline);
+    loop {
+        select! {
+            _ = &mut deadline => return true,
+            event = receiver.recv() => match event {
+                Some(event) => tracing::trace!("Target unavailable, discarding {:?}", event),
+                None => return false
+            }
+        }
+    }
+}
+
+/// The hid functions of the gadget
+pub struct HidDevices {
+    keyboard: configfs::Keyboard,
+    mouse: configfs::Mouse,
+    consumer_device: configfs::ConsumerDevice,
+    system_control: configfs::SystemControl,
+    gamepad: configfs::Gamepad
+}
+
+/// The reports that the functions of in-memory `HidDevices` wrote
+#[cfg(test)]
+pub struct HidReports {
+    pub keyboard: mpsc::UnboundedReceiver<Vec<u8>>,
+    pub mouse: mpsc::UnboundedReceiver<Vec<u8>>,
+    pub consumer_device: mpsc::UnboundedReceiver<Vec<u8>>,
+    pub system_control: mpsc::UnboundedReceiver<Vec<u8>>,
+    pub gamepad: mpsc::UnboundedReceiver<Vec<u8>>
+}
+
+impl HidDevices {
+    async fn open(mode: ProtocolMode, status: StatusSender) -> Result<Self> {
+        use configfs::*;
+        Ok(Self {
+            keyboard: Keyboard::new(mode, status.clone()).await?,
+            mouse: Mouse::new(mode, status.clone()).await?,
+            consumer_device: ConsumerDevice::new(mode, status.clone()).await?,
+            system_control: SystemControl::new(mode, status.clone()).await?,
+            gamepad: Gamepad::new(mode, status).await?
+        })
+    }
+
+    /// Creates devices that write their reports to channels instead of a gadget
+    #[cfg(test)]
+    pub fn in_memory(mode: ProtocolMode) -> (Self, HidReports) {
+        use configfs::*;
+        let status = Arc::new(watch::channel(DeviceStatus::empty()).0);
+        let (keyboard, keyboard_reports) = ReportWriter::in_memory(DeviceStatus::KEYBOARD_STALLED, status.clone());
+        let (mouse, mouse_reports) = ReportWriter::in_memory(DeviceStatus::MOUSE_STALLED, status.clone());
+        let (consumer_device, consumer_device_reports) = ReportWriter::in_memory(DeviceStatus::CONSUMER_DEVICE_STALLED, status.clone());
+        let (system_control, system_control_reports) = ReportWriter::in_memory(DeviceStatus::SYSTEM_CONTROL_STALLED, status.clone());
+        let (gamepad, gamepad_reports) = ReportWriter::in_memory(DeviceStatus::GAMEPAD_STALLED, status);
+        let devices = Self {
+            keyboard: Keyboard::with_writer(keyboard),
+            mouse: Mouse::with_writer(mode, mouse),
+            consumer_device: ConsumerDevice::with_writer(consumer_device),
+            system_control: SystemControl::with_writer(system_control),
+            gamepad: Gamepad::with_writer(gamepad)
+        };
+        let reports = HidReports {
+            keyboard: keyboard_reports,
+            mouse: mouse_reports,
+            consumer_device: consumer_device_reports,
+            system_control: system_control_reports,
+            gamepad: gamepad_reports
+        };
+        (devices, reports)
+    }
+
+    pub async fn reset(&mut self) -> Result<()> {
+        self.keyboard.reset().await?;
+        self.mouse.reset().await?;
+        self.consumer_device.reset().await?;
+        self.system_control.reset().await?;
+        self.gamepad.reset().await?;
+        Ok(())
+    }
+
+    /// Queues the reports for an event
+    ///
+    /// Movements are spread over multiple reports, `step` has to be called for the rest of them.
+    pub async fn apply(&mut self, event: InputEvent) -> Result<()> {
+        match event {
+            InputEvent::MouseMove(x, y) => self.mouse.move_by(x, y).await,
+            InputEvent::KeyPress(key) => self.keyboard.press_key(key).await,
+            InputEvent::KeyRelease(key) => self.keyboard.release_key(key).await,
+            InputEvent::MouseButtonPress(button) => self.mouse.press_button(button).await,
+            InputEvent::MouseButtonRelease(button) => self.mouse.release_button(button).await,
+            InputEvent::ConsumerDevicePress(button) => self.consumer_device.press_key(button).await,
+            InputEvent::ConsumerDeviceRelease(button) => self.consumer_device.release_key(button).await,
+            InputEvent::SystemControlPress(button) => self.system_control.press_key(button).await,
+            InputEvent::SystemControlRelease(button) => self.system_control.release_key(button).await,
+            InputEvent::HorizontalScrolling(amount) => self.mouse.scroll_horizontal(amount).await,
+            InputEvent::VerticalScrolling(amount) => self.mouse.scroll_vertical(amount).await,
+            InputEvent::Gamepad(state) => self.gamepad.send_state(&state).await,
+            InputEvent::Reset => self.reset().await
+        }
+    }
+
+    /// The point in time at which the next step of a movement is due, if there is one
+    pub fn next_step(&self) -> Option<Instant> {
+        self.mouse.is_moving().then(|| self.mouse.next_step())
+    }
+
+    pub async fn step(&mut self) -> Result<()> {
+        self.mouse.step().await
+    }
+
+    /// Produces an input that keeps the target from going idle without visibly changing anything
+    async fn nudge(&mut self, pattern: PresencePattern, direction: i8) -> Result<()> {
+        match pattern {
+            PresencePattern::Wiggle => self.mouse.move_by(direction.into(), 0).await,
+            PresencePattern::Key => {
+                self.keyboard.press_key(HidKeyCode::F15).await?;
+                self.keyboard.release_key(HidKeyCode::F15).await
+            }
+            PresencePattern::Scroll => {
+                self.mouse.scroll_horizontal(SCROLL_RESOLUTION).await?;
+                self.mouse.scroll_horizontal(-SCROLL_RESOLUTION).await
+            }
+        }
+    }
+}
+
+/// All hid functions of the gadget and the controller it is attached to
+///
+/// Dropping the last instance removes the gadget, opening a new instance creates it again
+struct ConfigFsDevices {
+    hid: HidDevices,
+    udc: configfs::UdcMonitor
+}
+
+impl ConfigFsDevices {
+    async fn open(mode: ProtocolMode, status: StatusSender) -> Result<Self> {
+        Ok(Self {
+            hid: HidDevices::open(mode, status).await?,
+            udc: configfs::UdcMonitor::start(configfs::Udc::find()?).await?
+        })
+    }
+
+    async fn reset(&mut self) -> Result<()> {
+        match self.udc.udc().polling_interval().await {
+            Ok(interval) => self.hid.mouse.set_polling_interval(interval),
+            Err(err) => tracing::warn!("Could not determine the polling interval: {}", err)
+        }
+        self.hid.reset().await
+    }
+
+    async fn apply(&mut self, event: InputEvent) -> Result<()> {
+        match event {
+            InputEvent::Reset => self.reset().await,
+            event => self.hid.apply(event).await
+        }
+    }
+}
+
+/// Holds back the events that arrive while the host is suspended, so that they reach it once it resumed
+#[derive(Debug, Default)]
+struct ResumeBuffer {
+    events: VecDeque<InputEvent>,
+    deadline: Option<Instant>
+}
+
+impl ResumeBuffer {
+    /// Returns `true` if the event has to wait for the host to resume
+    ///
+    /// Once an event is held back all following events are too, so that they stay in order.
+    fn hold(&mut self, event: InputEvent, state: UdcState, now: Instant) -> bool {
+        if state != UdcState::Suspended && self.events.is_empty() {
+            return false;
+        }
+        self.deadline.get_or_insert(now + RESUME_TIMEOUT);
+        self.events.push_back(event);
+        true
+    }
+
+    /// The point in time at which the held events get discarded
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn take(&mut self) -> VecDeque<InputEvent> {
+        self.deadline = None;
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// Writes events to the devices until all senders are gone or a device fails
+async fn process_configfs_events(mut devices: ConfigFsDevices, receiver: &mut EventReceiver, presence: &mut PresenceKeeper) -> Result<()> {
+    let mut held = ResumeBuffer::default();
+    loop {
+        let nudge = presence.deadline();
+        let resume = held.deadline();
+        let step = devices.hid.next_step();
+        select! {
+            event = receiver.recv() => match event {
+                Some(event) => {
+                    if !matches!(event, InputEvent::Reset) {
+                        presence.input_received();
+                        if let Err(err) = devices.udc.wake_up_host().await {
+                            tracing::warn!("Could not wake up the host: {}", err);
+                        }
+                    }
+                    if !held.hold(event, devices.udc.state(), Instant::now()) {
+                        devices.apply(event).await.context("Could not write hid command")?;
+                    }
+                },
+                None => return Ok(())
+            },
+            _ = sleep_until(resume.unwrap_or_else(Instant::now)), if resume.is_some() => {
+                let discarded = held.take();
+                tracing::warn!("The host did not resume within {:?}, discarding {} events", RESUME_TIMEOUT, discarded.len());
+                // some of the discarded events might have released inputs that were pressed before the host got suspended
+                devices.reset().await.context("Could not write hid command")?;
+            },
+            _ = sleep_until(step.unwrap_or_else(Instant::now)), if step.is_some() => {
+                devices.hid.step().await.context("Could not write hid command")?;
+            },
+            _ = sleep_until(nudge.unwrap_or_else(Instant::now)), if nudge.is_some() => {
+                if let Some((pattern, direction)) = presence.next_nudge() {
+                    match devices.udc.state() {
+                        UdcState::Configured => {
+                            tracing::trace!("Keeping the target awake with {:?}", pattern);
+                            devices.hid.nudge(pattern, direction).await.context("Could not write hid command")?;
+                        }
+                        state => tracing::trace!("Not keeping the target awake while it is {:?}", state)
+                    }
+                }
+            },
+            state = devices.udc.changed() => {
+                tracing::debug!("The udc is {:?} now", state);
+                // a suspended host keeps its configuration
+                if !matches!(state, UdcState::Configured | UdcState::Suspended) {
+                    devices.hid.mouse.reset_multipliers();
+                }
+                if state == UdcState::Configured {
+                    let events = held.take();
+                    if !events.is_empty() {
+                        tracing::debug!("The host resumed, replaying {} events", events.len());
+                    }
+                    for event in events {
+                        devices.apply(event).await.context("Could not write hid command")?;
+                    }
+                }
+            },
+            Ok(()) = presence.changed() => { }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_pass_while_the_host_is_awake() {
+        let mut held = ResumeBuffer::default();
+        assert!(!held.hold(InputEvent::KeyPress(HidKeyCode::KeyA), UdcState::Configured, Instant::now()));
+        assert_eq!(held.deadline(), None);
+    }
+
+    #[test]
+    fn events_are_held_until_the_host_resumed() {
+        let now = Instant::now();
+        let mut held = ResumeBuffer::default();
+        assert!(held.hold(InputEvent::KeyPress(HidKeyCode::KeyA), UdcState::Suspended, now));
+        let later = now + Duration::from_secs(1);
+        assert!(held.hold(InputEvent::KeyRelease(HidKeyCode::KeyA), UdcState::Suspended, later));
+        assert_eq!(held.deadline(), Some(now + RESUME_TIMEOUT));
+
+        // the host already resumed, but the held events have not been replayed yet
+        assert!(held.hold(InputEvent::MouseMove(1, 2), UdcState::Configured, now + Duration::from_secs(2)));
+        assert_eq!(
+            held.take(),
+            [
+                InputEvent::KeyPress(HidKeyCode::KeyA),
+                InputEvent::KeyRelease(HidKeyCode::KeyA),
+                InputEvent::MouseMove(1, 2)
+            ]
+        );
+        assert_eq!(held.deadline(), None);
+        assert!(!held.hold(InputEvent::Reset, UdcState::Configured, now + Duration::from_secs(3)));
+    }
+}
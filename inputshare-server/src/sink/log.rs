@@ -0,0 +1,40 @@
+use inputshare_common::DeviceStatus;
+use tokio::sync::watch;
+use tracing::Instrument;
+
+use crate::queue::EventReceiver;
+use crate::sink::{InputSink, SinkFuture};
+
+/// Prints every event to the console
+pub struct LogSink {
+    status: watch::Sender<DeviceStatus>
+}
+
+impl LogSink {
+    pub fn new() -> Self {
+        Self {
+            status: watch::channel(DeviceStatus::empty()).0
+        }
+    }
+}
+
+impl InputSink for LogSink {
+    fn status(&self) -> watch::Receiver<DeviceStatus> {
+        self.status.subscribe()
+    }
+
+    fn run(self: Box<Self>, mut events: EventReceiver) -> SinkFuture {
+        Box::pin(
+            async move {
+                let _status = self.status;
+                tracing::debug!("Starting print processor");
+                while let Some(event) = events.recv().await {
+                    tracing::info!("New input event: {:?}", event);
+                }
+                tracing::debug!("Stopping print processor");
+                Ok(())
+            }
+            .in_current_span()
+        )
+    }
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use inputshare_common::DeviceStatus;
+use tokio::select;
+use tokio::sync::watch;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::Instrument;
+
+use crate::queue::EventReceiver;
+use crate::receiver::InputEvent;
+use crate::sink::{InputSink, SinkFuture};
+
+/// How often the metrics are logged
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Counts the events and logs how many of each kind arrived once per `REPORT_INTERVAL`
+pub struct MetricsSink {
+    status: watch::Sender<DeviceStatus>
+}
+
+impl MetricsSink {
+    pub fn new() -> Self {
+        Self {
+            status: watch::channel(DeviceStatus::empty()).0
+        }
+    }
+}
+
+impl InputSink for MetricsSink {
+    fn status(&self) -> watch::Receiver<DeviceStatus> {
+        self.status.subscribe()
+    }
+
+    fn run(self: Box<Self>, mut events: EventReceiver) -> SinkFuture {
+        Box::pin(
+            async move {
+                let _status = self.status;
+                let mut metrics = Metrics::default();
+                let mut timer = interval(REPORT_INTERVAL);
+                timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                timer.tick().await;
+                loop {
+                    select! {
+                        event = events.recv() => match event {
+                            Some(event) => metrics.count(&event),
+                            None => break
+                        },
+                        _ = timer.tick() => {
+                            if !metrics.is_empty() {
+                                tracing::info!("Input in the last {:?}: {}", REPORT_INTERVAL, metrics);
+                                metrics = Metrics::default();
+                            }
+                        }
+                    }
+                }
+                if !metrics.is_empty() {
+                    tracing::info!("Input since the last report: {}", metrics);
+                }
+                Ok(())
+            }
+            .in_current_span()
+        )
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Metrics {
+    key_presses: u64,
+    clicks: u64,
+    movements: u64,
+    /// The absolute movement along both axes, in mouse counts
+    distance: u64,
+    scrolls: u64,
+    /// Media keys, system control keys and gamepad updates
+    other: u64,
+    resets: u64
+}
+
+impl Metrics {
+    fn count(&mut self, event: &InputEvent) {
+        match *event {
+            InputEvent::MouseMove(x, y) => {
+                self.movements += 1;
+                self.distance = self
+                    .distance
+                    .saturating_add(x.unsigned_abs())
+                    .saturating_add(y.unsigned_abs());
+            }
+            InputEvent::KeyPress(_) => self.key_presses += 1,
+            InputEvent::MouseButtonPress(_) => self.clicks += 1,
+            InputEvent::HorizontalScrolling(_) | InputEvent::VerticalScrolling(_) => self.scrolls += 1,
+            InputEvent::ConsumerDevicePress(_) | InputEvent::SystemControlPress(_) | InputEvent::Gamepad(_) => self.other += 1,
+            InputEvent::Reset => self.resets += 1,
+            InputEvent::KeyRelease(_)
+            | InputEvent::MouseButtonRelease(_)
+            | InputEvent::ConsumerDeviceRelease(_)
+            | InputEvent::SystemControlRelease(_) => {}
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl std::fmt::Display for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} key presses, {} clicks, {} movements over {} counts, {} scrolls, {} other inputs, {} resets",
+            self.key_presses, self.clicks, self.movements, self.distance, self.scrolls, self.other, self.resets
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use inputshare_common::{HidButtonCode, HidKeyCode};
+
+    use super::*;
+
+    #[test]
+    fn presses_and_movements_are_counted() {
+        let mut metrics = Metrics::default();
+        assert!(metrics.is_empty());
+        for event in [
+            InputEvent::KeyPress(HidKeyCode::KeyA),
+            InputEvent::KeyRelease(HidKeyCode::KeyA),
+            InputEvent::MouseMove(3, -4),
+            InputEvent::MouseMove(-1, 0),
+            InputEvent::MouseButtonPress(HidButtonCode::LButton),
+            InputEvent::MouseButtonRelease(HidButtonCode::LButton),
+            InputEvent::VerticalScrolling(-120),
+            InputEvent::Reset
+        ] {
+            metrics.count(&event);
+        }
+        assert_eq!(
+            metrics,
+            Metrics {
+                key_presses: 1,
+                clicks: 1,
+                movements: 2,
+                distance: 8,
+                scrolls: 1,
+                other: 0,
+                resets: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,276 @@
+#[cfg(test)]
+mod collector;
+mod configfs;
+mod log;
+mod metrics;
+mod record;
+#[cfg(target_os = "linux")]
+mod uinput;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{ensure, Result};
+use inputshare_common::{DeviceStatus, PresenceSettings};
+use tokio::spawn;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tracing::Instrument;
+
+#[cfg(test)]
+pub use self::collector::Collector;
+pub use self::configfs::ConfigFsSink;
+#[cfg(test)]
+pub use self::configfs::HidDevices;
+pub use self::log::LogSink;
+pub use self::metrics::MetricsSink;
+pub use self::record::RecordSink;
+#[cfg(target_os = "linux")]
+pub use self::uinput::UinputSink;
+use crate::config::{OutputConfig, PresenceConfig};
+use crate::configfs::ProtocolMode;
+use crate::queue::{self, event_queue, EventReceiver, EventSender};
+use crate::receiver::InputEvent;
+use crate::{InputProcessor, EVENT_QUEUE_CAPACITY};
+
+pub type SinkFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+/// A destination for the events of all connections
+pub trait InputSink: Send {
+    /// The status that is sent to the clients with every acknowledgement
+    fn status(&self) -> watch::Receiver<DeviceStatus>;
+
+    /// Processes events until all senders are gone
+    fn run(self: Box<Self>, events: EventReceiver) -> SinkFuture;
+}
+
+/// Runs the sink on its own task
+pub fn start(sink: Box<dyn InputSink>) -> InputProcessor {
+    let (events, receiver) = event_queue(EVENT_QUEUE_CAPACITY);
+    let status = sink.status();
+    spawn(queue::log_stats(events.monitor()).in_current_span());
+    run(sink, receiver);
+    InputProcessor { events, status }
+}
+
+fn run(sink: Box<dyn InputSink>, events: EventReceiver) -> JoinHandle<()> {
+    spawn(
+        async move {
+            if let Err(err) = sink.run(events).await {
+                tracing::error!("The input sink failed: {:#}", err);
+            }
+        }
+        .in_current_span()
+    )
+}
+
+/// Creates the sinks for `outputs`, combining them if there is more than one
+///
+/// Uses the usb gadget if there are no outputs
+pub async fn open(
+    outputs: &[OutputConfig], mode: ProtocolMode, presence: &PresenceConfig, presence_settings: watch::Receiver<PresenceSettings>
+) -> Result<Box<dyn InputSink>> {
+    let default = [OutputConfig::ConfigFs];
+    let outputs = match outputs.is_empty() {
+        true => &default[..],
+        false => outputs
+    };
+    let gadgets = outputs
+        .iter()
+        .filter(|output| matches!(output, OutputConfig::ConfigFs))
+        .count();
+    ensure!(gadgets <= 1, "There can only be a single ConfigFs output");
+    let virtual_devices = outputs
+        .iter()
+        .filter(|output| matches!(output, OutputConfig::Uinput))
+        .count();
+    ensure!(virtual_devices <= 1, "There can only be a single Uinput output");
+    let mut sinks: Vec<Box<dyn InputSink>> = Vec::new();
+    for output in outputs {
+        tracing::debug!("Opening the {:?} output", output);
+        sinks.push(match output {
+            OutputConfig::ConfigFs => Box::new(ConfigFsSink::open(mode, presence, presence_settings.clone()).await?),
+            OutputConfig::Log => Box::new(LogSink::new()),
+            OutputConfig::Metrics => Box::new(MetricsSink::new()),
+            OutputConfig::Record { path } => Box::new(RecordSink::create(path).await?),
+            #[cfg(target_os = "linux")]
+            OutputConfig::Uinput => Box::new(UinputSink::create()?),
+            #[cfg(not(target_os = "linux"))]
+            OutputConfig::Uinput => anyhow::bail!("The Uinput output is only supported on linux")
+        });
+    }
+    match sinks.len() {
+        1 => Ok(sinks.remove(0)),
+        _ => Ok(Box::new(Tee::new(sinks)))
+    }
+}
+
+/// How long the `Tee` waits for a full sink before it skips the sink
+const SINK_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Passes every event on to several sinks
+///
+/// Each sink gets its own queue, so a slow sink only coalesces the movements meant for itself.
+/// A sink whose queue stays full is skipped until it has room again and then gets a reset, so that the others are not held up
+/// and nothing that was released in the meantime stays held.
+/// The status is the combination of the status of all sinks.
+pub struct Tee {
+    sinks: Vec<Box<dyn InputSink>>,
+    status: watch::Sender<DeviceStatus>,
+    status_receiver: watch::Receiver<DeviceStatus>
+}
+
+impl Tee {
+    pub fn new(sinks: Vec<Box<dyn InputSink>>) -> Self {
+        let (status, status_receiver) = watch::channel(combined_status(&sinks.iter().map(|s| s.status()).collect::<Vec<_>>()));
+        Self {
+            sinks,
+            status,
+            status_receiver
+        }
+    }
+}
+
+fn combined_status(status: &[watch::Receiver<DeviceStatus>]) -> DeviceStatus {
+    status
+        .iter()
+        .fold(DeviceStatus::empty(), |combined, status| combined | *status.borrow())
+}
+
+impl InputSink for Tee {
+    fn status(&self) -> watch::Receiver<DeviceStatus> {
+        self.status_receiver.clone()
+    }
+
+    fn run(self: Box<Self>, mut events: EventReceiver) -> SinkFuture {
+        let Tee { sinks, status, .. } = *self;
+        let status = Arc::new(status);
+        Box::pin(async move {
+            let all_status: Vec<_> = sinks.iter().map(|sink| sink.status()).collect();
+            let mut outputs = Vec::new();
+            let mut tasks = Vec::new();
+            for sink in sinks {
+                let mut changes = sink.status();
+                let all_status = all_status.clone();
+                let status = status.clone();
+                spawn(
+                    async move {
+                        while changes.changed().await.is_ok() {
+                            let new = combined_status(&all_status);
+                            status.send_if_modified(|current| std::mem::replace(current, new) != new);
+                        }
+                    }
+                    .in_current_span()
+                );
+                let (sender, receiver) = event_queue(EVENT_QUEUE_CAPACITY);
+                outputs.push(TeeOutput { sender, lagging: false });
+                tasks.push(run(sink, receiver));
+            }
+            while let Some(event) = events.recv().await {
+                for output in &mut outputs {
+                    output.forward(event).await;
+                }
+            }
+            drop(outputs);
+            for task in tasks {
+                task.await?;
+            }
+            Ok(())
+        })
+    }
+}
+
+struct TeeOutput {
+    sender: EventSender,
+    lagging: bool
+}
+
+impl TeeOutput {
+    async fn forward(&mut self, event: InputEvent) {
+        if self.lagging {
+            match self.sender.try_send(InputEvent::Reset) {
+                Ok(true) => {
+                    tracing::info!("A lagging output caught up, resetting it");
+                    self.lagging = false;
+                }
+                Ok(false) | Err(_) => return
+            }
+        }
+        // a sink that failed already logged why
+        let sent = timeout(SINK_TIMEOUT, self.sender.send(event)).await;
+        if sent.is_err() {
+            tracing::warn!("An output is not keeping up, skipping its events until it has room again");
+            self.lagging = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use inputshare_common::HidKeyCode;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn tee_passes_events_to_every_sink() {
+        let (first, mut first_events, first_status) = Collector::new();
+        let (second, mut second_events, second_status) = Collector::new();
+        let processor = start(Box::new(Tee::new(vec![Box::new(first), Box::new(second)])));
+        let mut status = processor.status.clone();
+
+        let events = [InputEvent::KeyPress(HidKeyCode::KeyA), InputEvent::MouseMove(3, 4), InputEvent::Reset];
+        for event in events {
+            processor.events.send(event).await.unwrap();
+        }
+        for event in events {
+            assert_eq!(first_events.recv().await, Some(event));
+            assert_eq!(second_events.recv().await, Some(event));
+        }
+
+        first_status.send_replace(DeviceStatus::MOUSE_STALLED);
+        second_status.send_replace(DeviceStatus::KEYBOARD_STALLED);
+        let combined = async {
+            while *status.borrow_and_update() != DeviceStatus::MOUSE_STALLED | DeviceStatus::KEYBOARD_STALLED {
+                status.changed().await.unwrap();
+            }
+        };
+        timeout(Duration::from_secs(1), combined).await.unwrap();
+
+        drop(processor);
+        assert_eq!(first_events.recv().await, None);
+        assert_eq!(second_events.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn tee_skips_a_sink_that_stays_full() {
+        let (fast, mut fast_events, _fast_status) = Collector::new();
+        let (slow, mut slow_events, gate) = Collector::gated();
+        let processor = start(Box::new(Tee::new(vec![Box::new(fast), Box::new(slow)])));
+
+        let events: Vec<_> = (0..EVENT_QUEUE_CAPACITY + 8)
+            .map(|i| match i % 2 {
+                0 => InputEvent::KeyPress(HidKeyCode::KeyA),
+                _ => InputEvent::KeyRelease(HidKeyCode::KeyA)
+            })
+            .collect();
+        for event in &events {
+            processor.events.send(*event).await.unwrap();
+        }
+        for event in &events {
+            let received = timeout(Duration::from_secs(1), fast_events.recv()).await;
+            assert_eq!(received.unwrap(), Some(*event), "The slow sink must not hold up the fast one");
+        }
+
+        gate.notify_one();
+        for event in &events[..EVENT_QUEUE_CAPACITY] {
+            assert_eq!(slow_events.recv().await, Some(*event));
+        }
+        let next = InputEvent::KeyPress(HidKeyCode::KeyB);
+        processor.events.send(next).await.unwrap();
+        assert_eq!(slow_events.recv().await, Some(InputEvent::Reset));
+        assert_eq!(slow_events.recv().await, Some(next));
+    }
+}
@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use inputshare_common::DeviceStatus;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tracing::Instrument;
+
+use crate::queue::EventReceiver;
+use crate::sink::{InputSink, SinkFuture};
+
+/// Appends every event to a file, one line per event prefixed with the seconds since the sink was created
+pub struct RecordSink {
+    file: File,
+    started: Instant,
+    status: watch::Sender<DeviceStatus>
+}
+
+impl RecordSink {
+    pub async fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("Could not open {}", path.display()))?;
+        Ok(Self {
+            file,
+            started: Instant::now(),
+            status: watch::channel(DeviceStatus::empty()).0
+        })
+    }
+}
+
+impl InputSink for RecordSink {
+    fn status(&self) -> watch::Receiver<DeviceStatus> {
+        self.status.subscribe()
+    }
+
+    fn run(mut self: Box<Self>, mut events: EventReceiver) -> SinkFuture {
+        Box::pin(
+            async move {
+                while let Some(event) = events.recv().await {
+                    let line = format!("{:.3} {:?}\n", self.started.elapsed().as_secs_f64(), event);
+                    self.file
+                        .write_all(line.as_bytes())
+                        .await
+                        .context("Could not record the event")?;
+                }
+                self.file.flush().await?;
+                Ok(())
+            }
+            .in_current_span()
+        )
+    }
+}
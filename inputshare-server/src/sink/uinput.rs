@@ -0,0 +1,127 @@
+use anyhow::Result;
+use inputshare_common::evdev::{self, REL_HWHEEL, REL_HWHEEL_HI_RES, REL_WHEEL, REL_WHEEL_HI_RES, REL_X, REL_Y};
+use inputshare_common::{DeviceStatus, MouseType, SCROLL_RESOLUTION};
+use tokio::sync::watch;
+use tracing::Instrument;
+
+use crate::queue::EventReceiver;
+use crate::receiver::InputEvent;
+use crate::sink::{InputSink, SinkFuture};
+use crate::uinput::UinputDevice;
+
+/// Injects the events into the local machine through virtual uinput devices
+pub struct UinputSink {
+    keyboard: UinputDevice,
+    mouse: UinputDevice,
+    consumer_device: UinputDevice,
+    /// The partial detents that did not add up to a full one yet, horizontal and vertical
+    scroll_remainder: [i32; 2],
+    status: watch::Sender<DeviceStatus>
+}
+
+impl UinputSink {
+    pub fn create() -> Result<Self> {
+        let keyboard = UinputDevice::create("inputshare keyboard", evdev::KEYS.iter().map(|(_, code)| *code), &[])?;
+        let mouse = UinputDevice::create(
+            "inputshare mouse",
+            evdev::BUTTONS.iter().map(|(_, code)| *code),
+            &[REL_X, REL_Y, REL_WHEEL, REL_HWHEEL, REL_WHEEL_HI_RES, REL_HWHEEL_HI_RES]
+        )?;
+        let consumer_keys = evdev::CONSUMER_KEYS
+            .iter()
+            .map(|(_, code)| *code)
+            .chain(evdev::SYSTEM_CONTROL_KEYS.iter().map(|(_, code)| *code));
+        let consumer_device = UinputDevice::create("inputshare consumer control", consumer_keys, &[])?;
+        Ok(Self {
+            keyboard,
+            mouse,
+            consumer_device,
+            scroll_remainder: [0; 2],
+            status: watch::channel(DeviceStatus::empty()).0
+        })
+    }
+
+    /// Reports the high resolution `amount` together with the detents that it completes for older applications
+    fn scroll(&mut self, index: usize, hi_res_axis: u16, axis: u16, amount: i16) {
+        let resolution = i32::from(SCROLL_RESOLUTION);
+        let remainder = &mut self.scroll_remainder[index];
+        *remainder += i32::from(amount);
+        let detents = *remainder / resolution;
+        *remainder -= detents * resolution;
+        self.mouse.move_axis(hi_res_axis, amount.into());
+        self.mouse.move_axis(axis, detents);
+    }
+
+    fn apply(&mut self, event: InputEvent) -> Result<()> {
+        let (device, code, pressed) = match event {
+            InputEvent::MouseMove(x, y) => {
+                self.mouse.move_axis(REL_X, saturate(x));
+                self.mouse.move_axis(REL_Y, saturate(y));
+                return self.mouse.sync();
+            }
+            InputEvent::HorizontalScrolling(amount) => {
+                self.scroll(0, REL_HWHEEL_HI_RES, REL_HWHEEL, amount);
+                return self.mouse.sync();
+            }
+            InputEvent::VerticalScrolling(amount) => {
+                self.scroll(1, REL_WHEEL_HI_RES, REL_WHEEL, amount);
+                return self.mouse.sync();
+            }
+            InputEvent::Reset => {
+                for device in [&mut self.keyboard, &mut self.mouse, &mut self.consumer_device] {
+                    device.release_all();
+                    device.sync()?;
+                }
+                self.scroll_remainder = [0; 2];
+                return Ok(());
+            }
+            InputEvent::Gamepad(_) => {
+                tracing::trace!("Ignoring gamepad input");
+                return Ok(());
+            }
+            InputEvent::KeyPress(key) => (&mut self.keyboard, evdev::key_code(key), true),
+            InputEvent::KeyRelease(key) => (&mut self.keyboard, evdev::key_code(key), false),
+            InputEvent::MouseButtonPress(button) => (&mut self.mouse, evdev::button_code(button), true),
+            InputEvent::MouseButtonRelease(button) => (&mut self.mouse, evdev::button_code(button), false),
+            InputEvent::ConsumerDevicePress(key) => (&mut self.consumer_device, evdev::consumer_code(key), true),
+            InputEvent::ConsumerDeviceRelease(key) => (&mut self.consumer_device, evdev::consumer_code(key), false),
+            InputEvent::SystemControlPress(key) => (&mut self.consumer_device, evdev::system_control_code(key), true),
+            InputEvent::SystemControlRelease(key) => (&mut self.consumer_device, evdev::system_control_code(key), false)
+        };
+        match code {
+            Some(code) => {
+                device.set_key(code, pressed);
+                device.sync()
+            }
+            None => {
+                tracing::trace!("{:?} has no evdev equivalent", event);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn saturate(value: MouseType) -> i32 {
+    value.clamp(i32::MIN.into(), i32::MAX.into()) as i32
+}
+
+impl InputSink for UinputSink {
+    fn status(&self) -> watch::Receiver<DeviceStatus> {
+        self.status.subscribe()
+    }
+
+    fn run(mut self: Box<Self>, mut events: EventReceiver) -> SinkFuture {
+        Box::pin(
+            async move {
+                while let Some(event) = events.recv().await {
+                    if let Err(err) = self.apply(event) {
+                        self.status.send_replace(DeviceStatus::UNAVAILABLE);
+                        return Err(err);
+                    }
+                }
+                Ok(())
+            }
+            .in_current_span()
+        )
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeSet;
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::OpenOptionsExt;
+
+use anyhow::{Context, Result};
+use inputshare_common::evdev::{BUS_VIRTUAL, EV_KEY, EV_REL, EV_SYN, SYN_REPORT};
+use inputshare_common::uinput::{create_device, destroy_device, event, set_event_bit, set_key_bit, set_rel_bit, setup_device, write_events};
+use libc::input_event;
+
+/// A virtual input device that is removed again when dropped
+///
+/// Events are queued until `sync` writes them as a single frame.
+#[derive(Debug)]
+pub struct UinputDevice {
+    name: &'static str,
+    file: File,
+    pressed: BTreeSet<u16>,
+    events: Vec<input_event>
+}
+
+impl UinputDevice {
+    pub fn create(name: &'static str, keys: impl IntoIterator<Item = u16>, axes: &[u16]) -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_CLOEXEC)
+            .open("/dev/uinput")
+            .context("Could not open /dev/uinput")?;
+        set_event_bit(&file, EV_KEY)?;
+        for key in keys {
+            set_key_bit(&file, key)?;
+        }
+        if !axes.is_empty() {
+            set_event_bit(&file, EV_REL)?;
+        }
+        for axis in axes {
+            set_rel_bit(&file, *axis)?;
+        }
+        setup_device(&file, name, BUS_VIRTUAL)?;
+        create_device(&file)?;
+        tracing::debug!("Created the virtual device {:?}", name);
+        Ok(Self {
+            name,
+            file,
+            pressed: BTreeSet::new(),
+            events: Vec::new()
+        })
+    }
+
+    pub fn set_key(&mut self, code: u16, pressed: bool) {
+        let changed = match pressed {
+            true => self.pressed.insert(code),
+            false => self.pressed.remove(&code)
+        };
+        if changed {
+            self.events.push(event(EV_KEY, code, pressed.into()));
+        }
+    }
+
+    pub fn move_axis(&mut self, axis: u16, value: i32) {
+        if value != 0 {
+            self.events.push(event(EV_REL, axis, value));
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        for code in std::mem::take(&mut self.pressed) {
+            self.events.push(event(EV_KEY, code, 0));
+        }
+    }
+
+    /// Writes the queued events followed by a `SYN_REPORT`
+    pub fn sync(&mut self) -> Result<()> {
+        if self.events.is_empty() {
+            return Ok(());
+        }
+        self.events.push(event(EV_SYN, SYN_REPORT, 0));
+        tracing::trace!("Writing {} events to {:?}", self.events.len(), self.name);
+        let result = write_events(&self.file, &self.events);
+        self.events.clear();
+        result.with_context(|| format!("Could not write to {:?}", self.name))
+    }
+}
+
+impl Drop for UinputDevice {
+    fn drop(&mut self) {
+        tracing::debug!("Removing the virtual device {:?}", self.name);
+        destroy_device(&self.file).unwrap_or_else(|err| tracing::warn!("Could not remove {:?}: {}", self.name, err));
+    }
+}
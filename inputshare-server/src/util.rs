@@ -1,21 +1,102 @@
 use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
 use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use inputshare_common::PROTOCOL;
 use mdns_sd::{DaemonEvent, Receiver, ServiceDaemon, ServiceInfo};
-use quinn::ServerConfig;
+use quinn::crypto::rustls::HandshakeData;
+use quinn::{Connection, ServerConfig};
+use ring::digest::{digest, SHA256};
+use rustls::{Certificate, PrivateKey};
+use rustls_pemfile::Item;
 use tracing::instrument;
 
+/// Uses the certificate in `identity`, or a new self-signed one that is lost when the server stops
+///
+/// A missing `identity` file is created with a new self-signed certificate, so clients can pin it.
 #[instrument]
-pub fn self_signed_config(concurrent_connections: u32) -> Result<ServerConfig> {
-    let cert = rcgen::generate_simple_self_signed([String::from("inputshare")])?;
-    let key = rustls::PrivateKey(cert.serialize_private_key_der());
-    let cert = rustls::Certificate(cert.serialize_der()?);
-    let mut config = ServerConfig::with_single_cert(vec![cert], key)?;
+pub fn server_config(identity: Option<&Path>, concurrent_connections: u32) -> Result<ServerConfig> {
+    let (cert, key) = match identity {
+        Some(path) => match File::open(path) {
+            Ok(file) => load_identity(BufReader::new(file)).with_context(|| format!("Could not read the certificate from {}", path.display()))?,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                tracing::info!("Creating a new certificate in {}", path.display());
+                let generated = rcgen::generate_simple_self_signed([String::from("inputshare")])?;
+                let pem = generated.serialize_pem()? + &generated.serialize_private_key_pem();
+                create_private(path)
+                    .and_then(|mut file| file.write_all(pem.as_bytes()))
+                    .with_context(|| format!("Could not write the certificate to {}", path.display()))?;
+                // every serialization signs the certificate again, so only the saved one has the right fingerprint
+                load_identity(pem.as_bytes())?
+            }
+            Err(err) => return Err(err).with_context(|| format!("Could not open {}", path.display()))
+        },
+        None => {
+            let generated = rcgen::generate_simple_self_signed([String::from("inputshare")])?;
+            (Certificate(generated.serialize_der()?), PrivateKey(generated.serialize_private_key_der()))
+        }
+    };
+    tracing::info!("Certificate fingerprint: {}", fingerprint(&cert));
+    let mut crypto = rustls::ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)?;
+    crypto.max_early_data_size = u32::MAX;
+    crypto.alpn_protocols = vec![PROTOCOL.to_vec()];
+    let mut config = ServerConfig::with_crypto(Arc::new(crypto));
     config.concurrent_connections(concurrent_connections);
     Ok(config)
 }
 
+/// The ALPN protocol that was agreed on during the handshake
+pub fn negotiated_protocol(connection: &Connection) -> Option<Vec<u8>> {
+    connection
+        .handshake_data()?
+        .downcast::<HandshakeData>()
+        .ok()?
+        .protocol
+}
+
+/// Reads the first certificate and private key of a PEM file
+fn load_identity(mut pem: impl BufRead) -> Result<(Certificate, PrivateKey)> {
+    let (mut cert, mut key) = (None, None);
+    for item in rustls_pemfile::read_all(&mut pem)? {
+        match item {
+            Item::X509Certificate(der) => cert = cert.or(Some(Certificate(der))),
+            Item::PKCS8Key(der) | Item::RSAKey(der) | Item::ECKey(der) => key = key.or(Some(PrivateKey(der))),
+            _ => {}
+        }
+    }
+    Ok((
+        cert.ok_or_else(|| anyhow!("Missing certificate"))?,
+        key.ok_or_else(|| anyhow!("Missing private key"))?
+    ))
+}
+
+/// Creates a file that only the current user can read
+fn create_private(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+/// The SHA-256 hash of the certificate in hex, which clients use to pin it
+fn fingerprint(cert: &Certificate) -> String {
+    digest(&SHA256, &cert.0)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
 #[instrument]
 pub fn start_mdns(interface: SocketAddr) -> Result<ServiceDaemon> {
     let mdns = ServiceDaemon::new()?;
@@ -0,0 +1,174 @@
+use std::fmt::{Display, Formatter};
+use std::net::ToSocketAddrs;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use eyre::{ensure, eyre, WrapErr};
+use inputshare_common::PROTOCOL;
+use quinn::crypto::rustls::HandshakeData;
+use quinn::{ClientConfig, Connection, Endpoint, TransportConfig};
+use ring::digest::{digest, SHA256};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ServerName};
+use tracing::instrument;
+
+/// The SHA-256 hash of a DER encoded certificate
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint([u8; 32]);
+
+impl Fingerprint {
+    pub fn of(certificate: &Certificate) -> Self {
+        let mut hash = [0; 32];
+        hash.copy_from_slice(digest(&SHA256, &certificate.0).as_ref());
+        Self(hash)
+    }
+}
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = eyre::Report;
+
+    /// Parses the hex representation, with or without `:` between the bytes
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s
+            .chars()
+            .filter(|c| *c != ':')
+            .map(|c| {
+                c.to_digit(16)
+                    .map(|digit| digit as u8)
+                    .ok_or_else(|| eyre!("{:?} is not a hex digit", c))
+            })
+            .collect::<eyre::Result<Vec<u8>>>()?;
+        ensure!(digits.len() == 64, "A fingerprint has 64 hex digits, not {}", digits.len());
+        let mut hash = [0; 32];
+        for (byte, pair) in hash.iter_mut().zip(digits.chunks(2)) {
+            *byte = pair[0] << 4 | pair[1];
+        }
+        Ok(Self(hash))
+    }
+}
+
+/// How the certificate of the server is verified
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum CertificateCheck {
+    /// Accepts any certificate, servers create a new self-signed one on every start unless they are given one
+    #[default]
+    Skip,
+    /// Only accepts the certificate with this fingerprint
+    Pinned(Fingerprint)
+}
+
+/// The server presented a different certificate than the pinned one
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CertificateMismatch {
+    pub expected: Fingerprint,
+    pub found: Fingerprint
+}
+
+impl Display for CertificateMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The server certificate {} does not match the pinned certificate {}",
+            self.found, self.expected
+        )
+    }
+}
+
+impl std::error::Error for CertificateMismatch {}
+
+struct Verifier {
+    check: CertificateCheck,
+    /// The fingerprint of the last certificate that was presented, used to explain failed handshakes
+    seen: Mutex<Option<Fingerprint>>
+}
+
+impl Verifier {
+    fn mismatch(&self) -> Option<CertificateMismatch> {
+        let found = (*self
+            .seen
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()))?;
+        match self.check {
+            CertificateCheck::Pinned(expected) if expected != found => Some(CertificateMismatch { expected, found }),
+            _ => None
+        }
+    }
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self, end_entity: &Certificate, _intermediates: &[Certificate], _server_name: &ServerName, _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8], _now: SystemTime
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        *self
+            .seen
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Fingerprint::of(end_entity));
+        match self.mismatch() {
+            Some(mismatch) => Err(rustls::Error::General(mismatch.to_string())),
+            None => Ok(ServerCertVerified::assertion())
+        }
+    }
+}
+
+/// Connects to the server at `host`
+///
+/// The endpoint has to be kept until the connection is closed, waiting for it to become idle
+/// ensures that the server learns about the close. A rejected certificate is reported as a `CertificateMismatch`.
+#[instrument]
+pub async fn connect(host: &str, check: CertificateCheck) -> eyre::Result<(Endpoint, Connection)> {
+    let verifier = Arc::new(Verifier {
+        check,
+        seen: Mutex::new(None)
+    });
+    let mut crypto = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+    crypto.alpn_protocols = vec![PROTOCOL.to_vec()];
+    let mut transport = TransportConfig::default();
+    transport.keep_alive_interval(Some(Duration::from_secs(1)));
+
+    let mut config = ClientConfig::new(Arc::new(crypto));
+    config.transport_config(Arc::new(transport));
+    let mut endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
+    endpoint.set_default_client_config(config);
+
+    let addrs = host
+        .to_socket_addrs()
+        .wrap_err("Is the used host name correct?")?
+        .find(|a| a.is_ipv4())
+        .ok_or_else(|| eyre!("Can not find suitable address"))?;
+    tracing::debug!("Resolved {} to {}", host, addrs);
+    match endpoint.connect(addrs, "dummy")?.await {
+        Ok(connection) => {
+            let protocol = connection
+                .handshake_data()
+                .and_then(|data| data.downcast::<HandshakeData>().ok())
+                .and_then(|data| data.protocol);
+            ensure!(protocol.as_deref() == Some(PROTOCOL), "The server uses an incompatible protocol version");
+            Ok((endpoint, connection))
+        }
+        Err(err) => match verifier.mismatch() {
+            Some(mismatch) => Err(mismatch.into()),
+            None => Err(err.into())
+        }
+    }
+}
+
+/// The fingerprint of the certificate that the server presented
+pub fn server_fingerprint(connection: &Connection) -> Option<Fingerprint> {
+    connection
+        .peer_identity()?
+        .downcast::<Vec<Certificate>>()
+        .ok()?
+        .first()
+        .map(Fingerprint::of)
+}
@@ -0,0 +1,12 @@
+use eyre::WrapErr;
+use inputshare_common::{decode, encode, ControlRequest, ControlResponse, MAX_CONTROL_MESSAGE_SIZE};
+use quinn::Connection;
+
+/// Sends a single request on a new stream and waits for the response
+pub async fn request(connection: &Connection, request: &ControlRequest) -> eyre::Result<ControlResponse> {
+    let (mut send, recv) = connection.open_bi().await?;
+    send.write_all(&encode(request)).await?;
+    send.finish().await?;
+    let response = recv.read_to_end(MAX_CONTROL_MESSAGE_SIZE).await?;
+    decode(&response).wrap_err("Received an invalid response")
+}
@@ -0,0 +1,11 @@
+//! The parts of the client that do not depend on the user interface or the platform
+
+mod connection;
+mod control;
+pub mod pointer;
+pub mod sender;
+mod session;
+
+pub use connection::{connect, server_fingerprint, CertificateCheck, CertificateMismatch, Fingerprint};
+pub use control::request;
+pub use session::{Session, SessionOptions, SessionState, Stats};
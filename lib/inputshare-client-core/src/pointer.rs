@@ -0,0 +1,180 @@
+use inputshare_common::{MouseType, Vec2};
+use serde::{Deserialize, Serialize};
+
+/// The default `SmoothMouseXCurve` / `SmoothMouseYCurve` of Windows
+///
+/// Both axes are rescaled so that the first segment has a slope of one.
+/// The input is measured in counts per event and the output in pixels per event.
+const ENHANCED_PRECISION_CURVE: [(f64, f64); 5] = [(0.0, 0.0), (1.505, 1.505), (4.375, 5.822), (13.51, 26.69), (140.0, 623.9)];
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Acceleration {
+    /// Every movement is scaled by the same factor
+    #[default]
+    Linear,
+    /// Slow movements are precise while fast movements cover more distance
+    EnhancedPrecision,
+    /// A curve given as `(input speed, output speed)` control points
+    ///
+    /// Speeds are measured in counts per event. The curve always starts at `(0, 0)`
+    /// and the last segment is extended past the last point.
+    Custom(Vec<(f64, f64)>)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PointerProfile {
+    pub speed: f64,
+    pub acceleration: Acceleration
+}
+
+impl Default for PointerProfile {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            acceleration: Acceleration::Linear
+        }
+    }
+}
+
+/// Turns raw mouse movements into pointer movements according to a `PointerProfile`
+///
+/// Fractional pixels are carried over to the next movement so that no precision is lost with low speeds
+#[derive(Debug, Clone)]
+pub struct PointerBallistics {
+    speed: f64,
+    curve: Option<Vec<(f64, f64)>>,
+    remainder: Vec2<f64>
+}
+
+impl PointerBallistics {
+    pub fn new(profile: &PointerProfile) -> Self {
+        let curve = match &profile.acceleration {
+            Acceleration::Linear => None,
+            Acceleration::EnhancedPrecision => Some(ENHANCED_PRECISION_CURVE.to_vec()),
+            Acceleration::Custom(points) => {
+                let mut curve = vec![(0.0, 0.0)];
+                curve.extend(
+                    points
+                        .iter()
+                        .copied()
+                        .filter(|&(x, y)| x.is_finite() && y.is_finite() && x > 0.0 && y >= 0.0)
+                );
+                curve.sort_by(|a, b| a.0.total_cmp(&b.0));
+                curve.dedup_by(|a, b| a.0 == b.0);
+                if curve.len() < 2 {
+                    tracing::warn!("The custom acceleration curve has no valid points; falling back to linear");
+                }
+                Some(curve).filter(|curve| curve.len() >= 2)
+            }
+        };
+        Self {
+            speed: profile.speed,
+            curve,
+            remainder: Vec2::new(0.0, 0.0)
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    /// Returns the number of pixels the pointer should move for a movement of `x` / `y` counts
+    pub fn apply(&mut self, x: MouseType, y: MouseType) -> Vec2<MouseType> {
+        let (x, y) = (x as f64, y as f64);
+        let input = x.hypot(y);
+        let gain = match &self.curve {
+            Some(curve) if input > 0.0 => evaluate(curve, input) / input,
+            _ => 1.0
+        } * self.speed;
+        self.remainder.x += x * gain;
+        self.remainder.y += y * gain;
+        let output = Vec2::new(self.remainder.x.trunc(), self.remainder.y.trunc());
+        self.remainder.x -= output.x;
+        self.remainder.y -= output.y;
+        Vec2::new(output.x as MouseType, output.y as MouseType)
+    }
+
+    /// Discards the accumulated fractional pixels
+    pub fn reset(&mut self) {
+        self.remainder = Vec2::new(0.0, 0.0);
+    }
+}
+
+/// Linearly interpolates a sorted curve that contains at least two points
+fn evaluate(curve: &[(f64, f64)], input: f64) -> f64 {
+    let segment = curve
+        .windows(2)
+        .find(|segment| input <= segment[1].0)
+        .unwrap_or(&curve[curve.len() - 2..]);
+    let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+    y0 + (input - x0) * (y1 - y0) / (x1 - x0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(speed: f64, acceleration: Acceleration) -> PointerProfile {
+        PointerProfile { speed, acceleration }
+    }
+
+    #[test]
+    fn linear_scales_movement() {
+        let mut ballistics = PointerBallistics::new(&profile(2.0, Acceleration::Linear));
+        assert_eq!(ballistics.apply(3, -4), Vec2::new(6, -8));
+        assert_eq!(ballistics.apply(0, 0), Vec2::new(0, 0));
+    }
+
+    #[test]
+    fn fractions_are_accumulated() {
+        let mut ballistics = PointerBallistics::new(&profile(0.25, Acceleration::Linear));
+        let moved: Vec<_> = (0..8).map(|_| ballistics.apply(1, -1)).collect();
+        assert_eq!(moved.iter().map(|v| v.x).sum::<MouseType>(), 2);
+        assert_eq!(moved.iter().map(|v| v.y).sum::<MouseType>(), -2);
+        assert_eq!(moved[3], Vec2::new(1, -1));
+    }
+
+    #[test]
+    fn reset_discards_fractions() {
+        let mut ballistics = PointerBallistics::new(&profile(0.5, Acceleration::Linear));
+        assert_eq!(ballistics.apply(1, 0), Vec2::new(0, 0));
+        ballistics.reset();
+        assert_eq!(ballistics.apply(1, 0), Vec2::new(0, 0));
+    }
+
+    #[test]
+    fn enhanced_precision_accelerates_fast_movements() {
+        let mut ballistics = PointerBallistics::new(&profile(1.0, Acceleration::EnhancedPrecision));
+        assert_eq!(ballistics.apply(1, 0), Vec2::new(1, 0));
+        ballistics.reset();
+        let fast = ballistics.apply(40, 0);
+        assert!(fast.x > 80, "{:?}", fast);
+        assert_eq!(fast.y, 0);
+    }
+
+    #[test]
+    fn custom_curve_is_interpolated() {
+        let curve = Acceleration::Custom(vec![(20.0, 40.0), (10.0, 10.0), (f64::NAN, 1.0)]);
+        let mut ballistics = PointerBallistics::new(&profile(1.0, curve));
+        assert_eq!(ballistics.apply(5, 0), Vec2::new(5, 0));
+        assert_eq!(ballistics.apply(0, -15), Vec2::new(0, -25));
+        // extrapolated with the slope of the last segment
+        assert_eq!(ballistics.apply(30, 0), Vec2::new(70, 0));
+        // direction is preserved for diagonal movements
+        assert_eq!(ballistics.apply(12, 16), Vec2::new(24, 32));
+    }
+
+    #[test]
+    fn invalid_custom_curve_falls_back_to_linear() {
+        let mut ballistics = PointerBallistics::new(&profile(1.5, Acceleration::Custom(vec![(-1.0, 2.0)])));
+        assert_eq!(ballistics.apply(2, 4), Vec2::new(3, 6));
+    }
+
+    #[test]
+    fn evaluate_hits_control_points() {
+        for (x, y) in ENHANCED_PRECISION_CURVE {
+            assert!((evaluate(&ENHANCED_PRECISION_CURVE, x) - y).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::VecDeque;
+use std::io::{Result, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use inputshare_common::edge::EdgeReturns;
+use inputshare_common::{
+    ConsumerDeviceCode, DeviceStatus, GamepadState, HidButtonCode, HidKeyCode, MessageType, MouseType, SystemControlCode, Vec2, MESSAGE_SIZE,
+    SCROLL_RESOLUTION
+};
+
+use crate::pointer::{PointerBallistics, PointerProfile};
+
+/// Converts a scroll amount in detents into high resolution scroll units
+pub fn detents_to_scroll(v: f32) -> i16 {
+    (v * SCROLL_RESOLUTION as f32)
+        .round()
+        .clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+/// Where the fields of the packets from [`InputSender::write_packet`] are located
+pub mod layout {
+    use std::ops::Range;
+
+    /// Incremented with every packet
+    pub const SEQUENCE: Range<usize> = 0..8;
+    /// The local mouse position
+    pub const MOUSE_POSITION: Range<usize> = 8..24;
+    /// The index of the first message in the packet
+    pub const FIRST_MESSAGE: Range<usize> = 24..32;
+    /// The number of messages in the packet
+    pub const MESSAGE_COUNT: usize = 32;
+    /// The start of the messages, each of them is `MESSAGE_SIZE` bytes long
+    pub const MESSAGES: usize = 33;
+}
+
+#[derive(Debug)]
+pub struct InputSender {
+    local_sequence: u64,
+    remote_sequence: u64,
+    packet_buffer: Vec<u8>,
+    local_mouse_pos: Vec2<MouseType>,
+    ballistics: PointerBallistics,
+    remote_mouse_pos: Vec2<MouseType>,
+    remote_status: DeviceStatus,
+    edge_returns: EdgeReturns,
+    /// The count of `edge_returns` that was already handled
+    handled_returns: u8,
+    message_queue: VecDeque<[u8; MESSAGE_SIZE]>,
+    last_message: u64,
+    gamepad: GamepadState
+}
+
+impl InputSender {
+    pub fn new(pointer: &PointerProfile) -> Self {
+        Self {
+            local_sequence: 1,
+            remote_sequence: 0,
+            packet_buffer: Vec::new(),
+            local_mouse_pos: Vec2::new(0, 0),
+            ballistics: PointerBallistics::new(pointer),
+            remote_mouse_pos: Vec2::new(0, 0),
+            remote_status: DeviceStatus::empty(),
+            edge_returns: EdgeReturns::default(),
+            handled_returns: 0,
+            message_queue: VecDeque::new(),
+            last_message: 0,
+            gamepad: GamepadState::default()
+        }
+    }
+
+    pub fn move_mouse(&mut self, x: MouseType, y: MouseType) {
+        let delta = self.ballistics.apply(x, y);
+        self.local_mouse_pos.x += delta.x;
+        self.local_mouse_pos.y += delta.y;
+    }
+
+    /// Moves the remote pointer by exactly `x` / `y` pixels, ignoring the pointer profile
+    pub fn warp_mouse(&mut self, x: MouseType, y: MouseType) {
+        self.local_mouse_pos.x += x;
+        self.local_mouse_pos.y += y;
+    }
+
+    /// The sum of all movements so far
+    pub fn mouse_position(&self) -> Vec2<MouseType> {
+        self.local_mouse_pos
+    }
+
+    pub fn set_pointer_speed(&mut self, speed: f64) {
+        self.ballistics.set_speed(speed);
+    }
+
+    fn push_message(&mut self, msg: MessageType, arg: u16) {
+        let [lo, hi] = arg.to_le_bytes();
+        self.message_queue.push_back([msg.into(), lo, hi])
+    }
+
+    pub fn reset(&mut self) {
+        self.push_message(MessageType::Reset, 0);
+        self.gamepad = GamepadState::default();
+        self.ballistics.reset();
+    }
+
+    pub fn press_key(&mut self, key: HidKeyCode) {
+        self.push_message(MessageType::KeyPress, u8::from(key).into())
+    }
+
+    pub fn release_key(&mut self, key: HidKeyCode) {
+        self.push_message(MessageType::KeyRelease, u8::from(key).into())
+    }
+
+    pub fn press_mouse_button(&mut self, button: HidButtonCode) {
+        self.push_message(MessageType::MouseButtonPress, u8::from(button).into())
+    }
+
+    pub fn release_mouse_button(&mut self, button: HidButtonCode) {
+        self.push_message(MessageType::MouseButtonRelease, u8::from(button).into())
+    }
+
+    pub fn press_consumer_device(&mut self, button: ConsumerDeviceCode) {
+        self.push_message(MessageType::ConsumerDevicePress, button.into())
+    }
+
+    pub fn release_consumer_device(&mut self, button: ConsumerDeviceCode) {
+        self.push_message(MessageType::ConsumerDeviceRelease, button.into())
+    }
+
+    pub fn press_system_control(&mut self, button: SystemControlCode) {
+        self.push_message(MessageType::SystemControlPress, u8::from(button).into())
+    }
+
+    pub fn release_system_control(&mut self, button: SystemControlCode) {
+        self.push_message(MessageType::SystemControlRelease, u8::from(button).into())
+    }
+
+    /// Queues the fields of the gamepad that changed since the last update
+    pub fn update_gamepad(&mut self, state: GamepadState) {
+        for (msg, arg) in self.gamepad.diff(&state) {
+            self.push_message(msg, arg);
+        }
+        self.gamepad = state;
+    }
+
+    /// Scrolls by `amount / SCROLL_RESOLUTION` detents
+    pub fn scroll_horizontal(&mut self, amount: i16) {
+        self.push_message(MessageType::HighResHorizontalScrolling, amount as u16)
+    }
+
+    /// Scrolls by `amount / SCROLL_RESOLUTION` detents
+    pub fn scroll_vertical(&mut self, amount: i16) {
+        self.push_message(MessageType::HighResVerticalScrolling, amount as u16)
+    }
+
+    /// The position that the server reported with the latest acknowledgement
+    pub fn remote_mouse_position(&self) -> Vec2<MouseType> {
+        self.remote_mouse_pos
+    }
+
+    /// The number of messages that were queued so far
+    pub fn queued_messages(&self) -> u64 {
+        self.last_message + self.message_queue.len() as u64
+    }
+
+    /// The number of messages that the server acknowledged so far
+    pub fn acknowledged_messages(&self) -> u64 {
+        self.last_message
+    }
+
+    pub fn in_sync(&self) -> bool {
+        self.local_mouse_pos == self.remote_mouse_pos && self.message_queue.is_empty()
+    }
+
+    pub fn read_packet(&mut self, mut packet: &[u8]) -> Result<()> {
+        let sequence = packet.read_u64::<LittleEndian>()?;
+        if sequence <= self.remote_sequence {
+            return Ok(());
+        }
+        let x = packet.read_i64::<LittleEndian>()?;
+        let y = packet.read_i64::<LittleEndian>()?;
+        let received_index = packet.read_u64::<LittleEndian>()?;
+        let status = packet.read_u8()?;
+        let edge_returns = EdgeReturns {
+            count: packet.read_u8()?,
+            along: packet.read_u16::<LittleEndian>()?
+        };
+
+        self.remote_sequence = sequence;
+        self.remote_mouse_pos = Vec2::new(x, y);
+        let diff = received_index.saturating_sub(self.last_message);
+        self.message_queue.drain(..(diff as usize));
+        self.last_message = received_index;
+        self.remote_status = DeviceStatus::from_bits_truncate(status);
+        self.edge_returns = edge_returns;
+
+        Ok(())
+    }
+
+    /// Returns where along its return edge the pointer of the server left the screen, if it did since the last call
+    pub fn take_edge_return(&mut self) -> Option<f64> {
+        if self.edge_returns.count == self.handled_returns {
+            return None;
+        }
+        self.handled_returns = self.edge_returns.count;
+        Some(self.edge_returns.along())
+    }
+
+    /// The device status that the server reported with the latest acknowledgement
+    pub fn remote_status(&self) -> DeviceStatus {
+        self.remote_status
+    }
+
+    pub fn write_packet(&mut self) -> Result<&[u8]> {
+        self.packet_buffer.clear();
+        self.packet_buffer
+            .write_u64::<LittleEndian>(self.local_sequence)?;
+        self.packet_buffer
+            .write_i64::<LittleEndian>(self.local_mouse_pos.x)?;
+        self.packet_buffer
+            .write_i64::<LittleEndian>(self.local_mouse_pos.y)?;
+        self.packet_buffer
+            .write_u64::<LittleEndian>(self.last_message)?;
+        let size_index = self.packet_buffer.len();
+        debug_assert_eq!(size_index, layout::MESSAGE_COUNT);
+        self.packet_buffer.write_u8(0)?;
+        for i in 0..usize::min(self.message_queue.len(), u8::MAX as usize) {
+            self.packet_buffer[size_index] += 1;
+            self.packet_buffer
+                .write_all(self.message_queue.get(i).unwrap())?;
+        }
+        self.local_sequence += 1;
+        Ok(self.packet_buffer.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acknowledgement(sequence: u64, x: i64, y: i64, last_message: u64) -> Vec<u8> {
+        let mut packet = Vec::new();
+        packet.write_u64::<LittleEndian>(sequence).unwrap();
+        packet.write_i64::<LittleEndian>(x).unwrap();
+        packet.write_i64::<LittleEndian>(y).unwrap();
+        packet.write_u64::<LittleEndian>(last_message).unwrap();
+        packet
+    }
+
+    /// Appends the device status and the edge returns
+    fn trailer(mut packet: Vec<u8>, status: DeviceStatus, returns: EdgeReturns) -> Vec<u8> {
+        packet.push(status.bits());
+        packet.push(returns.count);
+        packet.write_u16::<LittleEndian>(returns.along).unwrap();
+        packet
+    }
+
+    #[test]
+    fn acknowledgement_carries_the_device_status() {
+        let mut sender = InputSender::new(&PointerProfile::default());
+        let packet = trailer(acknowledgement(1, 5, -3, 0), DeviceStatus::MOUSE_STALLED, EdgeReturns::default());
+        sender.read_packet(&packet).unwrap();
+        assert_eq!(sender.remote_mouse_position(), Vec2::new(5, -3));
+        assert_eq!(sender.remote_status(), DeviceStatus::MOUSE_STALLED);
+        assert_eq!(sender.take_edge_return(), None);
+    }
+
+    #[test]
+    fn acknowledgement_without_status_is_rejected() {
+        let mut sender = InputSender::new(&PointerProfile::default());
+        assert!(sender.read_packet(&acknowledgement(1, 5, -3, 0)).is_err());
+        let mut packet = acknowledgement(1, 5, -3, 0);
+        packet.push(0);
+        assert!(sender.read_packet(&packet).is_err());
+        assert_eq!(sender.remote_mouse_position(), Vec2::new(0, 0));
+
+        let packet = trailer(acknowledgement(1, 5, -3, 0), DeviceStatus::empty(), EdgeReturns::default());
+        sender.read_packet(&packet).unwrap();
+        assert_eq!(sender.remote_mouse_position(), Vec2::new(5, -3));
+    }
+
+    #[test]
+    fn edge_returns_are_taken_once() {
+        let mut sender = InputSender::new(&PointerProfile::default());
+        let returns = EdgeReturns { count: 1, along: u16::MAX };
+        sender
+            .read_packet(&trailer(acknowledgement(1, 0, 0, 0), DeviceStatus::empty(), returns))
+            .unwrap();
+        assert_eq!(sender.take_edge_return(), Some(1.0));
+        assert_eq!(sender.take_edge_return(), None);
+        // later acknowledgements repeat the last return
+        sender
+            .read_packet(&trailer(acknowledgement(2, 0, 0, 0), DeviceStatus::empty(), returns))
+            .unwrap();
+        assert_eq!(sender.take_edge_return(), None);
+    }
+}
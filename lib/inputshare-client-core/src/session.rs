@@ -0,0 +1,368 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use bytes::Bytes;
+use eyre::eyre;
+use inputshare_common::{DeviceStatus, MouseType, Vec2};
+use quinn::{Connection, Endpoint};
+use tokio::sync::{oneshot, watch, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep_until, Instant, MissedTickBehavior};
+use tokio::{select, spawn};
+use tracing::{instrument, Instrument};
+
+use crate::pointer::PointerProfile;
+use crate::sender::InputSender;
+use crate::{connect, CertificateCheck};
+
+/// How long the server may go without a packet while the keepalive is enabled
+const KEEPALIVE_INTERVAL: Duration = Duration::from_millis(250);
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+/// The number of stats samples over which the recent loss rate is measured
+const LOSS_WINDOW: usize = 20;
+/// The number of unacknowledged packets that are remembered to match the acknowledgements against
+const SENT_HISTORY: usize = 256;
+
+#[derive(Debug, Clone)]
+pub struct SessionOptions {
+    pub certificate: CertificateCheck,
+    pub pointer: PointerProfile,
+    /// The number of packets per second while there is something to send
+    pub send_rate: u32
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            certificate: CertificateCheck::Skip,
+            pointer: PointerProfile::default(),
+            send_rate: 100
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct SessionState {
+    /// The device status that the server reported with the latest acknowledgement
+    pub remote_status: DeviceStatus,
+    /// The number of updates that the server has acknowledged
+    pub acknowledged: u64,
+    /// Set once the session stopped, either because it was closed or because the connection was lost
+    pub closed: bool
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Stats {
+    pub rtt: Duration,
+    pub cwnd: u64,
+    pub congestion_events: u64,
+    pub lost_packets: u64,
+    pub lost_bytes: u64,
+    pub sent_packets: u64,
+    /// The share of packets that got lost during the last 20 seconds
+    pub recent_loss_rate: f64
+}
+
+struct Pending {
+    sender: InputSender,
+    /// The number of updates so far
+    updates: u64,
+    /// The number of updates that the server acknowledged so far
+    acknowledged: u64,
+    /// What the packets that were not acknowledged yet contained, oldest first
+    sent: VecDeque<Sent>
+}
+
+/// The state of the sender when a packet was written
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Sent {
+    updates: u64,
+    messages: u64,
+    position: Vec2<MouseType>
+}
+
+impl Pending {
+    fn record_sent(&mut self) {
+        let sent = Sent {
+            updates: self.updates,
+            messages: self.sender.queued_messages(),
+            position: self.sender.mouse_position()
+        };
+        if self.sent.back() != Some(&sent) {
+            if self.sent.len() >= SENT_HISTORY {
+                self.sent.pop_front();
+            }
+            self.sent.push_back(sent);
+        }
+    }
+
+    /// Finds the latest packet that the server's acknowledgement covers
+    ///
+    /// The server reports the position that it reached, which is the position of a packet that it received.
+    /// Matching against it keeps continuous mouse movement from delaying the acknowledgement of earlier updates.
+    fn acknowledge(&mut self) {
+        let messages = self.sender.acknowledged_messages();
+        let position = self.sender.remote_mouse_position();
+        let covered = self
+            .sent
+            .iter()
+            .rposition(|sent| sent.messages <= messages && sent.position == position);
+        if let Some(sent) = covered.and_then(|index| self.sent.drain(..=index).next_back()) {
+            self.acknowledged = self.acknowledged.max(sent.updates);
+        }
+    }
+}
+
+struct Shared {
+    pending: Mutex<Pending>,
+    keepalive: AtomicBool,
+    wake: Notify
+}
+
+impl Shared {
+    fn pending(&self) -> MutexGuard<'_, Pending> {
+        self.pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A connection to a server that sends the inputs of an `InputSender` in the background
+///
+/// Inputs are queued with `update` and sent at the configured rate until the server acknowledged them.
+/// Dropping the session stops it without waiting for the server to learn about it, `close` does both.
+pub struct Session {
+    endpoint: Endpoint,
+    connection: Connection,
+    shared: Arc<Shared>,
+    state: watch::Receiver<SessionState>,
+    stats: watch::Receiver<Stats>,
+    stop: Option<oneshot::Sender<()>>,
+    task: JoinHandle<eyre::Result<()>>
+}
+
+impl Session {
+    pub async fn connect(host: &str, options: SessionOptions) -> eyre::Result<Self> {
+        let (endpoint, connection) = connect(host, options.certificate).await?;
+        tracing::debug!("Connected to {}", connection.remote_address());
+        let shared = Arc::new(Shared {
+            pending: Mutex::new(Pending {
+                sender: InputSender::new(&options.pointer),
+                updates: 0,
+                acknowledged: 0,
+                sent: VecDeque::new()
+            }),
+            keepalive: AtomicBool::new(false),
+            wake: Notify::new()
+        });
+        let (state_sender, state) = watch::channel(SessionState::default());
+        let (stats_sender, stats) = watch::channel(Stats::default());
+        let (stop, stopped) = oneshot::channel();
+        let packet_interval = Duration::from_secs_f32(1.0 / options.send_rate.max(1) as f32);
+        let task = spawn({
+            let connection = connection.clone();
+            let shared = shared.clone();
+            async move {
+                let result = run(&connection, &shared, packet_interval, &state_sender, &stats_sender, stopped).await;
+                connection.close(0u8.into(), b"Disconnected");
+                state_sender.send_modify(|state| state.closed = true);
+                result
+            }
+            .in_current_span()
+        });
+        Ok(Self {
+            endpoint,
+            connection,
+            shared,
+            state,
+            stats,
+            stop: Some(stop),
+            task
+        })
+    }
+
+    /// The underlying connection, for example to send control requests
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Queues inputs with the sender, they are sent as soon as `f` returns
+    pub fn update<R>(&self, f: impl FnOnce(&mut InputSender) -> R) -> R {
+        let result = {
+            let mut pending = self.shared.pending();
+            pending.updates += 1;
+            f(&mut pending.sender)
+        };
+        self.shared.wake.notify_one();
+        result
+    }
+
+    /// Keeps sending packets even if there is nothing new to send
+    ///
+    /// This prevents the server from releasing inputs that are held down for a long time.
+    pub fn set_keepalive(&self, enabled: bool) {
+        if self.shared.keepalive.swap(enabled, Ordering::Relaxed) != enabled {
+            self.shared.wake.notify_one();
+        }
+    }
+
+    /// Waits until the server acknowledged every update so far
+    pub async fn flush(&self) -> eyre::Result<()> {
+        let updates = self.shared.pending().updates;
+        let mut state = self.state.clone();
+        loop {
+            {
+                let state = state.borrow_and_update();
+                if state.acknowledged >= updates {
+                    return Ok(());
+                }
+                if state.closed {
+                    return Err(eyre!("The session stopped before the server acknowledged the input"));
+                }
+            }
+            state
+                .changed()
+                .await
+                .map_err(|_| eyre!("The session stopped"))?;
+        }
+    }
+
+    pub fn state(&self) -> watch::Receiver<SessionState> {
+        self.state.clone()
+    }
+
+    /// Statistics about the connection, updated every second
+    pub fn stats(&self) -> watch::Receiver<Stats> {
+        self.stats.clone()
+    }
+
+    /// Resolves once the session stopped on its own, `close` returns the reason
+    pub async fn closed(&self) {
+        let mut state = self.state.clone();
+        while !state.borrow_and_update().closed {
+            if state.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Stops the session and waits until the server knows about it
+    ///
+    /// Returns the error that stopped the session if it did not run until now.
+    pub async fn close(mut self) -> eyre::Result<()> {
+        if let Some(stop) = self.stop.take() {
+            // the task might have stopped on its own already
+            stop.send(()).ok();
+        }
+        let result = (&mut self.task).await?;
+        self.endpoint.wait_idle().await;
+        tracing::trace!("Closed the session");
+        result
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            stop.send(()).ok();
+        }
+    }
+}
+
+#[instrument(skip_all)]
+async fn run(
+    connection: &Connection, shared: &Shared, packet_interval: Duration, state: &watch::Sender<SessionState>, stats: &watch::Sender<Stats>,
+    mut stopped: oneshot::Receiver<()>
+) -> eyre::Result<()> {
+    let mut deadline = None;
+    let mut last_packet = Instant::now();
+    let mut loss = VecDeque::new();
+    let mut stats_timer = interval(STATS_INTERVAL);
+    stats_timer.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        let timeout = async move {
+            match deadline {
+                Some(deadline) => sleep_until(deadline).await,
+                None => std::future::pending().await
+            };
+        };
+        let keepalive = shared.keepalive.load(Ordering::Relaxed);
+        select! {
+            _ = &mut stopped => return Ok(()),
+            datagram = connection.read_datagram() => {
+                let datagram: Bytes = datagram?;
+                let mut pending = shared.pending();
+                pending.sender.read_packet(&datagram)?;
+                pending.acknowledge();
+            },
+            _ = shared.wake.notified() => {},
+            _ = stats_timer.tick() => {
+                stats.send_replace(collect_stats(connection, &mut loss));
+            },
+            _ = sleep_until(last_packet + KEEPALIVE_INTERVAL), if keepalive && deadline.is_none() => {
+                send_packet(connection, shared)?;
+                last_packet = Instant::now();
+            },
+            _ = timeout => {
+                send_packet(connection, shared)?;
+                last_packet = Instant::now();
+                deadline = Some(last_packet + packet_interval);
+            }
+        }
+        let mut pending = shared.pending();
+        let in_sync = pending.sender.in_sync();
+        if in_sync {
+            pending.acknowledged = pending.updates;
+            pending.sent.clear();
+        }
+        deadline = match in_sync {
+            true => None,
+            false => Some(deadline.unwrap_or_else(Instant::now))
+        };
+        let remote_status = pending.sender.remote_status();
+        let acknowledged = pending.acknowledged;
+        drop(pending);
+        state.send_if_modified(|state| {
+            let changed = state.remote_status != remote_status || state.acknowledged != acknowledged;
+            state.remote_status = remote_status;
+            state.acknowledged = acknowledged;
+            changed
+        });
+    }
+}
+
+fn send_packet(connection: &Connection, shared: &Shared) -> eyre::Result<()> {
+    let mut pending = shared.pending();
+    pending.record_sent();
+    let msg = pending.sender.write_packet()?;
+    debug_assert!(connection
+        .max_datagram_size()
+        .is_none_or(|max| msg.len() <= max));
+    connection.send_datagram(Bytes::copy_from_slice(msg))?;
+    Ok(())
+}
+
+fn collect_stats(connection: &Connection, loss: &mut VecDeque<(u64, u64)>) -> Stats {
+    let path = connection.stats().path;
+    loss.push_back((path.sent_packets, path.lost_packets));
+    while loss.len() > LOSS_WINDOW {
+        loss.pop_front();
+    }
+    let recent_loss_rate = loss
+        .front()
+        .zip(loss.back())
+        .map(|((ot, ol), (nt, nl))| (nt - ot, nl - ol))
+        .map(|(t, l)| l as f64 / t.max(1) as f64)
+        .unwrap_or(0.0);
+    Stats {
+        rtt: path.rtt,
+        cwnd: path.cwnd,
+        congestion_events: path.congestion_events,
+        lost_packets: path.lost_packets,
+        lost_bytes: path.lost_bytes,
+        sent_packets: path.sent_packets,
+        recent_loss_rate
+    }
+}
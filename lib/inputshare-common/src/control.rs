@@ -0,0 +1,94 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// The largest request or response that is accepted on the control channel
+pub const MAX_CONTROL_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// An action that affects the power state of the server itself
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, IntoPrimitive, TryFromPrimitive, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum PowerAction {
+    Shutdown,
+    Reboot
+}
+
+/// A request sent by the client on its own bidirectional stream
+///
+/// Every request stream carries exactly one request followed by exactly one response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlRequest {
+    /// Sent once after connecting
+    Hello,
+    Power(PowerAction),
+    /// Asks for the named actions this client is allowed to run
+    ListActions,
+    RunAction { name: String, arguments: Vec<String> },
+    SetPresence(PresenceSettings)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlResponse {
+    Hello {
+        /// The power actions that are enabled on the server
+        power_actions: Vec<PowerAction>,
+        presence: PresenceSettings
+    },
+    Actions(Vec<RemoteActionInfo>),
+    ActionFinished(ActionOutput),
+    Done,
+    Failed(String)
+}
+
+/// The input the presence keeper produces to keep the target from going idle
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PresencePattern {
+    /// Moves the mouse by a single pixel
+    #[default]
+    Wiggle,
+    /// Taps F15, which no common application reacts to
+    Key,
+    /// Scrolls horizontally by one detent and back
+    Scroll
+}
+
+/// The part of the presence keeper that clients can change at runtime
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceSettings {
+    pub enabled: bool,
+    pub pattern: PresencePattern
+}
+
+/// A named command that is defined in the server config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteActionInfo {
+    pub name: String,
+    pub description: String,
+    /// Whether the client may append its own arguments to the command
+    pub takes_arguments: bool
+}
+
+/// The result of a command that ran to completion
+///
+/// Long outputs are truncated by the server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionOutput {
+    /// `None` if the command was terminated by a signal
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String
+}
+
+impl ActionOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+pub fn encode<T: Serialize>(message: &T) -> Vec<u8> {
+    bincode::serialize(message).expect("Control messages are always serializable")
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, bincode::Error> {
+    bincode::deserialize(bytes)
+}
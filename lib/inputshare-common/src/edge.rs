@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{MouseType, Vec2};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom
+}
+
+impl Edge {
+    pub fn opposite(self) -> Self {
+        match self {
+            Edge::Left => Edge::Right,
+            Edge::Right => Edge::Left,
+            Edge::Top => Edge::Bottom,
+            Edge::Bottom => Edge::Top
+        }
+    }
+}
+
+/// An area of a desktop in pixels
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rect {
+    pub x: MouseType,
+    pub y: MouseType,
+    pub width: MouseType,
+    pub height: MouseType
+}
+
+impl Rect {
+    pub fn new(x: MouseType, y: MouseType, width: MouseType, height: MouseType) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// A screen of the given size with its top left corner at the origin
+    pub fn screen((width, height): (MouseType, MouseType)) -> Self {
+        Self::new(0, 0, width.max(1), height.max(1))
+    }
+
+    fn right(&self) -> MouseType {
+        self.x + self.width - 1
+    }
+
+    fn bottom(&self) -> MouseType {
+        self.y + self.height - 1
+    }
+
+    /// Returns how far `point` lies past `edge`, zero or less if it is not past it
+    pub fn overshoot(&self, edge: Edge, point: Vec2<MouseType>) -> MouseType {
+        match edge {
+            Edge::Left => self.x - point.x,
+            Edge::Right => point.x - self.right(),
+            Edge::Top => self.y - point.y,
+            Edge::Bottom => point.y - self.bottom()
+        }
+    }
+
+    /// Returns the position of `point` along `edge`, from 0 at the top / left to 1 at the bottom / right
+    pub fn along(&self, edge: Edge, point: Vec2<MouseType>) -> f64 {
+        let (offset, length) = match edge {
+            Edge::Left | Edge::Right => (point.y - self.y, self.height),
+            Edge::Top | Edge::Bottom => (point.x - self.x, self.width)
+        };
+        (offset as f64 / (length - 1).max(1) as f64).clamp(0.0, 1.0)
+    }
+
+    /// The inverse of `along`
+    pub fn point_on(&self, edge: Edge, along: f64) -> Vec2<MouseType> {
+        let across = |start: MouseType, length: MouseType| start + (along.clamp(0.0, 1.0) * (length - 1).max(0) as f64).round() as MouseType;
+        match edge {
+            Edge::Left => Vec2::new(self.x, across(self.y, self.height)),
+            Edge::Right => Vec2::new(self.right(), across(self.y, self.height)),
+            Edge::Top => Vec2::new(across(self.x, self.width), self.y),
+            Edge::Bottom => Vec2::new(across(self.x, self.width), self.bottom())
+        }
+    }
+
+    pub fn clamp(&self, point: Vec2<MouseType>) -> Vec2<MouseType> {
+        Vec2::new(point.x.clamp(self.x, self.right()), point.y.clamp(self.y, self.bottom()))
+    }
+}
+
+/// Estimates the position of a pointer from the relative movements that are sent to it
+///
+/// Movements that would leave the screen anywhere but the return edge get clamped, just like the real pointer.
+#[derive(Debug, Clone)]
+pub struct RemotePointer {
+    screen: Rect,
+    return_edge: Edge,
+    position: Vec2<MouseType>
+}
+
+impl RemotePointer {
+    pub fn new(screen: Rect, return_edge: Edge) -> Self {
+        Self {
+            screen,
+            return_edge,
+            position: Vec2::new(screen.x, screen.y)
+        }
+    }
+
+    pub fn screen(&self) -> Rect {
+        self.screen
+    }
+
+    /// Places the pointer on the return edge, at the given relative position along it
+    pub fn enter(&mut self, along: f64) -> Vec2<MouseType> {
+        self.position = self.screen.point_on(self.return_edge, along);
+        self.position
+    }
+
+    /// Tracks a movement and returns where along the return edge the pointer left the screen
+    pub fn moved(&mut self, x: MouseType, y: MouseType) -> Option<f64> {
+        let target = Vec2::new(self.position.x + x, self.position.y + y);
+        if self.screen.overshoot(self.return_edge, target) > 0 {
+            return Some(self.screen.along(self.return_edge, target));
+        }
+        self.position = self.screen.clamp(target);
+        None
+    }
+
+    /// Tracks a movement that can not leave the screen, not even through the return edge
+    pub fn warp(&mut self, x: MouseType, y: MouseType) {
+        self.position = self
+            .screen
+            .clamp(Vec2::new(self.position.x + x, self.position.y + y));
+    }
+}
+
+/// How often the pointer of the server left its screen through the return edge, appended by the server to every acknowledgement
+///
+/// The count wraps around, a client only has to notice that it changed.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct EdgeReturns {
+    pub count: u8,
+    /// Where along the edge the pointer left the screen the last time, from 0 to `u16::MAX`
+    pub along: u16
+}
+
+impl EdgeReturns {
+    pub fn record(&mut self, along: f64) {
+        self.count = self.count.wrapping_add(1);
+        self.along = (along.clamp(0.0, 1.0) * f64::from(u16::MAX)).round() as u16;
+    }
+
+    pub fn along(&self) -> f64 {
+        f64::from(self.along) / f64::from(u16::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pointer_returns_through_the_return_edge() {
+        let mut pointer = RemotePointer::new(Rect::screen((1280, 720)), Edge::Left);
+        pointer.enter(1.0);
+        assert_eq!(pointer.moved(500, 0), None);
+        assert_eq!(pointer.moved(-500, 0), None);
+        assert_eq!(pointer.moved(-1, 0), Some(1.0));
+    }
+
+    #[test]
+    fn warps_never_leave_the_screen() {
+        let mut pointer = RemotePointer::new(Rect::screen((1280, 720)), Edge::Left);
+        pointer.enter(0.5);
+        pointer.warp(-2560, -1440);
+        assert_eq!(pointer.moved(0, 719), None);
+        assert_eq!(pointer.moved(-1, 0), Some(1.0));
+    }
+
+    #[test]
+    fn returns_keep_the_position_along_the_edge() {
+        let mut returns = EdgeReturns::default();
+        returns.record(0.25);
+        assert_eq!(returns.count, 1);
+        assert!((returns.along() - 0.25).abs() < 1e-4);
+        returns.count = u8::MAX;
+        returns.record(1.0);
+        assert_eq!(returns, EdgeReturns { count: 0, along: u16::MAX });
+    }
+}
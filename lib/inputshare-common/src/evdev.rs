@@ -0,0 +1,312 @@
+//! Translates hid usages into the evdev codes of linux/input-event-codes.h
+//!
+//! The tables follow the mapping of the kernel (drivers/hid/hid-input.c), so a key injected through uinput
+//! behaves like the same key on a usb keyboard. Usages without an entry have no evdev equivalent.
+//! The constants are the part of linux/input-event-codes.h that the virtual devices need.
+
+use crate::{ConsumerDeviceCode, HidButtonCode, HidKeyCode, SystemControlCode};
+
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+
+pub const SYN_REPORT: u16 = 0x00;
+
+pub const REL_X: u16 = 0x00;
+pub const REL_Y: u16 = 0x01;
+pub const REL_HWHEEL: u16 = 0x06;
+pub const REL_WHEEL: u16 = 0x08;
+pub const REL_WHEEL_HI_RES: u16 = 0x0b;
+pub const REL_HWHEEL_HI_RES: u16 = 0x0c;
+
+pub const KEY_A: u16 = 30;
+pub const BTN_MISC: u16 = 0x100;
+pub const BTN_LEFT: u16 = 0x110;
+pub const BTN_RIGHT: u16 = 0x111;
+pub const BTN_MIDDLE: u16 = 0x112;
+pub const BTN_SIDE: u16 = 0x113;
+pub const BTN_EXTRA: u16 = 0x114;
+pub const BTN_TASK: u16 = 0x117;
+pub const KEY_OK: u16 = 0x160;
+pub const KEY_MAX: u16 = 0x2ff;
+
+pub const BUS_VIRTUAL: u16 = 0x06;
+
+/// The resolution of the high resolution scroll wheel events per detent
+pub const WHEEL_HI_RES: i32 = 120;
+
+#[rustfmt::skip]
+pub const KEYS: &[(HidKeyCode, u16)] = &[
+    (HidKeyCode::KeyA, 30),
+    (HidKeyCode::KeyB, 48),
+    (HidKeyCode::KeyC, 46),
+    (HidKeyCode::KeyD, 32),
+    (HidKeyCode::KeyE, 18),
+    (HidKeyCode::KeyF, 33),
+    (HidKeyCode::KeyG, 34),
+    (HidKeyCode::KeyH, 35),
+    (HidKeyCode::KeyI, 23),
+    (HidKeyCode::KeyJ, 36),
+    (HidKeyCode::KeyK, 37),
+    (HidKeyCode::KeyL, 38),
+    (HidKeyCode::KeyM, 50),
+    (HidKeyCode::KeyN, 49),
+    (HidKeyCode::KeyO, 24),
+    (HidKeyCode::KeyP, 25),
+    (HidKeyCode::KeyQ, 16),
+    (HidKeyCode::KeyR, 19),
+    (HidKeyCode::KeyS, 31),
+    (HidKeyCode::KeyT, 20),
+    (HidKeyCode::KeyU, 22),
+    (HidKeyCode::KeyV, 47),
+    (HidKeyCode::KeyW, 17),
+    (HidKeyCode::KeyX, 45),
+    (HidKeyCode::KeyY, 21),
+    (HidKeyCode::KeyZ, 44),
+    (HidKeyCode::Key1, 2),
+    (HidKeyCode::Key2, 3),
+    (HidKeyCode::Key3, 4),
+    (HidKeyCode::Key4, 5),
+    (HidKeyCode::Key5, 6),
+    (HidKeyCode::Key6, 7),
+    (HidKeyCode::Key7, 8),
+    (HidKeyCode::Key8, 9),
+    (HidKeyCode::Key9, 10),
+    (HidKeyCode::Key0, 11),
+    (HidKeyCode::Enter, 28),
+    (HidKeyCode::Escape, 1),
+    (HidKeyCode::Backspace, 14),
+    (HidKeyCode::Tab, 15),
+    (HidKeyCode::Space, 57),
+    (HidKeyCode::Minus, 12),
+    (HidKeyCode::Equal, 13),
+    (HidKeyCode::LeftBrace, 26),
+    (HidKeyCode::RightBrace, 27),
+    (HidKeyCode::Backslash, 43),
+    // the non-us hash key shares its code with the backslash
+    (HidKeyCode::HashTilde, 43),
+    (HidKeyCode::Semicolon, 39),
+    (HidKeyCode::Apostrophe, 40),
+    (HidKeyCode::Grave, 41),
+    (HidKeyCode::Comma, 51),
+    (HidKeyCode::Dot, 52),
+    (HidKeyCode::Slash, 53),
+    (HidKeyCode::Capslock, 58),
+    (HidKeyCode::F1, 59),
+    (HidKeyCode::F2, 60),
+    (HidKeyCode::F3, 61),
+    (HidKeyCode::F4, 62),
+    (HidKeyCode::F5, 63),
+    (HidKeyCode::F6, 64),
+    (HidKeyCode::F7, 65),
+    (HidKeyCode::F8, 66),
+    (HidKeyCode::F9, 67),
+    (HidKeyCode::F10, 68),
+    (HidKeyCode::F11, 87),
+    (HidKeyCode::F12, 88),
+    (HidKeyCode::PrintScreen, 99),
+    (HidKeyCode::ScrollLock, 70),
+    (HidKeyCode::Pause, 119),
+    (HidKeyCode::Insert, 110),
+    (HidKeyCode::Home, 102),
+    (HidKeyCode::PageUp, 104),
+    (HidKeyCode::Delete, 111),
+    (HidKeyCode::End, 107),
+    (HidKeyCode::PageDown, 109),
+    (HidKeyCode::Right, 106),
+    (HidKeyCode::Left, 105),
+    (HidKeyCode::Down, 108),
+    (HidKeyCode::Up, 103),
+    (HidKeyCode::NumLock, 69),
+    (HidKeyCode::KpSlash, 98),
+    (HidKeyCode::KpAsterisk, 55),
+    (HidKeyCode::KpMinus, 74),
+    (HidKeyCode::KpPlus, 78),
+    (HidKeyCode::KpEnter, 96),
+    (HidKeyCode::Kp1, 79),
+    (HidKeyCode::Kp2, 80),
+    (HidKeyCode::Kp3, 81),
+    (HidKeyCode::Kp4, 75),
+    (HidKeyCode::Kp5, 76),
+    (HidKeyCode::Kp6, 77),
+    (HidKeyCode::Kp7, 71),
+    (HidKeyCode::Kp8, 72),
+    (HidKeyCode::Kp9, 73),
+    (HidKeyCode::Kp0, 82),
+    (HidKeyCode::KpDot, 83),
+    (HidKeyCode::Key102ND, 86),
+    (HidKeyCode::Compose, 127),
+    (HidKeyCode::Power, 116),
+    (HidKeyCode::KpEqual, 117),
+    (HidKeyCode::F13, 183),
+    (HidKeyCode::F14, 184),
+    (HidKeyCode::F15, 185),
+    (HidKeyCode::F16, 186),
+    (HidKeyCode::F17, 187),
+    (HidKeyCode::F18, 188),
+    (HidKeyCode::F19, 189),
+    (HidKeyCode::F20, 190),
+    (HidKeyCode::F21, 191),
+    (HidKeyCode::F22, 192),
+    (HidKeyCode::F23, 193),
+    (HidKeyCode::F24, 194),
+    (HidKeyCode::Execute, 134),
+    (HidKeyCode::Help, 138),
+    (HidKeyCode::Menu, 130),
+    (HidKeyCode::Select, 132),
+    (HidKeyCode::Stop, 128),
+    (HidKeyCode::Again, 129),
+    (HidKeyCode::Undo, 131),
+    (HidKeyCode::Cut, 137),
+    (HidKeyCode::Copy, 133),
+    (HidKeyCode::Paste, 135),
+    (HidKeyCode::Find, 136),
+    (HidKeyCode::Mute, 113),
+    (HidKeyCode::VolumeUp, 115),
+    (HidKeyCode::VolumeDown, 114),
+    (HidKeyCode::KpComma, 121),
+    (HidKeyCode::International1, 89),
+    (HidKeyCode::International2, 93),
+    (HidKeyCode::International3, 124),
+    (HidKeyCode::International4, 92),
+    (HidKeyCode::International5, 94),
+    (HidKeyCode::International6, 95),
+    (HidKeyCode::Language1, 122),
+    (HidKeyCode::Language2, 123),
+    (HidKeyCode::Language3, 90),
+    (HidKeyCode::Language4, 91),
+    (HidKeyCode::Language5, 85),
+    (HidKeyCode::Clear, 111),
+    (HidKeyCode::LeftCtrl, 29),
+    (HidKeyCode::LeftShift, 42),
+    (HidKeyCode::LeftAlt, 56),
+    (HidKeyCode::LeftMeta, 125),
+    (HidKeyCode::RightCtrl, 97),
+    (HidKeyCode::RightShift, 54),
+    (HidKeyCode::RightAlt, 100),
+    (HidKeyCode::RightMeta, 126),
+];
+
+pub const BUTTONS: &[(HidButtonCode, u16)] = &[
+    (HidButtonCode::LButton, BTN_LEFT),
+    (HidButtonCode::RButton, BTN_RIGHT),
+    (HidButtonCode::MButton, BTN_MIDDLE),
+    (HidButtonCode::Button4, BTN_SIDE),
+    (HidButtonCode::Button5, BTN_EXTRA)
+];
+
+#[rustfmt::skip]
+pub const CONSUMER_KEYS: &[(ConsumerDeviceCode, u16)] = &[
+    (ConsumerDeviceCode::BrightnessUp, 225),
+    (ConsumerDeviceCode::BrightnessDown, 224),
+    (ConsumerDeviceCode::NextTrack, 163),
+    (ConsumerDeviceCode::PreviousTrack, 165),
+    (ConsumerDeviceCode::Stop, 166),
+    (ConsumerDeviceCode::PlayPause, 164),
+    (ConsumerDeviceCode::Mute, 113),
+    (ConsumerDeviceCode::BassBoost, 209),
+    (ConsumerDeviceCode::VolumeUp, 115),
+    (ConsumerDeviceCode::VolumeDown, 114),
+    (ConsumerDeviceCode::MediaSelect, 171),
+    (ConsumerDeviceCode::Mail, 155),
+    (ConsumerDeviceCode::Calculator, 140),
+    (ConsumerDeviceCode::MyComputer, 144),
+    (ConsumerDeviceCode::BrowserSearch, 217),
+    (ConsumerDeviceCode::BrowserHome, 172),
+    (ConsumerDeviceCode::BrowserBack, 158),
+    (ConsumerDeviceCode::BrowserForward, 159),
+    (ConsumerDeviceCode::BrowserStop, 128),
+    (ConsumerDeviceCode::BrowserRefresh, 173),
+    (ConsumerDeviceCode::BrowserFavorites, 156),
+];
+
+pub const SYSTEM_CONTROL_KEYS: &[(SystemControlCode, u16)] = &[
+    (SystemControlCode::PowerDown, 116),
+    (SystemControlCode::Sleep, 142),
+    (SystemControlCode::WakeUp, 143)
+];
+
+fn lookup<T: PartialEq>(table: &[(T, u16)], usage: T) -> Option<u16> {
+    table
+        .iter()
+        .find(|(entry, _)| *entry == usage)
+        .map(|(_, code)| *code)
+}
+
+pub fn key_code(key: HidKeyCode) -> Option<u16> {
+    lookup(KEYS, key)
+}
+
+pub fn button_code(button: HidButtonCode) -> Option<u16> {
+    lookup(BUTTONS, button)
+}
+
+pub fn consumer_code(key: ConsumerDeviceCode) -> Option<u16> {
+    lookup(CONSUMER_KEYS, key)
+}
+
+pub fn system_control_code(key: SystemControlCode) -> Option<u16> {
+    lookup(SYSTEM_CONTROL_KEYS, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn keys_match_the_kernel() {
+        assert_eq!(key_code(HidKeyCode::KeyA), Some(30));
+        assert_eq!(key_code(HidKeyCode::KeyZ), Some(44));
+        assert_eq!(key_code(HidKeyCode::Key1), Some(2));
+        assert_eq!(key_code(HidKeyCode::Key0), Some(11));
+        assert_eq!(key_code(HidKeyCode::Enter), Some(28));
+        assert_eq!(key_code(HidKeyCode::F12), Some(88));
+        assert_eq!(key_code(HidKeyCode::F13), Some(183));
+        assert_eq!(key_code(HidKeyCode::LeftMeta), Some(125));
+        assert_eq!(key_code(HidKeyCode::RightAlt), Some(100));
+    }
+
+    #[test]
+    fn usages_without_a_code_are_skipped() {
+        assert_eq!(key_code(HidKeyCode::None), None);
+        assert_eq!(key_code(HidKeyCode::ErrorRollOver), None);
+        assert_eq!(key_code(HidKeyCode::LockingCapsLock), None);
+        assert_eq!(button_code(HidButtonCode::None), None);
+        assert_eq!(consumer_code(ConsumerDeviceCode::Other(0x0001)), None);
+        assert_eq!(system_control_code(SystemControlCode::None), None);
+    }
+
+    #[test]
+    fn common_keys_are_mapped() {
+        // the usages up to 0x65 and the modifiers make up a regular keyboard
+        for key in (0x04..=0x65).chain(0xe0..=0xe7).map(HidKeyCode::from) {
+            assert!(key_code(key).is_some(), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn tables_have_unique_usages_and_valid_codes() {
+        fn check<T: std::hash::Hash + Eq + std::fmt::Debug + Copy>(table: &[(T, u16)]) {
+            let mut seen = HashSet::new();
+            for (usage, code) in table {
+                assert!(seen.insert(*usage), "{:?} is mapped twice", usage);
+                assert!(*code > 0 && *code <= KEY_MAX, "{:?} maps to {}", usage, code);
+            }
+        }
+        check(KEYS);
+        check(BUTTONS);
+        check(CONSUMER_KEYS);
+        check(SYSTEM_CONTROL_KEYS);
+    }
+
+    #[test]
+    fn buttons_and_system_keys() {
+        assert_eq!(button_code(HidButtonCode::LButton), Some(BTN_LEFT));
+        assert_eq!(button_code(HidButtonCode::Button5), Some(BTN_EXTRA));
+        assert_eq!(consumer_code(ConsumerDeviceCode::VolumeUp), key_code(HidKeyCode::VolumeUp));
+        assert_eq!(consumer_code(ConsumerDeviceCode::PlayPause), Some(164));
+        assert_eq!(system_control_code(SystemControlCode::Sleep), Some(142));
+    }
+}
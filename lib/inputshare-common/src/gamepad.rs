@@ -0,0 +1,133 @@
+use num_enum::{FromPrimitive, IntoPrimitive};
+
+use crate::{MessageType, Vec2};
+
+/// The buttons of a gamepad, numbered in the order of the HID button usages
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, IntoPrimitive)]
+#[repr(u8)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    Mode
+}
+
+impl GamepadButton {
+    pub fn mask(self) -> u16 {
+        1 << u8::from(self)
+    }
+}
+
+/// The direction of the d-pad, encoded the same way as the HID hat switch
+#[derive(Debug, Default, Copy, Clone, Hash, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
+#[repr(u8)]
+pub enum GamepadHat {
+    Up = 0,
+    UpRight = 1,
+    Right = 2,
+    DownRight = 3,
+    Down = 4,
+    DownLeft = 5,
+    Left = 6,
+    UpLeft = 7,
+    #[default]
+    Centered = 8
+}
+
+impl GamepadHat {
+    pub fn from_directions(up: bool, down: bool, left: bool, right: bool) -> Self {
+        match (up && !down, down && !up, left && !right, right && !left) {
+            (true, _, false, false) => GamepadHat::Up,
+            (true, _, false, true) => GamepadHat::UpRight,
+            (true, _, true, false) => GamepadHat::UpLeft,
+            (_, true, false, false) => GamepadHat::Down,
+            (_, true, false, true) => GamepadHat::DownRight,
+            (_, true, true, false) => GamepadHat::DownLeft,
+            (false, false, true, _) => GamepadHat::Left,
+            (false, false, _, true) => GamepadHat::Right,
+            _ => GamepadHat::Centered
+        }
+    }
+}
+
+/// A full snapshot of a gamepad
+///
+/// The sender only transmits the fields that changed since the last snapshot.
+/// Every field is absolute, so replaying the messages always results in the latest state.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct GamepadState {
+    pub buttons: u16,
+    pub hat: GamepadHat,
+    pub left_stick: Vec2<i16>,
+    pub right_stick: Vec2<i16>,
+    pub left_trigger: u8,
+    pub right_trigger: u8
+}
+
+impl GamepadState {
+    pub fn is_pressed(&self, button: GamepadButton) -> bool {
+        self.buttons & button.mask() != 0
+    }
+
+    pub fn set_pressed(&mut self, button: GamepadButton, pressed: bool) {
+        match pressed {
+            true => self.buttons |= button.mask(),
+            false => self.buttons &= !button.mask()
+        }
+    }
+
+    /// Returns the messages that are required to turn `self` into `new`
+    pub fn diff(&self, new: &GamepadState) -> Vec<(MessageType, u16)> {
+        let mut messages = Vec::new();
+        if self.buttons != new.buttons {
+            messages.push((MessageType::GamepadButtons, new.buttons));
+        }
+        if self.hat != new.hat {
+            messages.push((MessageType::GamepadHat, u8::from(new.hat).into()));
+        }
+        if self.left_stick.x != new.left_stick.x {
+            messages.push((MessageType::GamepadLeftStickX, new.left_stick.x as u16));
+        }
+        if self.left_stick.y != new.left_stick.y {
+            messages.push((MessageType::GamepadLeftStickY, new.left_stick.y as u16));
+        }
+        if self.right_stick.x != new.right_stick.x {
+            messages.push((MessageType::GamepadRightStickX, new.right_stick.x as u16));
+        }
+        if self.right_stick.y != new.right_stick.y {
+            messages.push((MessageType::GamepadRightStickY, new.right_stick.y as u16));
+        }
+        if self.left_trigger != new.left_trigger {
+            messages.push((MessageType::GamepadLeftTrigger, new.left_trigger.into()));
+        }
+        if self.right_trigger != new.right_trigger {
+            messages.push((MessageType::GamepadRightTrigger, new.right_trigger.into()));
+        }
+        messages
+    }
+
+    /// Applies a single message created by `diff`
+    ///
+    /// Returns `false` if the message is not a gamepad message
+    pub fn apply(&mut self, msg: MessageType, arg: u16) -> bool {
+        match msg {
+            MessageType::GamepadButtons => self.buttons = arg,
+            MessageType::GamepadHat => self.hat = GamepadHat::from(arg as u8),
+            MessageType::GamepadLeftStickX => self.left_stick.x = arg as i16,
+            MessageType::GamepadLeftStickY => self.left_stick.y = arg as i16,
+            MessageType::GamepadRightStickX => self.right_stick.x = arg as i16,
+            MessageType::GamepadRightStickY => self.right_stick.y = arg as i16,
+            MessageType::GamepadLeftTrigger => self.left_trigger = arg as u8,
+            MessageType::GamepadRightTrigger => self.right_trigger = arg as u8,
+            _ => return false
+        }
+        true
+    }
+}
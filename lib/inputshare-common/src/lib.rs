@@ -1,10 +1,30 @@
+mod control;
+pub mod edge;
+pub mod evdev;
+mod gamepad;
+#[cfg(target_os = "linux")]
+pub mod uinput;
+
 use std::fmt::Debug;
 
+use bitflags::bitflags;
 use num_enum::{FromPrimitive, IntoPrimitive, TryFromPrimitive};
+use serde::{Deserialize, Serialize};
+
+pub use crate::control::{
+    decode, encode, ActionOutput, ControlRequest, ControlResponse, PowerAction, PresencePattern, PresenceSettings, RemoteActionInfo,
+    MAX_CONTROL_MESSAGE_SIZE
+};
+pub use crate::gamepad::{GamepadButton, GamepadHat, GamepadState};
 
 pub const IDENTIFIER: &str = concat!(env!("CARGO_CRATE_NAME"), "_", env!("CARGO_PKG_VERSION"));
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+/// The ALPN protocol of the connection, changes whenever the encoding of the messages does
+///
+/// Peers that can not agree on it fail during the handshake instead of misreading each other's packets.
+pub const PROTOCOL: &[u8] = b"inputshare/3";
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub struct Vec2<T>
 where
     T: Debug + Copy + PartialEq
@@ -24,6 +44,26 @@ where
 
 pub type MouseType = i64;
 
+/// The number of high resolution scroll units that make up one wheel detent
+pub const SCROLL_RESOLUTION: i16 = 120;
+
+/// The size of a single encoded message: the `MessageType` followed by a little endian `u16` argument
+pub const MESSAGE_SIZE: usize = 3;
+
+bitflags! {
+    /// The state of the emulated devices, appended by the server to every acknowledgement
+    #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+    pub struct DeviceStatus: u8 {
+        const KEYBOARD_STALLED = 0x01;
+        const MOUSE_STALLED = 0x02;
+        const CONSUMER_DEVICE_STALLED = 0x04;
+        const SYSTEM_CONTROL_STALLED = 0x08;
+        const GAMEPAD_STALLED = 0x10;
+        /// The server lost access to the target and is trying to recover
+        const UNAVAILABLE = 0x80;
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
 #[repr(u8)]
 pub enum MessageType {
@@ -36,10 +76,25 @@ pub enum MessageType {
     HorizontalScrolling,
     VerticalScrolling,
     Reset,
-    Shutdown
+    /// No longer used, power actions are requested through the control channel
+    Shutdown,
+    SystemControlPress,
+    SystemControlRelease,
+    GamepadButtons,
+    GamepadHat,
+    GamepadLeftStickX,
+    GamepadLeftStickY,
+    GamepadRightStickX,
+    GamepadRightStickY,
+    GamepadLeftTrigger,
+    GamepadRightTrigger,
+    /// Carries an `i16` in units of `1 / SCROLL_RESOLUTION` detents
+    HighResHorizontalScrolling,
+    /// Carries an `i16` in units of `1 / SCROLL_RESOLUTION` detents
+    HighResVerticalScrolling
 }
 
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, IntoPrimitive, FromPrimitive, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum HidButtonCode {
     #[num_enum(default)]
@@ -51,38 +106,55 @@ pub enum HidButtonCode {
     Button5 = 0x05
 }
 
+/// A usage id from the System Control collection of the Generic Desktop page (0x01)
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
 #[repr(u8)]
-pub enum ConsumerDeviceCode {
+pub enum SystemControlCode {
     #[num_enum(default)]
     None = 0x00,
-    NextTrack,
-    PreviousTrack,
-    Stop,
-    PlayPause,
-    Mute,
-    BassBoost,
-    Loudness,
-    VolumeUp,
-    VolumeDown,
-    BassUp,
-    BassDown,
-    TrebleUp,
-    TrebleDown,
-    MediaSelect,
-    Mail,
-    Calculator,
-    MyComputer,
-    BrowserSearch,
-    BrowserHome,
-    BrowserBack,
-    BrowserForward,
-    BrowserStop,
-    BrowserRefresh,
-    BrowserFavorites
+    PowerDown = 0x81,
+    Sleep = 0x82,
+    WakeUp = 0x83
 }
 
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
+/// A usage id from the HID Consumer page (0x0C)
+///
+/// Usages without a dedicated variant are carried in `Other`
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, IntoPrimitive, FromPrimitive, Serialize, Deserialize)]
+#[repr(u16)]
+pub enum ConsumerDeviceCode {
+    None = 0x0000,
+    BrightnessUp = 0x006F,
+    BrightnessDown = 0x0070,
+    NextTrack = 0x00B5,
+    PreviousTrack = 0x00B6,
+    Stop = 0x00B7,
+    PlayPause = 0x00CD,
+    Mute = 0x00E2,
+    BassBoost = 0x00E5,
+    Loudness = 0x00E7,
+    VolumeUp = 0x00E9,
+    VolumeDown = 0x00EA,
+    BassUp = 0x0152,
+    BassDown = 0x0153,
+    TrebleUp = 0x0154,
+    TrebleDown = 0x0155,
+    MediaSelect = 0x0183,
+    Mail = 0x018A,
+    Calculator = 0x0192,
+    MyComputer = 0x0194,
+    BrowserSearch = 0x0221,
+    BrowserHome = 0x0223,
+    BrowserBack = 0x0224,
+    BrowserForward = 0x0225,
+    BrowserStop = 0x0226,
+    BrowserRefresh = 0x0227,
+    BrowserFavorites = 0x022A,
+    #[num_enum(catch_all)]
+    Other(u16)
+}
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, IntoPrimitive, FromPrimitive, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum HidKeyCode {
     #[num_enum(default)]
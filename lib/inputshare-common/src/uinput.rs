@@ -0,0 +1,110 @@
+//! Safe wrappers around the ioctls of linux/input.h and linux/uinput.h
+
+use std::fs::File;
+use std::io::{Error, Read, Result, Write};
+use std::mem::{size_of, size_of_val, zeroed};
+use std::os::fd::AsRawFd;
+use std::slice;
+
+use libc::{c_int, input_event, uinput_setup};
+
+pub fn event(type_: u16, code: u16, value: i32) -> input_event {
+    // zeroed to stay independent of the layout of the time fields
+    let mut event: input_event = unsafe { zeroed() };
+    event.type_ = type_;
+    event.code = code;
+    event.value = value;
+    event
+}
+
+fn check(result: c_int) -> Result<()> {
+    match result < 0 {
+        true => Err(Error::last_os_error()),
+        false => Ok(())
+    }
+}
+
+pub fn test_bit(bits: &[u8], bit: u16) -> bool {
+    bits.get(usize::from(bit / 8))
+        .is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+}
+
+/// EVIOCGBIT: the supported event types for `ev == 0`, otherwise the supported codes of the event type
+pub fn event_bits(file: &File, ev: u16) -> Result<[u8; 96]> {
+    let mut bits = [0u8; 96];
+    check(unsafe {
+        libc::ioctl(
+            file.as_raw_fd(),
+            libc::_IOR::<[u8; 96]>(b'E'.into(), 0x20 + u32::from(ev)),
+            bits.as_mut_ptr()
+        )
+    })?;
+    Ok(bits)
+}
+
+/// EVIOCGNAME
+pub fn device_name(file: &File) -> Result<String> {
+    let mut name = [0u8; 256];
+    check(unsafe { libc::ioctl(file.as_raw_fd(), libc::_IOR::<[u8; 256]>(b'E'.into(), 0x06), name.as_mut_ptr()) })?;
+    let length = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+    Ok(String::from_utf8_lossy(&name[..length]).into_owned())
+}
+
+/// EVIOCGRAB: keeps the events of the device from everyone else
+pub fn grab(file: &File, grab: bool) -> Result<()> {
+    check(unsafe { libc::ioctl(file.as_raw_fd(), libc::_IOW::<c_int>(b'E'.into(), 0x90), c_int::from(grab)) })
+}
+
+pub fn set_event_bit(file: &File, ev: u16) -> Result<()> {
+    check(unsafe { libc::ioctl(file.as_raw_fd(), libc::_IOW::<c_int>(b'U'.into(), 100), c_int::from(ev)) })
+}
+
+pub fn set_key_bit(file: &File, key: u16) -> Result<()> {
+    check(unsafe { libc::ioctl(file.as_raw_fd(), libc::_IOW::<c_int>(b'U'.into(), 101), c_int::from(key)) })
+}
+
+pub fn set_rel_bit(file: &File, rel: u16) -> Result<()> {
+    check(unsafe { libc::ioctl(file.as_raw_fd(), libc::_IOW::<c_int>(b'U'.into(), 102), c_int::from(rel)) })
+}
+
+pub fn setup_device(file: &File, name: &str, bustype: u16) -> Result<()> {
+    let mut setup: uinput_setup = unsafe { zeroed() };
+    setup.id.bustype = bustype;
+    setup.id.version = 1;
+    // the last byte stays zero to terminate the name
+    for (dst, src) in setup
+        .name
+        .iter_mut()
+        .zip(name.bytes().take(libc::UINPUT_MAX_NAME_SIZE - 1))
+    {
+        *dst = src as libc::c_char;
+    }
+    check(unsafe { libc::ioctl(file.as_raw_fd(), libc::_IOW::<uinput_setup>(b'U'.into(), 3), &setup) })
+}
+
+pub fn create_device(file: &File) -> Result<()> {
+    check(unsafe { libc::ioctl(file.as_raw_fd(), libc::_IO(b'U'.into(), 1)) })
+}
+
+pub fn destroy_device(file: &File) -> Result<()> {
+    check(unsafe { libc::ioctl(file.as_raw_fd(), libc::_IO(b'U'.into(), 2)) })
+}
+
+/// Reads as many events as are available without blocking
+pub fn read_events(mut file: &File, events: &mut Vec<input_event>) -> Result<()> {
+    let mut buffer = [event(0, 0, 0); 64];
+    loop {
+        let bytes = unsafe { slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, size_of::<input_event>() * buffer.len()) };
+        match file.read(bytes) {
+            Ok(0) => return Ok(()),
+            Ok(read) => events.extend_from_slice(&buffer[..read / size_of::<input_event>()]),
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => return Ok(()),
+            Err(err) => return Err(err)
+        }
+    }
+}
+
+pub fn write_events(mut file: &File, events: &[input_event]) -> Result<()> {
+    let bytes = unsafe { slice::from_raw_parts(events.as_ptr() as *const u8, size_of_val(events)) };
+    file.write_all(bytes)
+}
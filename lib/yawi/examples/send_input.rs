@@ -3,7 +3,7 @@ use std::time::Duration;
 
 use yawi::{Input, KeyState, VirtualKey};
 
-fn main() -> windows::core::Result<()> {
+fn main() -> yawi::Result<()> {
     yawi::send_inputs([
         Input::KeyboardKeyInput(VirtualKey::LWin, KeyState::Pressed),
         Input::KeyboardKeyInput(VirtualKey::LWin, KeyState::Released)
@@ -0,0 +1,188 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::iter::once;
+use std::marker::PhantomData;
+use std::sync::{Arc, OnceLock};
+
+use crate::{Input, InputEvent};
+
+pub type Result<T> = std::io::Result<T>;
+
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum HookAction {
+    #[default]
+    Continue,
+    Block
+}
+
+#[repr(transparent)]
+pub struct HookFn(Box<dyn FnMut(InputEvent) -> HookAction + Send>);
+
+impl HookFn {
+    pub fn new(callback: impl FnMut(InputEvent) -> HookAction + Send + 'static) -> Self {
+        Self(Box::new(callback))
+    }
+
+    pub(crate) fn handle(&mut self, event: InputEvent) -> HookAction {
+        self.0(event)
+    }
+}
+
+impl<F: FnMut(InputEvent) -> HookAction + Send + 'static> From<F> for HookFn {
+    fn from(value: F) -> Self {
+        HookFn::new(value)
+    }
+}
+
+/// A way to capture and simulate the input of a desktop
+pub trait Backend: Send + Sync {
+    /// Passes every local input event to `callback` until the returned hook is dropped
+    ///
+    /// Blocked events never reach the rest of the system, events that were sent by the backend itself are not passed.
+    fn register_hook(&self, callback: HookFn) -> Result<InputHook>;
+
+    /// Simulates the inputs in order
+    fn send_inputs(&self, inputs: &[Input]) -> Result<()>;
+
+    fn cursor_pos(&self) -> (i32, i32);
+
+    /// Moves the cursor to a position on the virtual screen without generating an input event
+    fn set_cursor_pos(&self, x: i32, y: i32) -> Result<()>;
+
+    /// Returns the bounds of the desktop spanning all monitors as `(x, y, width, height)`
+    fn virtual_screen(&self) -> (i32, i32, i32, i32);
+}
+
+/// A registered hook, dropping it unregisters the callback
+pub struct InputHook {
+    _guard: Box<dyn Any>
+}
+
+impl InputHook {
+    /// Registers `callback` with the backend of the current thread
+    ///
+    /// See `backend` for more info
+    #[must_use = "The hook will immediately be unregistered and not work."]
+    pub fn register(callback: impl Into<HookFn>) -> Result<InputHook> {
+        backend().register_hook(callback.into())
+    }
+
+    /// Wraps the state of a backend that unregisters the callback when it gets dropped
+    pub fn from_guard(guard: impl Any) -> Self {
+        Self { _guard: Box::new(guard) }
+    }
+}
+
+thread_local! {
+    static OVERRIDE: RefCell<Option<Arc<dyn Backend>>> = RefCell::default();
+}
+
+/// Returns the backend that the free functions of this crate use on the current thread
+///
+/// This is the backend of the current platform unless it was replaced with `use_backend`.
+pub fn backend() -> Arc<dyn Backend> {
+    OVERRIDE
+        .with(|current| current.borrow().clone())
+        .unwrap_or_else(platform)
+}
+
+fn platform() -> Arc<dyn Backend> {
+    static PLATFORM: OnceLock<Arc<dyn Backend>> = OnceLock::new();
+    PLATFORM
+        .get_or_init(|| {
+            #[cfg(windows)]
+            let backend = crate::win32::Win32;
+            #[cfg(target_os = "linux")]
+            let backend = crate::linux::Linux::from_env();
+            #[cfg(not(any(windows, target_os = "linux")))]
+            let backend = Unsupported;
+            Arc::new(backend)
+        })
+        .clone()
+}
+
+/// Replaces the backend of the current thread until the returned guard is dropped
+pub fn use_backend(backend: Arc<dyn Backend>) -> BackendGuard {
+    let previous = OVERRIDE.with(|current| current.replace(Some(backend)));
+    BackendGuard {
+        previous,
+        _thread: PhantomData
+    }
+}
+
+/// Restores the previous backend of its thread when dropped
+#[must_use = "The backend will immediately be restored."]
+pub struct BackendGuard {
+    previous: Option<Arc<dyn Backend>>,
+    // the guard has to be dropped on the thread whose backend it replaced
+    _thread: PhantomData<*const ()>
+}
+
+impl Drop for BackendGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        OVERRIDE.with(|current| current.replace(previous));
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+struct Unsupported;
+
+#[cfg(not(any(windows, target_os = "linux")))]
+impl Unsupported {
+    fn error() -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::Unsupported, "yawi has no backend for this platform")
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+impl Backend for Unsupported {
+    fn register_hook(&self, _: HookFn) -> Result<InputHook> {
+        Err(Self::error())
+    }
+
+    fn send_inputs(&self, _: &[Input]) -> Result<()> {
+        Err(Self::error())
+    }
+
+    fn cursor_pos(&self) -> (i32, i32) {
+        (0, 0)
+    }
+
+    fn set_cursor_pos(&self, _: i32, _: i32) -> Result<()> {
+        Err(Self::error())
+    }
+
+    fn virtual_screen(&self) -> (i32, i32, i32, i32) {
+        (0, 0, 0, 0)
+    }
+}
+
+/// Send multiple input events through the backend of the current thread
+///
+/// Return Ok if all inputs were sent
+pub fn send_inputs<'a>(inputs: impl IntoIterator<Item = Input<'a>>) -> Result<()> {
+    let inputs: Vec<Input> = inputs.into_iter().collect();
+    backend().send_inputs(&inputs)
+}
+
+/// Convenience function to send a single input
+///
+/// See `send_inputs` for more info
+pub fn send_input(input: Input) -> Result<()> {
+    send_inputs(once(input))
+}
+
+pub fn get_cursor_pos() -> (i32, i32) {
+    backend().cursor_pos()
+}
+
+/// Moves the cursor to a position on the virtual screen without generating an input event
+pub fn set_cursor_pos(x: i32, y: i32) -> Result<()> {
+    backend().set_cursor_pos(x, y)
+}
+
+/// Returns the bounds of the desktop spanning all monitors as `(x, y, width, height)`
+pub fn get_virtual_screen() -> (i32, i32, i32, i32) {
+    backend().virtual_screen()
+}
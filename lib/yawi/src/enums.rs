@@ -1,8 +1,12 @@
+#[cfg(windows)]
 use std::char::REPLACEMENT_CHARACTER;
 use std::fmt;
-use std::fmt::{Display, Formatter, Write};
+#[cfg(windows)]
+use std::fmt::Write;
+use std::fmt::{Display, Formatter};
 
 use num_enum::{IntoPrimitive, TryFromPrimitive};
+#[cfg(windows)]
 use windows::Win32::UI::Input::KeyboardAndMouse::{GetKeyNameTextW, MapVirtualKeyW, MAPVK_VK_TO_VSC_EX, VIRTUAL_KEY};
 
 pub type WindowsScanCode = u16;
@@ -67,7 +71,7 @@ pub enum Input<'a> {
     ///
     /// # Arguments
     /// * x, y - The new mouse coordinates in pixels.
-    /// (0,0) is the top-left corner of the primary monitor
+    ///   (0,0) is the top-left corner of the primary monitor
     AbsoluteMouseMoveInput(i32, i32)
 }
 
@@ -84,6 +88,47 @@ pub enum ScrollDirection {
     Vertical(f32)
 }
 
+/// The buttons of an XInput controller
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoPrimitive)]
+#[repr(u16)]
+pub enum ControllerButton {
+    DPadUp = 0x0001,
+    DPadDown = 0x0002,
+    DPadLeft = 0x0004,
+    DPadRight = 0x0008,
+    Start = 0x0010,
+    Back = 0x0020,
+    LeftThumb = 0x0040,
+    RightThumb = 0x0080,
+    LeftShoulder = 0x0100,
+    RightShoulder = 0x0200,
+    Guide = 0x0400,
+    A = 0x1000,
+    B = 0x2000,
+    X = 0x4000,
+    Y = 0x8000
+}
+
+/// A snapshot of an XInput controller
+///
+/// Created by calling `get_controller_state()`
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ControllerState {
+    pub buttons: u16,
+    pub left_trigger: u8,
+    pub right_trigger: u8,
+    /// x,y - positive values point right and up
+    pub left_thumb: (i16, i16),
+    /// x,y - positive values point right and up
+    pub right_thumb: (i16, i16)
+}
+
+impl ControllerState {
+    pub fn is_pressed(&self, button: ControllerButton) -> bool {
+        self.buttons & u16::from(button) != 0
+    }
+}
+
 /// The state of a key or mouse button
 #[derive(Debug, Copy, Clone, PartialEq, Hash)]
 pub enum KeyState {
@@ -327,6 +372,7 @@ pub enum VirtualKey {
     OemClear = 0xFE
 }
 
+#[cfg(windows)]
 impl From<VirtualKey> for VIRTUAL_KEY {
     fn from(value: VirtualKey) -> Self {
         VIRTUAL_KEY(u8::from(value).into())
@@ -346,32 +392,31 @@ impl Display for VirtualKey {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match KEY_NAMES[u8::from(*self) as usize] {
             None => f.write_str("<UNKNOWN>"),
-            Some(name) => match name.contains("OEM") {
-                true => {
-                    let mut buffer = [0u16; 512];
-                    let length = unsafe {
-                        let scan_code = MapVirtualKeyW(u8::from(*self) as u32, MAPVK_VK_TO_VSC_EX);
-                        let extended = scan_code & 0xFF00 == 0xE100 || scan_code & 0xFF00 == 0xE000;
-                        let scan_code = (scan_code & 0xFF) << 16 | u32::from(extended) << 24;
-                        GetKeyNameTextW(scan_code as i32, &mut buffer) as usize
-                    };
-                    let iter = char::decode_utf16(buffer[..length].iter().copied()).map(|r| r.unwrap_or(REPLACEMENT_CHARACTER));
-                    let mut start = true;
-                    for mut c in iter {
-                        if c.is_whitespace() {
-                            start = true;
-                        } else if start {
-                            c = c.to_ascii_uppercase();
-                            start = false;
-                        } else {
-                            c = c.to_ascii_lowercase();
-                        }
-                        f.write_char(c)?
+            #[cfg(windows)]
+            Some(name) if name.contains("OEM") => {
+                let mut buffer = [0u16; 512];
+                let length = unsafe {
+                    let scan_code = MapVirtualKeyW(u8::from(*self) as u32, MAPVK_VK_TO_VSC_EX);
+                    let extended = scan_code & 0xFF00 == 0xE100 || scan_code & 0xFF00 == 0xE000;
+                    let scan_code = (scan_code & 0xFF) << 16 | u32::from(extended) << 24;
+                    GetKeyNameTextW(scan_code as i32, &mut buffer) as usize
+                };
+                let iter = char::decode_utf16(buffer[..length].iter().copied()).map(|r| r.unwrap_or(REPLACEMENT_CHARACTER));
+                let mut start = true;
+                for mut c in iter {
+                    if c.is_whitespace() {
+                        start = true;
+                    } else if start {
+                        c = c.to_ascii_uppercase();
+                        start = false;
+                    } else {
+                        c = c.to_ascii_lowercase();
                     }
-                    Ok(())
+                    f.write_char(c)?
                 }
-                false => f.write_str(name)
+                Ok(())
             }
+            Some(name) => f.write_str(name)
         }
     }
 }
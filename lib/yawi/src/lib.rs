@@ -1,13 +1,23 @@
+mod backend;
 mod enums;
-mod hook;
-mod message;
-mod query;
-mod send;
+#[cfg(target_os = "linux")]
+mod linux;
+pub mod mock;
+#[cfg(windows)]
+mod win32;
 
-pub type WinResult<T> = windows::core::Result<T>;
+pub use backend::{
+    backend, get_cursor_pos, get_virtual_screen, send_input, send_inputs, set_cursor_pos, use_backend, Backend, BackendGuard, HookAction, HookFn,
+    InputHook, Result
+};
+pub use enums::{ControllerButton, ControllerState, Input, InputEvent, KeyEvent, KeyState, ScrollDirection, VirtualKey, WindowsScanCode};
+#[cfg(target_os = "linux")]
+pub use linux::Linux;
+#[cfg(windows)]
+pub use win32::{get_controller_state, quit, run, Win32};
 
-pub use enums::{Input, InputEvent, KeyEvent, KeyState, ScrollDirection, VirtualKey, WindowsScanCode};
-pub use hook::{HookAction, HookFn, InputHook};
-pub use message::{quit, run};
-pub use query::get_cursor_pos;
-pub use send::{send_input, send_inputs};
+/// XInput only exists on windows, so there never is a controller
+#[cfg(not(windows))]
+pub fn get_controller_state(_user_index: u32) -> Option<ControllerState> {
+    None
+}
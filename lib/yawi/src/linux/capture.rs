@@ -0,0 +1,200 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::iter::once;
+use std::mem::take;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use inputshare_common::evdev::{
+    EV_KEY, EV_REL, EV_SYN, REL_HWHEEL, REL_HWHEEL_HI_RES, REL_WHEEL, REL_WHEEL_HI_RES, REL_X, REL_Y, SYN_REPORT, WHEEL_HI_RES
+};
+use inputshare_common::uinput::event;
+use libc::input_event;
+
+use crate::linux::device::InputDevice;
+use crate::linux::{keys, push_frame, Shared};
+use crate::{HookAction, HookFn, InputEvent, KeyState, ScrollDirection};
+
+const SYN_DROPPED: u16 = 0x03;
+
+/// Grabs the input devices and passes their events to the hook on a separate thread
+///
+/// Everything the hook lets through is forwarded to the virtual device.
+pub struct Capture {
+    wake: File,
+    thread: Option<JoinHandle<()>>
+}
+
+impl Capture {
+    pub fn start(shared: Arc<Shared>, callback: HookFn) -> Result<Self> {
+        // the virtual device has to exist before the grabbed devices go silent
+        shared.write(&[])?;
+        let devices = InputDevice::grab_all()?;
+        let (stop, wake) = pipe()?;
+        let thread = thread::Builder::new()
+            .name("yawi capture".into())
+            .spawn(move || capture(&shared, devices, stop, callback))?;
+        Ok(Self { wake, thread: Some(thread) })
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        tracing::trace!("Releasing the input devices");
+        (&self.wake)
+            .write_all(&[0])
+            .unwrap_or_else(|err| tracing::warn!("Could not stop the capture thread: {}", err));
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                tracing::warn!("The capture thread panicked");
+            }
+        }
+    }
+}
+
+fn pipe() -> Result<(File, File)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
+
+/// Runs until `stop` becomes readable, the devices are released when they get dropped
+fn capture(shared: &Shared, mut devices: Vec<InputDevice>, stop: File, mut callback: HookFn) {
+    let mut events = Vec::new();
+    loop {
+        let mut fds: Vec<libc::pollfd> = devices
+            .iter()
+            .map(|device| device.as_raw_fd())
+            .chain(once(stop.as_raw_fd()))
+            .map(|fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0
+            })
+            .collect();
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+            let err = Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            tracing::warn!("Could not wait for input: {}", err);
+            return;
+        }
+        if fds.last().is_some_and(|fd| fd.revents != 0) {
+            return;
+        }
+        let mut lost = Vec::new();
+        for (index, (device, fd)) in devices.iter_mut().zip(&fds).enumerate() {
+            if fd.revents == 0 {
+                continue;
+            }
+            events.clear();
+            if let Err(err) = device.read(&mut events) {
+                tracing::warn!("Lost {}: {}", device.name(), err);
+                lost.push(index);
+                continue;
+            }
+            for event in &events {
+                match (event.type_, event.code) {
+                    (EV_SYN, SYN_REPORT) => dispatch(shared, &mut callback, &take(&mut device.frame)),
+                    // the kernel ran out of buffer space, the incomplete frame is useless
+                    (EV_SYN, SYN_DROPPED) => device.frame.clear(),
+                    _ => device.frame.push(*event)
+                }
+            }
+        }
+        for index in lost.into_iter().rev() {
+            devices.remove(index);
+        }
+    }
+}
+
+/// The events of one scroll wheel in a frame
+#[derive(Default)]
+struct Wheel {
+    detents: i32,
+    hi_res: Option<i32>,
+    events: Vec<input_event>
+}
+
+impl Wheel {
+    fn push(&mut self, event: input_event, hi_res: bool) {
+        match hi_res {
+            true => *self.hi_res.get_or_insert(0) += event.value,
+            false => self.detents += event.value
+        }
+        self.events.push(event);
+    }
+
+    /// The scrolled distance in detents, the high resolution events are preferred if the device has them
+    fn amount(&self) -> Option<f32> {
+        match self.hi_res {
+            Some(hi_res) => Some(hi_res as f32 / WHEEL_HI_RES as f32),
+            None if self.detents != 0 => Some(self.detents as f32),
+            None => None
+        }
+    }
+}
+
+/// Passes the events of a frame to the hook and forwards the ones it did not block
+fn dispatch(shared: &Shared, callback: &mut HookFn, frame: &[input_event]) {
+    let mut forward = Vec::with_capacity(frame.len());
+    let (mut dx, mut dy) = (0, 0);
+    let mut vertical = Wheel::default();
+    let mut horizontal = Wheel::default();
+    for event in frame {
+        match (event.type_, event.code) {
+            (EV_KEY, code) => {
+                // repeats have the value 2 and are reported as presses, just like on windows
+                let state = match event.value {
+                    0 => KeyState::Released,
+                    _ => KeyState::Pressed
+                };
+                let translated = match keys::key_from_evdev(code) {
+                    Some((key, scancode)) => Some(InputEvent::KeyboardKeyEvent(key, scancode, state)),
+                    None => keys::button_from_evdev(code).map(|button| InputEvent::MouseButtonEvent(button, state))
+                };
+                if translated.map_or(HookAction::Continue, |translated| callback.handle(translated)) == HookAction::Continue {
+                    forward.push(*event);
+                }
+            }
+            (EV_REL, REL_X) => dx += event.value,
+            (EV_REL, REL_Y) => dy += event.value,
+            (EV_REL, REL_WHEEL) => vertical.push(*event, false),
+            (EV_REL, REL_WHEEL_HI_RES) => vertical.push(*event, true),
+            (EV_REL, REL_HWHEEL) => horizontal.push(*event, false),
+            (EV_REL, REL_HWHEEL_HI_RES) => horizontal.push(*event, true),
+            _ => forward.push(*event)
+        }
+    }
+    if dx != 0 || dy != 0 {
+        let (x, y) = *shared.cursor();
+        // like on windows the hook sees where the cursor would go, even past the edge of the screen
+        let target = (x + dx, y + dy);
+        if callback.handle(InputEvent::MouseMoveEvent(target.0, target.1)) == HookAction::Continue {
+            *shared.cursor() = shared.screen.clamp(target);
+            forward.extend([event(EV_REL, REL_X, dx), event(EV_REL, REL_Y, dy)]);
+        }
+    }
+    for (wheel, direction) in [
+        (vertical, ScrollDirection::Vertical as fn(f32) -> ScrollDirection),
+        (horizontal, ScrollDirection::Horizontal)
+    ] {
+        let action = wheel.amount().map_or(HookAction::Continue, |amount| {
+            callback.handle(InputEvent::MouseWheelEvent(direction(amount)))
+        });
+        if action == HookAction::Continue {
+            forward.extend(wheel.events);
+        }
+    }
+    if !forward.is_empty() {
+        let mut events = Vec::with_capacity(forward.len() + 1);
+        push_frame(&mut events, &forward);
+        shared
+            .write(&events)
+            .unwrap_or_else(|err| tracing::warn!("Could not forward input: {}", err));
+    }
+}
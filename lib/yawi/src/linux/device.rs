@@ -0,0 +1,140 @@
+use std::fs::{read_dir, File, OpenOptions};
+use std::io::{Error, ErrorKind, Result};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+
+use inputshare_common::evdev::{
+    BTN_LEFT, BTN_MISC, BTN_TASK, BUS_VIRTUAL, EV_KEY, EV_REL, KEY_A, KEY_MAX, KEY_OK, REL_HWHEEL, REL_HWHEEL_HI_RES, REL_WHEEL, REL_WHEEL_HI_RES,
+    REL_X, REL_Y
+};
+use inputshare_common::uinput::{
+    create_device, destroy_device, device_name, event_bits, grab, read_events, set_event_bit, set_key_bit, set_rel_bit, setup_device, test_bit,
+    write_events
+};
+use libc::input_event;
+
+/// The name of the devices created by yawi, they are never grabbed
+const VIRTUAL_DEVICE_NAME: &str = "yawi virtual input";
+
+/// A grabbed keyboard or mouse
+pub struct InputDevice {
+    file: File,
+    name: String,
+    /// The events since the last `SYN_REPORT`
+    pub frame: Vec<input_event>
+}
+
+impl InputDevice {
+    /// Opens and grabs every keyboard and mouse in `/dev/input`
+    pub fn grab_all() -> Result<Vec<InputDevice>> {
+        let mut devices = Vec::new();
+        let mut denied = false;
+        for entry in read_dir("/dev/input")? {
+            let path = entry?.path();
+            if !path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("event"))
+            {
+                continue;
+            }
+            let file = match OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+                .open(&path)
+            {
+                Ok(file) => file,
+                Err(err) => {
+                    denied |= err.kind() == ErrorKind::PermissionDenied;
+                    tracing::debug!("Could not open {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+            let name = device_name(&file)?;
+            if name == VIRTUAL_DEVICE_NAME || !is_keyboard_or_mouse(&file)? {
+                continue;
+            }
+            if let Err(err) = grab(&file, true) {
+                tracing::warn!("Could not grab {}: {}", name, err);
+                continue;
+            }
+            tracing::debug!("Grabbed {} ({})", name, path.display());
+            devices.push(InputDevice {
+                file,
+                name,
+                frame: Vec::new()
+            });
+        }
+        match (devices.is_empty(), denied) {
+            (true, true) => Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "can not open /dev/input, the user has to be in the input group"
+            )),
+            (true, false) => Err(Error::new(ErrorKind::NotFound, "no keyboard or mouse in /dev/input")),
+            (false, _) => Ok(devices)
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn read(&mut self, events: &mut Vec<input_event>) -> Result<()> {
+        read_events(&self.file, events)
+    }
+}
+
+impl AsRawFd for InputDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+fn is_keyboard_or_mouse(file: &File) -> Result<bool> {
+    let types = event_bits(file, 0)?;
+    let keyboard = test_bit(&types, EV_KEY) && test_bit(&event_bits(file, EV_KEY)?, KEY_A);
+    let mouse = test_bit(&types, EV_REL) && test_bit(&event_bits(file, EV_REL)?, REL_X);
+    Ok(keyboard || mouse)
+}
+
+/// A uinput device that can produce every key, button and relative movement that yawi knows
+pub struct VirtualDevice {
+    file: File
+}
+
+impl VirtualDevice {
+    pub fn create() -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open("/dev/uinput")?;
+        set_event_bit(&file, EV_KEY)?;
+        // the joystick and gamepad buttons are left out, otherwise the device would be classified as a joystick
+        for key in (1..BTN_MISC)
+            .chain(BTN_LEFT..=BTN_TASK)
+            .chain(KEY_OK..=KEY_MAX)
+        {
+            set_key_bit(&file, key)?;
+        }
+        set_event_bit(&file, EV_REL)?;
+        for rel in [REL_X, REL_Y, REL_HWHEEL, REL_WHEEL, REL_WHEEL_HI_RES, REL_HWHEEL_HI_RES] {
+            set_rel_bit(&file, rel)?;
+        }
+        setup_device(&file, VIRTUAL_DEVICE_NAME, BUS_VIRTUAL)?;
+        create_device(&file)?;
+        tracing::trace!("Created the virtual input device");
+        Ok(Self { file })
+    }
+
+    /// Writes the events, every frame has to end with a `SYN_REPORT`
+    pub fn write(&self, events: &[input_event]) -> Result<()> {
+        write_events(&self.file, events)
+    }
+}
+
+impl Drop for VirtualDevice {
+    fn drop(&mut self) {
+        tracing::trace!("Removing the virtual input device");
+        destroy_device(&self.file).unwrap_or_else(|err| tracing::warn!("Could not remove the virtual input device: {}", err));
+    }
+}
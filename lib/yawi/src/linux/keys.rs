@@ -0,0 +1,208 @@
+use inputshare_common::evdev::{BTN_EXTRA, BTN_LEFT, BTN_MIDDLE, BTN_RIGHT, BTN_SIDE};
+
+use crate::{VirtualKey, WindowsScanCode};
+
+/// evdev key codes with the virtual key and scan code that windows reports for the same key
+///
+/// The first entry of a code or virtual key wins, so the generic modifiers come after the left ones.
+#[rustfmt::skip]
+const KEYS: &[(u16, VirtualKey, WindowsScanCode)] = &[
+    (1, VirtualKey::Escape, 0x01),
+    (2, VirtualKey::Key1, 0x02),
+    (3, VirtualKey::Key2, 0x03),
+    (4, VirtualKey::Key3, 0x04),
+    (5, VirtualKey::Key4, 0x05),
+    (6, VirtualKey::Key5, 0x06),
+    (7, VirtualKey::Key6, 0x07),
+    (8, VirtualKey::Key7, 0x08),
+    (9, VirtualKey::Key8, 0x09),
+    (10, VirtualKey::Key9, 0x0a),
+    (11, VirtualKey::Key0, 0x0b),
+    (12, VirtualKey::OemMinus, 0x0c),
+    (13, VirtualKey::OemPlus, 0x0d),
+    (14, VirtualKey::Back, 0x0e),
+    (15, VirtualKey::Tab, 0x0f),
+    (16, VirtualKey::KeyQ, 0x10),
+    (17, VirtualKey::KeyW, 0x11),
+    (18, VirtualKey::KeyE, 0x12),
+    (19, VirtualKey::KeyR, 0x13),
+    (20, VirtualKey::KeyT, 0x14),
+    (21, VirtualKey::KeyY, 0x15),
+    (22, VirtualKey::KeyU, 0x16),
+    (23, VirtualKey::KeyI, 0x17),
+    (24, VirtualKey::KeyO, 0x18),
+    (25, VirtualKey::KeyP, 0x19),
+    (26, VirtualKey::Oem4, 0x1a),
+    (27, VirtualKey::Oem6, 0x1b),
+    (28, VirtualKey::Return, 0x1c),
+    (29, VirtualKey::LControl, 0x1d),
+    (30, VirtualKey::KeyA, 0x1e),
+    (31, VirtualKey::KeyS, 0x1f),
+    (32, VirtualKey::KeyD, 0x20),
+    (33, VirtualKey::KeyF, 0x21),
+    (34, VirtualKey::KeyG, 0x22),
+    (35, VirtualKey::KeyH, 0x23),
+    (36, VirtualKey::KeyJ, 0x24),
+    (37, VirtualKey::KeyK, 0x25),
+    (38, VirtualKey::KeyL, 0x26),
+    (39, VirtualKey::Oem1, 0x27),
+    (40, VirtualKey::Oem7, 0x28),
+    (41, VirtualKey::Oem3, 0x29),
+    (42, VirtualKey::LShift, 0x2a),
+    (43, VirtualKey::Oem5, 0x2b),
+    (44, VirtualKey::KeyZ, 0x2c),
+    (45, VirtualKey::KeyX, 0x2d),
+    (46, VirtualKey::KeyC, 0x2e),
+    (47, VirtualKey::KeyV, 0x2f),
+    (48, VirtualKey::KeyB, 0x30),
+    (49, VirtualKey::KeyN, 0x31),
+    (50, VirtualKey::KeyM, 0x32),
+    (51, VirtualKey::OemComma, 0x33),
+    (52, VirtualKey::OemPeriod, 0x34),
+    (53, VirtualKey::Oem2, 0x35),
+    (54, VirtualKey::RShift, 0x36),
+    (55, VirtualKey::Multiply, 0x37),
+    (56, VirtualKey::LMenu, 0x38),
+    (57, VirtualKey::Space, 0x39),
+    (58, VirtualKey::Capital, 0x3a),
+    (59, VirtualKey::F1, 0x3b),
+    (60, VirtualKey::F2, 0x3c),
+    (61, VirtualKey::F3, 0x3d),
+    (62, VirtualKey::F4, 0x3e),
+    (63, VirtualKey::F5, 0x3f),
+    (64, VirtualKey::F6, 0x40),
+    (65, VirtualKey::F7, 0x41),
+    (66, VirtualKey::F8, 0x42),
+    (67, VirtualKey::F9, 0x43),
+    (68, VirtualKey::F10, 0x44),
+    (69, VirtualKey::Numlock, 0x45),
+    (70, VirtualKey::Scroll, 0x46),
+    (71, VirtualKey::Numpad7, 0x47),
+    (72, VirtualKey::Numpad8, 0x48),
+    (73, VirtualKey::Numpad9, 0x49),
+    (74, VirtualKey::Subtract, 0x4a),
+    (75, VirtualKey::Numpad4, 0x4b),
+    (76, VirtualKey::Numpad5, 0x4c),
+    (77, VirtualKey::Numpad6, 0x4d),
+    (78, VirtualKey::Add, 0x4e),
+    (79, VirtualKey::Numpad1, 0x4f),
+    (80, VirtualKey::Numpad2, 0x50),
+    (81, VirtualKey::Numpad3, 0x51),
+    (82, VirtualKey::Numpad0, 0x52),
+    (83, VirtualKey::Decimal, 0x53),
+    (86, VirtualKey::Oem102, 0x56),
+    (87, VirtualKey::F11, 0x57),
+    (88, VirtualKey::F12, 0x58),
+    (96, VirtualKey::Return, 0xe01c),
+    (97, VirtualKey::RControl, 0xe01d),
+    (98, VirtualKey::Divide, 0xe035),
+    (99, VirtualKey::Snapshot, 0x54),
+    (100, VirtualKey::RMenu, 0xe038),
+    (102, VirtualKey::Home, 0xe047),
+    (103, VirtualKey::Up, 0xe048),
+    (104, VirtualKey::Prior, 0xe049),
+    (105, VirtualKey::Left, 0xe04b),
+    (106, VirtualKey::Right, 0xe04d),
+    (107, VirtualKey::End, 0xe04f),
+    (108, VirtualKey::Down, 0xe050),
+    (109, VirtualKey::Next, 0xe051),
+    (110, VirtualKey::Insert, 0xe052),
+    (111, VirtualKey::Delete, 0xe053),
+    (113, VirtualKey::VolumeMute, 0xe020),
+    (114, VirtualKey::VolumeDown, 0xe02e),
+    (115, VirtualKey::VolumeUp, 0xe030),
+    (119, VirtualKey::Pause, 0xe11d),
+    (125, VirtualKey::LWin, 0xe05b),
+    (126, VirtualKey::RWin, 0xe05c),
+    (127, VirtualKey::Apps, 0xe05d),
+    (128, VirtualKey::BrowserStop, 0xe068),
+    (140, VirtualKey::LaunchApp2, 0xe021),
+    (155, VirtualKey::LaunchMail, 0xe06c),
+    (156, VirtualKey::BrowserFavorites, 0xe066),
+    (157, VirtualKey::LaunchApp1, 0xe06b),
+    (158, VirtualKey::BrowserBack, 0xe06a),
+    (159, VirtualKey::BrowserForward, 0xe069),
+    (163, VirtualKey::MediaNextTrack, 0xe019),
+    (164, VirtualKey::MediaPlayPause, 0xe022),
+    (165, VirtualKey::MediaPrevTrack, 0xe010),
+    (166, VirtualKey::MediaStop, 0xe024),
+    (172, VirtualKey::BrowserHome, 0xe032),
+    (173, VirtualKey::BrowserRefresh, 0xe067),
+    (183, VirtualKey::F13, 0x64),
+    (184, VirtualKey::F14, 0x65),
+    (185, VirtualKey::F15, 0x66),
+    (186, VirtualKey::F16, 0x67),
+    (187, VirtualKey::F17, 0x68),
+    (188, VirtualKey::F18, 0x69),
+    (189, VirtualKey::F19, 0x6a),
+    (190, VirtualKey::F20, 0x6b),
+    (191, VirtualKey::F21, 0x6c),
+    (192, VirtualKey::F22, 0x6d),
+    (193, VirtualKey::F23, 0x6e),
+    (194, VirtualKey::F24, 0x76),
+    (217, VirtualKey::BrowserSearch, 0xe065),
+    (226, VirtualKey::LaunchMediaSelect, 0xe06d),
+    (42, VirtualKey::Shift, 0x2a),
+    (29, VirtualKey::Control, 0x1d),
+    (56, VirtualKey::Menu, 0x38)
+];
+
+const BUTTONS: &[(u16, VirtualKey)] = &[
+    (BTN_LEFT, VirtualKey::LButton),
+    (BTN_RIGHT, VirtualKey::RButton),
+    (BTN_MIDDLE, VirtualKey::MButton),
+    (BTN_SIDE, VirtualKey::XButton1),
+    (BTN_EXTRA, VirtualKey::XButton2)
+];
+
+pub fn key_from_evdev(code: u16) -> Option<(VirtualKey, WindowsScanCode)> {
+    KEYS.iter()
+        .find(|(evdev, _, _)| *evdev == code)
+        .map(|(_, key, scancode)| (*key, *scancode))
+}
+
+pub fn button_from_evdev(code: u16) -> Option<VirtualKey> {
+    BUTTONS
+        .iter()
+        .find(|(evdev, _)| *evdev == code)
+        .map(|(_, key)| *key)
+}
+
+pub fn to_evdev(key: VirtualKey) -> Option<u16> {
+    KEYS.iter()
+        .map(|(evdev, key, _)| (*evdev, *key))
+        .chain(BUTTONS.iter().copied())
+        .find(|(_, k)| *k == key)
+        .map(|(evdev, _)| evdev)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_map_both_ways() {
+        assert_eq!(key_from_evdev(30), Some((VirtualKey::KeyA, 0x1e)));
+        assert_eq!(to_evdev(VirtualKey::KeyA), Some(30));
+        assert_eq!(button_from_evdev(BTN_SIDE), Some(VirtualKey::XButton1));
+        assert_eq!(to_evdev(VirtualKey::XButton1), Some(BTN_SIDE));
+        assert_eq!(key_from_evdev(BTN_LEFT), None);
+    }
+
+    #[test]
+    fn ambiguous_keys_prefer_the_common_one() {
+        assert_eq!(key_from_evdev(96), Some((VirtualKey::Return, 0xe01c)));
+        assert_eq!(to_evdev(VirtualKey::Return), Some(28));
+        assert_eq!(key_from_evdev(42), Some((VirtualKey::LShift, 0x2a)));
+        assert_eq!(to_evdev(VirtualKey::Shift), Some(42));
+        assert_eq!(to_evdev(VirtualKey::Menu), Some(56));
+    }
+
+    #[test]
+    fn basic_codes_are_the_scan_codes() {
+        // evdev numbers the keys of a classic pc keyboard by their set 1 scan code
+        for (code, _, scancode) in KEYS.iter().filter(|(code, _, _)| *code <= 83) {
+            assert_eq!(*code, *scancode);
+        }
+    }
+}
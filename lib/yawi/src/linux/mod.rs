@@ -0,0 +1,169 @@
+mod capture;
+mod device;
+mod keys;
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use inputshare_common::evdev::{
+    EV_KEY, EV_REL, EV_SYN, REL_HWHEEL, REL_HWHEEL_HI_RES, REL_WHEEL, REL_WHEEL_HI_RES, REL_X, REL_Y, SYN_REPORT, WHEEL_HI_RES
+};
+use inputshare_common::uinput::event;
+use libc::input_event;
+
+use crate::linux::capture::Capture;
+use crate::linux::device::VirtualDevice;
+use crate::{Backend, HookFn, Input, InputHook, KeyState, Result, ScrollDirection};
+
+/// The backend that grabs the evdev devices and simulates input through uinput
+///
+/// evdev knows nothing about monitors, so the backend tracks the cursor itself from the relative movements,
+/// on a desktop with the size given by `YAWI_SCREEN` (e.g. `2560x1440`) or 1920x1080 if it is not set.
+/// The compositor still applies its pointer acceleration to the simulated movements, which lets the real cursor drift away.
+///
+/// Access to `/dev/input` and `/dev/uinput` usually requires the user to be in the `input` group.
+pub struct Linux {
+    shared: Arc<Shared>
+}
+
+impl Linux {
+    pub fn new(width: i32, height: i32) -> Self {
+        let screen = Screen {
+            width: width.max(1),
+            height: height.max(1)
+        };
+        Self {
+            shared: Arc::new(Shared {
+                cursor: Mutex::new(screen.center()),
+                screen,
+                device: Mutex::new(None)
+            })
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let screen = std::env::var("YAWI_SCREEN").ok().and_then(|value| {
+            let parsed = value
+                .split_once('x')
+                .and_then(|(width, height)| Some((width.trim().parse().ok()?, height.trim().parse().ok()?)));
+            if parsed.is_none() {
+                tracing::warn!("YAWI_SCREEN has to look like 1920x1080, not {}", value);
+            }
+            parsed
+        });
+        let (width, height) = screen.unwrap_or((1920, 1080));
+        Self::new(width, height)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Screen {
+    width: i32,
+    height: i32
+}
+
+impl Screen {
+    fn center(self) -> (i32, i32) {
+        (self.width / 2, self.height / 2)
+    }
+
+    fn clamp(self, (x, y): (i32, i32)) -> (i32, i32) {
+        (x.clamp(0, self.width - 1), y.clamp(0, self.height - 1))
+    }
+}
+
+/// The state that is shared with the capture thread
+struct Shared {
+    screen: Screen,
+    cursor: Mutex<(i32, i32)>,
+    /// Created on first use, also receives the events that the hook lets through
+    device: Mutex<Option<VirtualDevice>>
+}
+
+impl Shared {
+    fn cursor(&self) -> MutexGuard<'_, (i32, i32)> {
+        self.cursor
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Writes the events to the virtual device, which is created if it does not exist yet
+    fn write(&self, events: &[input_event]) -> Result<()> {
+        let mut device = self
+            .device
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let device = match &mut *device {
+            Some(device) => device,
+            None => device.insert(VirtualDevice::create()?)
+        };
+        device.write(events)
+    }
+}
+
+fn push_frame(events: &mut Vec<input_event>, frame: &[input_event]) {
+    events.extend_from_slice(frame);
+    events.push(event(EV_SYN, SYN_REPORT, 0));
+}
+
+fn push_scroll(events: &mut Vec<input_event>, low_res: u16, hi_res: u16, detents: f32) {
+    push_frame(
+        events,
+        &[
+            event(EV_REL, hi_res, (detents * WHEEL_HI_RES as f32).round() as i32),
+            event(EV_REL, low_res, detents.round() as i32)
+        ]
+    );
+}
+
+impl Backend for Linux {
+    fn register_hook(&self, callback: HookFn) -> Result<InputHook> {
+        Ok(InputHook::from_guard(Capture::start(self.shared.clone(), callback)?))
+    }
+
+    fn send_inputs(&self, inputs: &[Input]) -> Result<()> {
+        let mut events = Vec::new();
+        let mut cursor = self.shared.cursor();
+        for input in inputs {
+            match *input {
+                Input::KeyboardKeyInput(key, state) | Input::MouseButtonInput(key, state) => match keys::to_evdev(key) {
+                    Some(code) => push_frame(&mut events, &[event(EV_KEY, code, i32::from(state == KeyState::Pressed))]),
+                    None => tracing::warn!("Unsupported key ({:?}): Skipping!", key)
+                },
+                Input::StringInput(_) => tracing::warn!("uinput can not type text: Skipping!"),
+                Input::MouseScrollInput(ScrollDirection::Vertical(detents)) => push_scroll(&mut events, REL_WHEEL, REL_WHEEL_HI_RES, detents),
+                Input::MouseScrollInput(ScrollDirection::Horizontal(detents)) => push_scroll(&mut events, REL_HWHEEL, REL_HWHEEL_HI_RES, detents),
+                Input::RelativeMouseMoveInput(dx, dy) => {
+                    push_frame(&mut events, &[event(EV_REL, REL_X, dx), event(EV_REL, REL_Y, dy)]);
+                    *cursor = self.shared.screen.clamp((cursor.0 + dx, cursor.1 + dy));
+                }
+                Input::AbsoluteMouseMoveInput(x, y) => {
+                    let target = self.shared.screen.clamp((x, y));
+                    push_frame(
+                        &mut events,
+                        &[event(EV_REL, REL_X, target.0 - cursor.0), event(EV_REL, REL_Y, target.1 - cursor.1)]
+                    );
+                    *cursor = target;
+                }
+            }
+        }
+        drop(cursor);
+        match events.is_empty() {
+            true => Ok(()),
+            false => self.shared.write(&events)
+        }
+    }
+
+    fn cursor_pos(&self) -> (i32, i32) {
+        *self.shared.cursor()
+    }
+
+    /// Unlike on windows, this moves the real cursor with a relative movement that the hook does not see
+    fn set_cursor_pos(&self, x: i32, y: i32) -> Result<()> {
+        self.send_inputs(&[Input::AbsoluteMouseMoveInput(x, y)])
+    }
+
+    fn virtual_screen(&self) -> (i32, i32, i32, i32) {
+        let Screen { width, height } = self.shared.screen;
+        (0, 0, width, height)
+    }
+}
@@ -0,0 +1,247 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{Backend, HookAction, HookFn, Input, InputEvent, InputHook, KeyState, Result, ScrollDirection, VirtualKey};
+
+/// An owned copy of an `Input` that was sent through a `MockBackend`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sent {
+    Key(VirtualKey, KeyState),
+    Text(String),
+    MouseButton(VirtualKey, KeyState),
+    Scroll(ScrollDirection),
+    RelativeMove(i32, i32),
+    AbsoluteMove(i32, i32)
+}
+
+impl From<&Input<'_>> for Sent {
+    fn from(input: &Input) -> Self {
+        match *input {
+            Input::KeyboardKeyInput(key, state) => Sent::Key(key, state),
+            Input::StringInput(text) => Sent::Text(text.to_owned()),
+            Input::MouseButtonInput(key, state) => Sent::MouseButton(key, state),
+            Input::MouseScrollInput(direction) => Sent::Scroll(direction),
+            Input::RelativeMouseMoveInput(x, y) => Sent::RelativeMove(x, y),
+            Input::AbsoluteMouseMoveInput(x, y) => Sent::AbsoluteMove(x, y)
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    registered: bool,
+    /// Taken out while it handles an event, so it can call back into the backend
+    hook: Option<HookFn>,
+    sent: Vec<Sent>,
+    cursor: (i32, i32)
+}
+
+/// A backend without any devices for tests
+///
+/// Local input is simulated with `emit` and everything that is sent gets recorded.
+/// The cursor moves like a real one, but nothing else reacts to the recorded inputs.
+#[derive(Clone)]
+pub struct MockBackend {
+    screen: (i32, i32, i32, i32),
+    state: Arc<Mutex<State>>
+}
+
+impl MockBackend {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self {
+            screen: (0, 0, width.max(1), height.max(1)),
+            state: Arc::default()
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn clamp(&self, x: i32, y: i32) -> (i32, i32) {
+        let (left, top, width, height) = self.screen;
+        (x.clamp(left, left + width - 1), y.clamp(top, top + height - 1))
+    }
+
+    /// Passes `event` to the registered hook as if it came from a local device
+    ///
+    /// Mouse movements that are not blocked move the cursor.
+    pub fn emit(&self, event: InputEvent) -> HookAction {
+        let hook = self.state().hook.take();
+        let action = match hook {
+            Some(mut hook) => {
+                let action = hook.handle(event);
+                let mut state = self.state();
+                if state.registered {
+                    state.hook = Some(hook);
+                }
+                action
+            }
+            None => HookAction::Continue
+        };
+        if let (HookAction::Continue, InputEvent::MouseMoveEvent(x, y)) = (action, event) {
+            self.state().cursor = self.clamp(x, y);
+        }
+        action
+    }
+
+    pub fn is_hooked(&self) -> bool {
+        self.state().registered
+    }
+
+    /// Returns and forgets everything that was sent so far
+    pub fn take_sent(&self) -> Vec<Sent> {
+        std::mem::take(&mut self.state().sent)
+    }
+}
+
+struct MockHook(Arc<Mutex<State>>);
+
+impl Drop for MockHook {
+    fn drop(&mut self) {
+        let mut state = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.registered = false;
+        state.hook = None;
+    }
+}
+
+impl Backend for MockBackend {
+    fn register_hook(&self, callback: HookFn) -> Result<InputHook> {
+        let mut state = self.state();
+        assert!(!state.registered, "Only one hook can be registered per backend.");
+        state.registered = true;
+        state.hook = Some(callback);
+        Ok(InputHook::from_guard(MockHook(self.state.clone())))
+    }
+
+    fn send_inputs(&self, inputs: &[Input]) -> Result<()> {
+        for input in inputs {
+            let cursor = match *input {
+                Input::RelativeMouseMoveInput(x, y) => {
+                    let (cx, cy) = self.cursor_pos();
+                    Some(self.clamp(cx + x, cy + y))
+                }
+                Input::AbsoluteMouseMoveInput(x, y) => Some(self.clamp(x, y)),
+                _ => None
+            };
+            let mut state = self.state();
+            state.sent.push(input.into());
+            if let Some(cursor) = cursor {
+                state.cursor = cursor;
+            }
+        }
+        Ok(())
+    }
+
+    fn cursor_pos(&self) -> (i32, i32) {
+        self.state().cursor
+    }
+
+    fn set_cursor_pos(&self, x: i32, y: i32) -> Result<()> {
+        self.state().cursor = self.clamp(x, y);
+        Ok(())
+    }
+
+    fn virtual_screen(&self) -> (i32, i32, i32, i32) {
+        self.screen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{send_inputs, use_backend};
+
+    #[test]
+    fn hook_receives_and_blocks_events() {
+        let backend = MockBackend::new(1920, 1080);
+        let hook = backend
+            .register_hook(HookFn::new(|event| match event {
+                InputEvent::KeyboardKeyEvent(VirtualKey::KeyA, _, _) => HookAction::Block,
+                _ => HookAction::Continue
+            }))
+            .unwrap();
+        assert_eq!(
+            backend.emit(InputEvent::KeyboardKeyEvent(VirtualKey::KeyA, 0x1e, KeyState::Pressed)),
+            HookAction::Block
+        );
+        assert_eq!(
+            backend.emit(InputEvent::KeyboardKeyEvent(VirtualKey::KeyB, 0x30, KeyState::Pressed)),
+            HookAction::Continue
+        );
+        drop(hook);
+        assert!(!backend.is_hooked());
+        assert_eq!(
+            backend.emit(InputEvent::KeyboardKeyEvent(VirtualKey::KeyA, 0x1e, KeyState::Pressed)),
+            HookAction::Continue
+        );
+    }
+
+    #[test]
+    fn blocked_movements_keep_the_cursor() {
+        let backend = MockBackend::new(1920, 1080);
+        backend.set_cursor_pos(100, 100).unwrap();
+        let _hook = backend
+            .register_hook(HookFn::new(|event| match event {
+                InputEvent::MouseMoveEvent(x, _) if x > 1000 => HookAction::Block,
+                _ => HookAction::Continue
+            }))
+            .unwrap();
+        backend.emit(InputEvent::MouseMoveEvent(500, 200));
+        assert_eq!(backend.cursor_pos(), (500, 200));
+        backend.emit(InputEvent::MouseMoveEvent(1500, 200));
+        assert_eq!(backend.cursor_pos(), (500, 200));
+    }
+
+    #[test]
+    fn free_functions_use_the_backend_of_the_thread() {
+        let backend = MockBackend::new(1920, 1080);
+        let guard = use_backend(Arc::new(backend.clone()));
+        send_inputs([
+            Input::KeyboardKeyInput(VirtualKey::Return, KeyState::Pressed),
+            Input::StringInput("hi"),
+            Input::RelativeMouseMoveInput(-50, 5000)
+        ])
+        .unwrap();
+        assert_eq!(crate::get_cursor_pos(), (0, 1079));
+        assert_eq!(crate::get_virtual_screen(), (0, 0, 1920, 1080));
+        drop(guard);
+        assert_eq!(
+            backend.take_sent(),
+            vec![
+                Sent::Key(VirtualKey::Return, KeyState::Pressed),
+                Sent::Text("hi".into()),
+                Sent::RelativeMove(-50, 5000)
+            ]
+        );
+        assert!(backend.take_sent().is_empty());
+    }
+
+    #[test]
+    fn hooks_can_send_input() {
+        let backend = MockBackend::new(1920, 1080);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let _hook = {
+            let backend = backend.clone();
+            let calls = calls.clone();
+            backend.clone().register_hook(HookFn::new(move |_| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                backend
+                    .send_inputs(&[Input::KeyboardKeyInput(VirtualKey::KeyB, KeyState::Released)])
+                    .unwrap();
+                HookAction::Continue
+            }))
+        }
+        .unwrap();
+        backend.emit(InputEvent::MouseWheelEvent(ScrollDirection::Vertical(1.0)));
+        backend.emit(InputEvent::MouseWheelEvent(ScrollDirection::Vertical(-1.0)));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        assert_eq!(backend.take_sent().len(), 2);
+    }
+}
@@ -7,59 +7,33 @@ use windows::Win32::Foundation::{HINSTANCE, LPARAM, LRESULT, WPARAM};
 use windows::Win32::UI::Input::KeyboardAndMouse::*;
 use windows::Win32::UI::WindowsAndMessaging::*;
 
-use crate::{InputEvent, KeyState, ScrollDirection, VirtualKey, WinResult, WindowsScanCode};
-
-#[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
-pub enum HookAction {
-    #[default]
-    Continue,
-    Block
-}
-
-#[repr(transparent)]
-pub struct HookFn(Box<dyn FnMut(InputEvent) -> HookAction>);
-
-impl HookFn {
-    pub fn new(callback: impl FnMut(InputEvent) -> HookAction + 'static) -> Self {
-        Self(Box::new(callback))
-    }
-
-    fn handle(&mut self, event: InputEvent) -> HookAction {
-        self.0(event)
-    }
-}
-
-impl<F: FnMut(InputEvent) -> HookAction + 'static> From<F> for HookFn {
-    fn from(value: F) -> Self {
-        HookFn::new(value)
-    }
-}
+use crate::{HookAction, HookFn, InputEvent, KeyState, ScrollDirection, VirtualKey, WindowsScanCode};
 
 thread_local! {
     static HOOK: Cell<Option<HookFn>> = Cell::default();
 }
 
-pub struct InputHook {
+/// The low level keyboard and mouse hooks of the current thread
+pub struct SystemHook {
     keyboard: HHOOK,
     mouse: HHOOK
 }
 
-impl InputHook {
-    #[must_use = "The hook will immediately be unregistered and not work."]
-    pub fn register(callback: impl Into<HookFn>) -> WinResult<InputHook> {
+impl SystemHook {
+    pub fn register(callback: HookFn) -> windows::core::Result<SystemHook> {
         HOOK.with(|state| {
             assert!(state.take().is_none(), "Only one keyboard hook can be registered per thread.");
 
             tracing::trace!("Registering system hooks");
             let keyboard = unsafe { SetWindowsHookExW(WH_KEYBOARD_LL, Some(low_level_keyboard_proc), HINSTANCE::default(), 0)? };
             let mouse = unsafe { SetWindowsHookExW(WH_MOUSE_LL, Some(low_level_mouse_proc), HINSTANCE::default(), 0)? };
-            state.set(Some(callback.into()));
-            Ok(InputHook { keyboard, mouse })
+            state.set(Some(callback));
+            Ok(SystemHook { keyboard, mouse })
         })
     }
 }
 
-impl Drop for InputHook {
+impl Drop for SystemHook {
     fn drop(&mut self) {
         tracing::trace!("Removing system hooks");
         HOOK.with(|state| state.take());
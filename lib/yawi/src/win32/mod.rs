@@ -0,0 +1,37 @@
+mod hook;
+mod message;
+mod query;
+mod send;
+
+pub use message::{quit, run};
+pub use query::get_controller_state;
+
+use crate::{Backend, HookFn, Input, InputHook, Result};
+
+/// The backend that uses the low level hooks and `SendInput` of windows
+///
+/// Hooks only receive events while the thread that registered them runs a message loop.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Win32;
+
+impl Backend for Win32 {
+    fn register_hook(&self, callback: HookFn) -> Result<InputHook> {
+        Ok(InputHook::from_guard(hook::SystemHook::register(callback)?))
+    }
+
+    fn send_inputs(&self, inputs: &[Input]) -> Result<()> {
+        Ok(send::send_inputs(inputs)?)
+    }
+
+    fn cursor_pos(&self) -> (i32, i32) {
+        query::get_cursor_pos()
+    }
+
+    fn set_cursor_pos(&self, x: i32, y: i32) -> Result<()> {
+        Ok(send::set_cursor_pos(x, y)?)
+    }
+
+    fn virtual_screen(&self) -> (i32, i32, i32, i32) {
+        query::get_virtual_screen()
+    }
+}
@@ -0,0 +1,44 @@
+use windows::Win32::Foundation::{ERROR_SUCCESS, POINT};
+use windows::Win32::UI::Input::XboxController::{XInputGetState, XINPUT_STATE};
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetCursorPos, GetSystemMetrics, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN
+};
+
+use crate::ControllerState;
+
+pub fn get_cursor_pos() -> (i32, i32) {
+    unsafe {
+        let mut pt = POINT::default();
+        GetCursorPos(&mut pt).expect("Failed to get current cursor pos");
+        (pt.x, pt.y)
+    }
+}
+
+/// Returns the bounds of the desktop spanning all monitors as `(x, y, width, height)`
+pub fn get_virtual_screen() -> (i32, i32, i32, i32) {
+    unsafe {
+        (
+            GetSystemMetrics(SM_XVIRTUALSCREEN),
+            GetSystemMetrics(SM_YVIRTUALSCREEN),
+            GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            GetSystemMetrics(SM_CYVIRTUALSCREEN)
+        )
+    }
+}
+
+/// Queries the state of an XInput controller
+///
+/// Returns `None` if no controller is connected at the given index (0 - 3)
+pub fn get_controller_state(user_index: u32) -> Option<ControllerState> {
+    let mut state = XINPUT_STATE::default();
+    match unsafe { XInputGetState(user_index, &mut state) } {
+        code if code == ERROR_SUCCESS.0 => Some(ControllerState {
+            buttons: state.Gamepad.wButtons.0,
+            left_trigger: state.Gamepad.bLeftTrigger,
+            right_trigger: state.Gamepad.bRightTrigger,
+            left_thumb: (state.Gamepad.sThumbLX, state.Gamepad.sThumbLY),
+            right_thumb: (state.Gamepad.sThumbRX, state.Gamepad.sThumbRY)
+        }),
+        _ => None
+    }
+}
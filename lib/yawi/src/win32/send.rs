@@ -1,11 +1,10 @@
-use std::iter::once;
 use std::mem::size_of;
 
 use windows::core::Error;
 use windows::Win32::UI::Input::KeyboardAndMouse::*;
 use windows::Win32::UI::WindowsAndMessaging::*;
 
-use crate::{Input, KeyState, ScrollDirection, VirtualKey, WinResult};
+use crate::{Input, KeyState, ScrollDirection, VirtualKey};
 
 fn add_to_vec(vec: &mut Vec<INPUT>, input: Input) {
     match input {
@@ -126,10 +125,10 @@ fn create_keyboard_input(kb: KEYBDINPUT) -> INPUT {
 /// [SendInput](https://docs.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-sendinput)
 ///
 /// Return Ok if the number of send inputs match the number of supplied inputs
-pub fn send_inputs<'a>(inputs: impl IntoIterator<Item = Input<'a>>) -> WinResult<()> {
+pub fn send_inputs(inputs: &[Input]) -> windows::core::Result<()> {
     let mut vec = Vec::new();
     for input in inputs {
-        add_to_vec(&mut vec, input);
+        add_to_vec(&mut vec, *input);
     }
     let c = unsafe { SendInput(&vec, size_of::<INPUT>() as i32) };
     match vec.len() == c as usize {
@@ -138,9 +137,7 @@ pub fn send_inputs<'a>(inputs: impl IntoIterator<Item = Input<'a>>) -> WinResult
     }
 }
 
-/// Convenience function to send a single input
-///
-/// See `send_inputs` for more info
-pub fn send_input(input: Input) -> WinResult<()> {
-    send_inputs(once(input))
+/// Moves the cursor to a position on the virtual screen without generating an input event
+pub fn set_cursor_pos(x: i32, y: i32) -> windows::core::Result<()> {
+    unsafe { SetCursorPos(x, y) }.ok()
 }